@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use std::time::SystemTime;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
@@ -23,4 +24,16 @@ impl<T> Config<T>
     pub fn write(&self) {
         confy::store_path(&self.path, &self.cfg).unwrap();
     }
+
+    /// Reads the file again, keeping the current values when it can't be parsed.
+    pub fn reload(&mut self) -> Result<(), confy::ConfyError> {
+        self.cfg = confy::load_path(&self.path)?;
+
+        Ok(())
+    }
+
+    /// When the file was last changed, `None` if that can't be read.
+    pub fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,267 @@
+use std::fmt::{Display, Formatter};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use log::warn;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use crate::entities::config::ConfigFile;
+
+/// Environment variable holding the passphrase the credentials are encrypted with.
+pub const PASSPHRASE_ENV: &str = "SPOTIFY_OSC_PASSPHRASE";
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+
+/// Tokens obtained from Spotify, kept apart from the settings in `config.toml`.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq, Eq)]
+pub struct Credentials {
+    #[serde(default)]
+    pub token: String,
+    #[serde(default)]
+    pub refresh_token: String,
+    /// Scopes granted with the current token, as returned by Spotify.
+    #[serde(default)]
+    pub scope: String
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedCredentials {
+    salt: String,
+    nonce: String,
+    ciphertext: String
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum CredentialsFile {
+    Encrypted(EncryptedCredentials),
+    Plain(Credentials)
+}
+
+pub enum CredentialsKey {
+    Passphrase(String),
+    KeyFile(PathBuf)
+}
+
+/// The credentials file, readable and writable by its owner only and optionally encrypted.
+pub struct CredentialsStore {
+    path: PathBuf,
+    key: Option<CredentialsKey>
+}
+
+impl CredentialsStore {
+    pub fn new(path: PathBuf, key: Option<CredentialsKey>) -> Self {
+        Self {
+            path,
+            key
+        }
+    }
+
+    /// Store of `profile`, or of the first profile when `None`.
+    /// Uses the configured key file, or else the passphrase from [`PASSPHRASE_ENV`] if it's set.
+    pub fn from_config(cfg: &ConfigFile, profile: Option<&str>) -> Self {
+        let key = if !cfg.spotify.credentials_key_file.is_empty() {
+            Some(CredentialsKey::KeyFile(PathBuf::from(&cfg.spotify.credentials_key_file)))
+        } else {
+            std::env::var(PASSPHRASE_ENV).ok()
+                .filter(|passphrase| !passphrase.is_empty())
+                .map(CredentialsKey::Passphrase)
+        };
+
+        let path = match profile {
+            Some(profile) => cfg.spotify.credentials_path_for(profile),
+            None => cfg.spotify.credentials_path_for(&cfg.spotify.profiles()[0].name)
+        };
+
+        Self::new(PathBuf::from(path), key)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the stored credentials, a missing file means nothing has been stored yet.
+    pub fn load(&self) -> Result<Credentials, CredentialsError> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Credentials::default()),
+            Err(err) => return Err(CredentialsError::Io(err))
+        };
+
+        self.restrict_permissions()?;
+
+        match serde_json::from_slice(&data).map_err(CredentialsError::Format)? {
+            CredentialsFile::Plain(credentials) => Ok(credentials),
+            CredentialsFile::Encrypted(encrypted) => self.decrypt(&encrypted)
+        }
+    }
+
+    pub fn save(&self, credentials: &Credentials) -> Result<(), CredentialsError> {
+        let file = match self.key_material()? {
+            Some(material) => CredentialsFile::Encrypted(encrypt(&material, credentials)?),
+            None => CredentialsFile::Plain(credentials.clone())
+        };
+
+        let data = serde_json::to_vec_pretty(&file).map_err(CredentialsError::Format)?;
+
+        write_private(&self.path, &data).map_err(CredentialsError::Io)
+    }
+
+    fn key_material(&self) -> Result<Option<Vec<u8>>, CredentialsError> {
+        match &self.key {
+            Some(CredentialsKey::Passphrase(passphrase)) => Ok(Some(passphrase.as_bytes().to_vec())),
+            Some(CredentialsKey::KeyFile(path)) => {
+                let mut material = fs::read(path).map_err(CredentialsError::KeyFile)?;
+
+                // Key files made with `echo` end with a newline that isn't part of the key.
+                while material.last().map(|byte| byte.is_ascii_whitespace()).unwrap_or(false) {
+                    material.pop();
+                }
+
+                Ok(Some(material))
+            }
+            None => Ok(None)
+        }
+    }
+
+    fn decrypt(&self, encrypted: &EncryptedCredentials) -> Result<Credentials, CredentialsError> {
+        let material = self.key_material()?.ok_or(CredentialsError::Locked)?;
+
+        let salt = base64::decode(&encrypted.salt).map_err(|_| CredentialsError::Decrypt)?;
+        let nonce = base64::decode(&encrypted.nonce).map_err(|_| CredentialsError::Decrypt)?;
+        let ciphertext = base64::decode(&encrypted.ciphertext).map_err(|_| CredentialsError::Decrypt)?;
+
+        if nonce.len() != NONCE_LENGTH {
+            return Err(CredentialsError::Decrypt)
+        }
+
+        let cipher = ChaCha20Poly1305::new(&derive_key(&material, &salt)?);
+
+        let plaintext = cipher.decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| CredentialsError::Decrypt)?;
+
+        serde_json::from_slice(&plaintext).map_err(CredentialsError::Format)
+    }
+
+    #[cfg(unix)]
+    fn restrict_permissions(&self) -> Result<(), CredentialsError> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = fs::metadata(&self.path).map_err(CredentialsError::Io)?.permissions().mode();
+
+        if mode & 0o077 != 0 {
+            warn!("{} was readable by other users, restricting it to its owner", self.path.display());
+
+            fs::set_permissions(&self.path, fs::Permissions::from_mode(0o600)).map_err(CredentialsError::Io)?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn restrict_permissions(&self) -> Result<(), CredentialsError> {
+        Ok(())
+    }
+}
+
+fn derive_key(material: &[u8], salt: &[u8]) -> Result<Key, CredentialsError> {
+    let mut key = Key::default();
+
+    Argon2::default().hash_password_into(material, salt, &mut key).map_err(|_| CredentialsError::Decrypt)?;
+
+    Ok(key)
+}
+
+fn encrypt(material: &[u8], credentials: &Credentials) -> Result<EncryptedCredentials, CredentialsError> {
+    let mut salt = [0_u8; SALT_LENGTH];
+    let mut nonce = [0_u8; NONCE_LENGTH];
+
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(material, &salt)?);
+    let plaintext = serde_json::to_vec(credentials).map_err(CredentialsError::Format)?;
+
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| CredentialsError::Decrypt)?;
+
+    Ok(EncryptedCredentials {
+        salt: base64::encode(salt),
+        nonce: base64::encode(nonce),
+        ciphertext: base64::encode(ciphertext)
+    })
+}
+
+/// Writes to a temporary file created owner-only, then moves it over `path`.
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+
+    let tmp = path.with_extension("tmp");
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(&tmp)?;
+
+    // The mode only applies when the file is created, a leftover temporary file keeps its own.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+
+    file.write_all(data)?;
+    file.sync_all()?;
+
+    fs::rename(&tmp, path)
+}
+
+#[derive(Debug)]
+pub enum CredentialsError {
+    /// Reading or writing the credentials file failed.
+    Io(std::io::Error),
+    /// The key file couldn't be read.
+    KeyFile(std::io::Error),
+    /// The file isn't valid credentials JSON.
+    Format(serde_json::Error),
+    /// The file is encrypted but no passphrase or key file is configured.
+    Locked,
+    /// The passphrase or key file doesn't match the one the file was encrypted with.
+    Decrypt
+}
+
+impl Display for CredentialsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CredentialsError::Io(err) => write!(f, "couldn't access the credentials file: {}", err),
+            CredentialsError::KeyFile(err) => write!(f, "couldn't read the credentials key file: {}", err),
+            CredentialsError::Format(err) => write!(f, "the credentials file is malformed: {}", err),
+            CredentialsError::Locked => write!(f, "the credentials file is encrypted, set {} or credentials_key_file", PASSPHRASE_ENV),
+            CredentialsError::Decrypt => write!(f, "couldn't decrypt the credentials file, check the passphrase or key file")
+        }
+    }
+}
+
+impl std::error::Error for CredentialsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CredentialsError::Io(err) | CredentialsError::KeyFile(err) => Some(err),
+            CredentialsError::Format(err) => Some(err),
+            _ => None
+        }
+    }
+}
@@ -1,15 +1,101 @@
+use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
+use crate::entities::osc::OscAction;
+use crate::entities::spotify::SpotifyClientAuth;
 
 #[derive(Deserialize, Serialize)]
 pub struct ConfigFileSpotify {
     pub client_id: String,
     pub client_secret: String,
     pub callback_url: String,
+    /// Legacy token fields, moved into the credentials file on startup.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub token: String,
-    pub refresh_token: String
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub refresh_token: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub scope: String,
+    #[serde(default = "default_spotify_api_url")]
+    pub api_url: String,
+    #[serde(default = "default_spotify_accounts_url")]
+    pub accounts_url: String,
+    #[serde(default = "default_spotify_credentials_path")]
+    pub credentials_path: String,
+    /// Encrypts the credentials file with the contents of this file when set.
+    #[serde(default)]
+    pub credentials_key_file: String,
+    /// Profile used at startup, the first one when empty.
+    #[serde(default)]
+    pub active_profile: String,
+    #[serde(default = "default_spotify_profiles")]
+    pub profiles: Vec<ConfigFileSpotifyProfile>
 }
 
-#[derive(Deserialize, Serialize)]
+/// A Spotify account with its own tokens, all profiles share the same app registration.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ConfigFileSpotifyProfile {
+    pub name: String,
+    /// Where this profile's tokens are kept, see [`ConfigFileSpotify::credentials_path_for`].
+    #[serde(default)]
+    pub credentials_path: String
+}
+
+fn default_spotify_profiles() -> Vec<ConfigFileSpotifyProfile> {
+    vec![ConfigFileSpotifyProfile {
+        name: "default".to_string(),
+        credentials_path: "".to_string()
+    }]
+}
+
+impl ConfigFileSpotify {
+    /// The configured profiles, never empty.
+    pub fn profiles(&self) -> Vec<ConfigFileSpotifyProfile> {
+        if self.profiles.is_empty() {
+            default_spotify_profiles()
+        } else {
+            self.profiles.clone()
+        }
+    }
+
+    pub fn profile(&self, name: &str) -> Option<ConfigFileSpotifyProfile> {
+        self.profiles().into_iter().find(|profile| profile.name == name)
+    }
+
+    /// The first profile keeps `credentials_path` so tokens from before profiles existed still apply,
+    /// the others get their name appended to it unless they set their own path.
+    pub fn credentials_path_for(&self, profile: &str) -> String {
+        let profiles = self.profiles();
+
+        if let Some(path) = profiles.iter().find(|entry| entry.name == profile).map(|entry| &entry.credentials_path) {
+            if !path.is_empty() {
+                return String::from(path);
+            }
+        }
+
+        if profiles[0].name == profile {
+            return String::from(&self.credentials_path);
+        }
+
+        match self.credentials_path.rsplit_once('.') {
+            Some((stem, extension)) if !extension.contains('/') => format!("{}-{}.{}", stem, profile, extension),
+            _ => format!("{}-{}", self.credentials_path, profile)
+        }
+    }
+}
+
+fn default_spotify_api_url() -> String {
+    "https://api.spotify.com/v1".to_string()
+}
+
+fn default_spotify_accounts_url() -> String {
+    "https://accounts.spotify.com".to_string()
+}
+
+fn default_spotify_credentials_path() -> String {
+    "credentials.json".to_string()
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct ConfigFileParameters {
     pub spotify_playing: String,
     pub spotify_seek: String,
@@ -18,13 +104,246 @@ pub struct ConfigFileParameters {
     pub spotify_stop: String,
     pub spotify_next: String,
     pub spotify_previous: String,
-    pub spotify_volume: String
+    pub spotify_volume: String,
+    #[serde(default = "default_spotify_rate_limited")]
+    pub spotify_rate_limited: String,
+    /// Int parameter picking the active Spotify profile by its position in the list.
+    #[serde(default = "default_spotify_profile")]
+    pub spotify_profile: String,
+    /// VRChat sends the new avatar's ID here, switching to its entry in `avatars`.
+    #[serde(default = "default_avatar_change")]
+    pub avatar_change: String,
+    /// How arguments sent to specific receive addresses are read.
+    #[serde(default)]
+    pub overrides: Vec<ConfigFileParameterOverride>
+}
+
+/// Type an argument is expected to have, anything else is ignored.
+/// `auto` takes whatever arrives and converts it.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ArgumentType {
+    #[default]
+    Auto,
+    Bool,
+    Int,
+    Float,
+    String
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct ConfigFileParameterOverride {
+    /// Receive address or address pattern the override applies to.
+    pub address: String,
+    #[serde(default, rename = "type")]
+    pub argument_type: ArgumentType,
+    /// Numbers at or above this press a button.
+    #[serde(default = "default_trigger_threshold")]
+    pub threshold: f32
+}
+
+pub fn default_trigger_threshold() -> f32 {
+    0.5
+}
+
+fn default_spotify_rate_limited() -> String {
+    "/avatar/parameters/spotify_rate_limited".to_string()
+}
+
+fn default_spotify_profile() -> String {
+    "/avatar/parameters/spotify_profile".to_string()
+}
+
+fn default_avatar_change() -> String {
+    "/avatar/change".to_string()
+}
+
+/// Parameters for one avatar, anything left out keeps the address from `[parameters]`.
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct ConfigFileAvatar {
+    /// VRChat avatar ID, `avtr_...`.
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spotify_playing: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spotify_seek: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spotify_chatbox: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spotify_play: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spotify_stop: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spotify_next: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spotify_previous: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spotify_volume: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spotify_rate_limited: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spotify_profile: Option<String>,
+    /// Checked before the overrides in `[parameters]`.
+    #[serde(default)]
+    pub overrides: Vec<ConfigFileParameterOverride>
+}
+
+impl ConfigFileAvatar {
+    /// Replaces the addresses this avatar sets in `parameters`.
+    pub fn apply(&self, parameters: &mut ConfigFileParameters) {
+        let addresses = [
+            (&self.spotify_playing, &mut parameters.spotify_playing),
+            (&self.spotify_seek, &mut parameters.spotify_seek),
+            (&self.spotify_chatbox, &mut parameters.spotify_chatbox),
+            (&self.spotify_play, &mut parameters.spotify_play),
+            (&self.spotify_stop, &mut parameters.spotify_stop),
+            (&self.spotify_next, &mut parameters.spotify_next),
+            (&self.spotify_previous, &mut parameters.spotify_previous),
+            (&self.spotify_volume, &mut parameters.spotify_volume),
+            (&self.spotify_rate_limited, &mut parameters.spotify_rate_limited),
+            (&self.spotify_profile, &mut parameters.spotify_profile)
+        ];
+
+        for (address, parameter) in addresses {
+            if let Some(address) = address {
+                *parameter = String::from(address);
+            }
+        }
+
+        parameters.overrides = self.overrides.iter()
+            .chain(parameters.overrides.iter())
+            .cloned()
+            .collect();
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ConfigFileButtons {
+    /// Presses this soon after the previous one are ignored, avatar sync can repeat them.
+    pub debounce_ms: u64,
+    /// How long a button has to be held for its long press action, which repeats at this interval while held.
+    pub long_press_ms: u64,
+    /// How far the seek actions jump.
+    pub seek_step_ms: i64,
+    /// Actions run when a receive address is held instead of tapped.
+    /// Tapping such a button only acts once it's released.
+    pub long_press: Vec<ConfigFileLongPress>
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ConfigFileLongPress {
+    /// Receive address or address pattern.
+    pub address: String,
+    pub action: OscAction
+}
+
+impl Default for ConfigFileButtons {
+    fn default() -> Self {
+        Self {
+            debounce_ms: 150,
+            long_press_ms: 500,
+            seek_step_ms: 10_000,
+            long_press: vec![]
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct ConfigFileGeneralOsc {
     pub host_address: String,
+    /// Receives every output, unless `targets` is set.
     pub client_address: String,
+    /// Also accepts OSC 1.1 over TCP (SLIP framed) here, off while empty.
+    #[serde(default)]
+    pub tcp_host_address: String,
+    #[serde(default)]
+    pub targets: Vec<ConfigFileOscTarget>,
+    #[serde(default)]
+    pub forward: ConfigFileOscForward
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ForwardMode {
+    /// Every packet received.
+    #[default]
+    All,
+    /// Packets without any message the app acts on.
+    Unmatched
+}
+
+/// Relays packets received on `host_address` so other OSC apps can listen to VRChat as well.
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct ConfigFileOscForward {
+    #[serde(default)]
+    pub mode: ForwardMode,
+    /// Where packets are relayed to, forwarding is off while this is empty.
+    #[serde(default)]
+    pub addresses: Vec<String>
+}
+
+/// Now-playing state the app sends out, targets can pick which of them they receive.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum OscOutput {
+    Playing,
+    Seek,
+    Chatbox,
+    RateLimited
+}
+
+impl OscOutput {
+    pub fn all() -> Vec<OscOutput> {
+        vec![OscOutput::Playing, OscOutput::Seek, OscOutput::Chatbox, OscOutput::RateLimited]
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ConfigFileOscTarget {
+    pub address: String,
+    #[serde(default = "OscOutput::all")]
+    pub outputs: Vec<OscOutput>,
+    /// Address prefixes to replace before sending, the longest matching one wins.
+    #[serde(default)]
+    pub remap: BTreeMap<String, String>
+}
+
+impl ConfigFileGeneralOsc {
+    /// Where outputs are sent, `client_address` with everything when no targets are configured.
+    pub fn targets(&self) -> Vec<ConfigFileOscTarget> {
+        if !self.targets.is_empty() {
+            return self.targets.clone()
+        }
+
+        vec![ConfigFileOscTarget {
+            address: String::from(&self.client_address),
+            outputs: OscOutput::all(),
+            remap: BTreeMap::new()
+        }]
+    }
+}
+
+/// Lets VRChat find the app with mDNS instead of everyone sharing ports 9000/9001.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ConfigFileOscQuery {
+    /// Listens for OSC on a random free port and advertises it, `host_address`'s port is ignored then.
+    pub enabled: bool,
+    /// Name the app is advertised as.
+    pub name: String,
+    /// OSCQuery services whose name starts with this are taken for VRChat, which then replaces `client_address`.
+    pub vrchat_service: String
+}
+
+impl Default for ConfigFileOscQuery {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            name: "spotify-osc".to_string(),
+            vrchat_service: "VRChat-Client-".to_string()
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -33,34 +352,105 @@ pub struct ConfigFileGeneralWebServer {
     pub port: u16,
 }
 
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaBackend {
+    #[default]
+    Spotify,
+    Mpris,
+    Mpd
+}
+
+/// Where VRChat keeps the avatars' OSC configs, used to check the parameter addresses.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ConfigFileVrchat {
+    /// VRChat's `OSC` folder, empty uses `AppData/LocalLow/VRChat/VRChat/OSC` in the user's profile.
+    pub osc_dir: String,
+    /// Reports parameters missing from or mistyped on the avatars when the app starts.
+    pub check_on_startup: bool
+}
+
+impl Default for ConfigFileVrchat {
+    fn default() -> Self {
+        Self {
+            osc_dir: "".to_string(),
+            check_on_startup: true
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct ConfigFileGeneral {
+    #[serde(default)]
+    pub backend: MediaBackend,
     pub osc: ConfigFileGeneralOsc,
     pub web_server: ConfigFileGeneralWebServer,
+    #[serde(default)]
+    pub oscquery: ConfigFileOscQuery,
+    #[serde(default)]
+    pub vrchat: ConfigFileVrchat
+}
+
+#[derive(Deserialize, Serialize, Default)]
+pub struct ConfigFileMpris {
+    /// Player bus name without the `org.mpris.MediaPlayer2.` prefix, empty picks the first player found.
+    pub player: String,
+    /// D-Bus address to connect to, empty uses the session bus.
+    pub bus_address: String
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ConfigFileMpd {
+    /// Either `host:port` or the path to MPD's Unix socket.
+    pub address: String,
+    pub password: String
+}
+
+impl Default for ConfigFileMpd {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:6600".to_string(),
+            password: "".to_string()
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct ConfigFile {
     pub general: ConfigFileGeneral,
     pub spotify: ConfigFileSpotify,
-    pub parameters: ConfigFileParameters
-}
-
-pub trait Configuration {
-    fn default() -> Self;
+    pub parameters: ConfigFileParameters,
+    #[serde(default)]
+    pub mpris: ConfigFileMpris,
+    #[serde(default)]
+    pub mpd: ConfigFileMpd,
+    #[serde(default)]
+    pub buttons: ConfigFileButtons,
+    /// Per-avatar parameters, avatars without an entry use `[parameters]`.
+    /// Left out when empty, toml can't write `avatars = []` after the tables above.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub avatars: Vec<ConfigFileAvatar>
 }
 
 impl Default for ConfigFile {
     fn default() -> Self {
         Self {
             general: ConfigFileGeneral {
+                backend: MediaBackend::Spotify,
                 osc: ConfigFileGeneralOsc {
                     host_address: "127.0.0.1:9001".to_string(),
-                    client_address: "127.0.0.1:9000".to_string() },
+                    client_address: "127.0.0.1:9000".to_string(),
+                    tcp_host_address: "".to_string(),
+                    targets: vec![],
+                    forward: ConfigFileOscForward::default()
+                },
                 web_server: ConfigFileGeneralWebServer {
                     host_address: "127.0.0.1".to_string(),
                     port: 8080
-                }
+                },
+                oscquery: ConfigFileOscQuery::default(),
+                vrchat: ConfigFileVrchat::default()
             },
 
             spotify: ConfigFileSpotify {
@@ -68,7 +458,14 @@ impl Default for ConfigFile {
                 client_secret: "".to_string(),
                 callback_url: "http://localhost:8080/callback".to_string(),
                 token: "".to_string(),
-                refresh_token: "".to_string()
+                refresh_token: "".to_string(),
+                scope: "".to_string(),
+                api_url: default_spotify_api_url(),
+                accounts_url: default_spotify_accounts_url(),
+                credentials_path: default_spotify_credentials_path(),
+                credentials_key_file: "".to_string(),
+                active_profile: "".to_string(),
+                profiles: default_spotify_profiles()
             },
             parameters: ConfigFileParameters {
                 spotify_playing: "/avatar/parameters/spotify_playing".to_string(),
@@ -78,8 +475,16 @@ impl Default for ConfigFile {
                 spotify_stop: "/avatar/parameters/spotify_stop".to_string(),
                 spotify_next: "/avatar/parameters/spotify_next".to_string(),
                 spotify_previous: "/avatar/parameters/spotify_previous".to_string(),
-                spotify_volume: "/avatar/parameters/spotify_volume".to_string()
-            }
+                spotify_volume: "/avatar/parameters/spotify_volume".to_string(),
+                spotify_rate_limited: default_spotify_rate_limited(),
+                spotify_profile: default_spotify_profile(),
+                avatar_change: default_avatar_change(),
+                overrides: vec![]
+            },
+            mpris: ConfigFileMpris::default(),
+            mpd: ConfigFileMpd::default(),
+            buttons: ConfigFileButtons::default(),
+            avatars: vec![]
         }
     }
 }
@@ -89,8 +494,44 @@ impl ConfigFile {
         base64::encode(format!("{}:{}", &self.spotify.client_id, &self.spotify.client_secret))
     }
 
+    /// Without a client secret the PKCE flow is used instead.
+    pub fn uses_pkce(&self) -> bool {
+        self.spotify.client_secret.is_empty()
+    }
+
+    pub fn get_client_auth(&self) -> SpotifyClientAuth {
+        if self.uses_pkce() {
+            SpotifyClientAuth::Pkce { client_id: String::from(&self.spotify.client_id) }
+        } else {
+            SpotifyClientAuth::Secret(self.get_auth_base64())
+        }
+    }
+
+    /// The parameters for `avatar`, `[parameters]` for avatars without an entry.
+    pub fn parameters_for(&self, avatar: Option<&str>) -> ConfigFileParameters {
+        let mut parameters = self.parameters.clone();
+
+        if let Some(profile) = avatar.and_then(|id| self.avatar(id)) {
+            profile.apply(&mut parameters);
+        }
+
+        parameters
+    }
+
+    pub fn avatar(&self, id: &str) -> Option<&ConfigFileAvatar> {
+        self.avatars.iter().find(|avatar| avatar.id == id)
+    }
+
     pub fn get_webserver_address(&self) -> (String, u16) {
         (String::from(&self.general.web_server.host_address), self.general.web_server.port)
     }
+
+    /// Where the user can (re)start the Spotify login, next to the configured callback.
+    pub fn get_setup_url(&self) -> String {
+        match self.spotify.callback_url.strip_suffix("/callback") {
+            Some(base) => format!("{}/setup", base),
+            None => format!("http://localhost:{}/setup", self.general.web_server.port)
+        }
+    }
 }
 
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use crate::entities::spotify::{SpotifyDevice, SpotifyInfo};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NowPlaying {
+    pub id: String,
+    pub title: String,
+    pub artists: Vec<String>,
+    pub position_ms: i64,
+    pub duration_ms: i64,
+    pub is_playing: bool
+}
+
+impl NowPlaying {
+    pub fn seek(&self) -> f32 {
+        if self.duration_ms <= 0 {
+            return 0_f32;
+        }
+
+        self.position_ms as f32 / self.duration_ms as f32
+    }
+}
+
+impl From<SpotifyInfo> for NowPlaying {
+    fn from(info: SpotifyInfo) -> Self {
+        Self {
+            id: info.item.id,
+            title: info.item.name,
+            artists: info.item.artists.into_iter().map(|artist| artist.name).collect(),
+            position_ms: info.progress_ms,
+            duration_ms: info.item.duration_ms,
+            is_playing: info.is_playing
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MediaDevice {
+    pub id: String,
+    pub is_active: bool,
+    pub volume_percent: u16
+}
+
+impl From<&SpotifyDevice> for MediaDevice {
+    fn from(device: &SpotifyDevice) -> Self {
+        Self {
+            id: String::from(&device.id),
+            is_active: device.is_active,
+            volume_percent: device.volume_percent
+        }
+    }
+}
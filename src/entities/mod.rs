@@ -1,2 +1,7 @@
 pub mod spotify;
-pub mod config;
\ No newline at end of file
+pub mod config;
+pub mod media;
+pub mod scope;
+pub mod osc;
+pub mod oscquery;
+pub mod vrchat;
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// What an incoming OSC message asks the player to do.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OscAction {
+    Play,
+    Pause,
+    Next,
+    Previous,
+    Volume,
+    SwitchProfile,
+    SeekForward,
+    SeekBackward,
+    AvatarChange
+}
+
+impl OscAction {
+    /// Buttons fire once per press instead of reading the argument as a value.
+    pub fn is_button(&self) -> bool {
+        !matches!(self, OscAction::Volume | OscAction::SwitchProfile | OscAction::AvatarChange)
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+/// Neither read nor written, used for containers.
+pub const ACCESS_NONE: u8 = 0;
+pub const ACCESS_READ: u8 = 1;
+pub const ACCESS_WRITE: u8 = 2;
+pub const ACCESS_READ_WRITE: u8 = 3;
+
+/// A node of an OSCQuery address tree, containers have `CONTENTS` and methods a `TYPE`.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct OscQueryNode {
+    #[serde(rename = "FULL_PATH")]
+    pub full_path: String,
+    #[serde(rename = "CONTENTS", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub contents: BTreeMap<String, OscQueryNode>,
+    #[serde(rename = "TYPE", default, skip_serializing_if = "Option::is_none")]
+    pub osc_type: Option<String>,
+    #[serde(rename = "ACCESS", default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<u8>,
+    #[serde(rename = "DESCRIPTION", default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Current values, one per type in `TYPE`.
+    #[serde(rename = "VALUE", default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Vec<serde_json::Value>>
+}
+
+impl OscQueryNode {
+    pub fn root() -> Self {
+        Self {
+            full_path: "/".to_string(),
+            access: Some(ACCESS_NONE),
+            ..Self::default()
+        }
+    }
+
+    /// The node at `path`, `/` being this node.
+    pub fn find(&self, path: &str) -> Option<&OscQueryNode> {
+        path.split('/')
+            .filter(|part| !part.is_empty())
+            .try_fold(self, |node, part| node.contents.get(part))
+    }
+
+    /// Adds a method at `address`, creating the containers above it.
+    pub fn insert(&mut self, address: &str, osc_type: &str, access: u8, description: &str) {
+        let mut node = self;
+        let mut full_path = String::new();
+
+        for part in address.split('/').filter(|part| !part.is_empty()) {
+            full_path = format!("{}/{}", full_path, part);
+
+            node = node.contents.entry(part.to_string()).or_insert_with(|| OscQueryNode {
+                full_path: String::from(&full_path),
+                access: Some(ACCESS_NONE),
+                ..OscQueryNode::default()
+            });
+        }
+
+        node.osc_type = Some(osc_type.to_string());
+        node.access = Some(access);
+        node.description = Some(description.to_string());
+    }
+}
+
+/// What `?HOST_INFO` answers, mainly where to send OSC to.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct OscQueryHostInfo {
+    #[serde(rename = "NAME")]
+    pub name: String,
+    #[serde(rename = "OSC_IP")]
+    pub osc_ip: String,
+    #[serde(rename = "OSC_PORT")]
+    pub osc_port: u16,
+    #[serde(rename = "OSC_TRANSPORT", default = "default_osc_transport")]
+    pub osc_transport: String,
+    #[serde(rename = "EXTENSIONS", default)]
+    pub extensions: BTreeMap<String, bool>
+}
+
+fn default_osc_transport() -> String {
+    "UDP".to_string()
+}
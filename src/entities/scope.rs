@@ -0,0 +1,70 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use crate::entities::config::ConfigFile;
+
+pub const USER_READ_CURRENTLY_PLAYING: &str = "user-read-currently-playing";
+pub const USER_READ_PLAYBACK_STATE: &str = "user-read-playback-state";
+pub const USER_MODIFY_PLAYBACK_STATE: &str = "user-modify-playback-state";
+
+/// A set of Spotify OAuth scopes, written space separated like the accounts service expects.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Scopes {
+    scopes: BTreeSet<String>
+}
+
+impl Scopes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, scope: &str) -> Self {
+        self.scopes.insert(scope.to_string());
+        self
+    }
+
+    /// Parses the space separated `scope` of a token response.
+    pub fn parse(scope: &str) -> Self {
+        Self {
+            scopes: scope.split_whitespace().map(|scope| scope.to_string()).collect()
+        }
+    }
+
+    /// Scopes needed by the features enabled in the config.
+    pub fn required_for(cfg: &ConfigFile) -> Self {
+        let mut scopes = Self::new().with(USER_READ_CURRENTLY_PLAYING);
+
+        let parameters = &cfg.parameters;
+        let controls = [&parameters.spotify_play, &parameters.spotify_stop, &parameters.spotify_next,
+                        &parameters.spotify_previous, &parameters.spotify_volume];
+
+        // Every playback control looks up the active device before changing anything.
+        if controls.iter().any(|address| !address.is_empty()) {
+            scopes = scopes
+                .with(USER_READ_PLAYBACK_STATE)
+                .with(USER_MODIFY_PLAYBACK_STATE);
+        }
+
+        scopes
+    }
+
+    /// Scopes in `self` that `granted` doesn't have.
+    pub fn missing(&self, granted: &Scopes) -> Scopes {
+        Self {
+            scopes: self.scopes.difference(&granted.scopes).cloned().collect()
+        }
+    }
+
+    pub fn contains(&self, scope: &str) -> bool {
+        self.scopes.contains(scope)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scopes.is_empty()
+    }
+}
+
+impl Display for Scopes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.scopes.iter().map(|scope| scope.as_str()).collect::<Vec<&str>>().join(" "))
+    }
+}
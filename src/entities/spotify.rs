@@ -1,3 +1,4 @@
+use std::fmt::{Display, Formatter};
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Serialize)]
@@ -20,9 +21,43 @@ pub struct SpotifyInfo {
     pub is_playing: bool
 }
 
-#[derive(Debug, Deserialize)]
+/// Error object of the Web API, `{"error": {"status", "message", "reason"}}`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SpotifyApiError {
+    pub status: u16,
+    pub message: String,
+    #[serde(default)]
+    pub reason: Option<String>
+}
+
+impl Display for SpotifyApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.reason {
+            Some(reason) => write!(f, "{} ({})", self.message, reason),
+            None => write!(f, "{}", self.message)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SpotifyApiErrorResponse {
+    pub error: SpotifyApiError
+}
+
+/// Error body of the accounts service, `{"error", "error_description"}`.
+#[derive(Deserialize)]
+pub struct SpotifyAuthErrorResponse {
+    pub error: String,
+    #[serde(default)]
+    pub error_description: Option<String>
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct SpotifyCallbackQuery {
-    pub code : String
+    pub code: Option<String>,
+    pub state: Option<String>,
+    /// Set instead of `code` when the user didn't grant access, e.g. `access_denied`.
+    pub error: Option<String>
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -30,6 +65,10 @@ pub struct SpotifyAuthTokenPayload {
     pub code: String,
     pub redirect_uri : String,
     pub grant_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_verifier: Option<String>
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -42,6 +81,8 @@ pub struct SpotifySetActivePayload {
 pub struct SpotifyAuthRefreshTokenPayload {
     pub refresh_token: String,
     pub grant_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -49,6 +90,9 @@ pub struct SpotifyAuthTokenResponse {
     pub access_token: String,
     pub token_type: String,
     pub expires_in: i32,
+    /// Only present when Spotify rotated the refresh token.
+    #[serde(default)]
+    pub refresh_token: Option<String>,
     pub scope: String
 }
 
@@ -77,7 +121,7 @@ impl SpotifyDevices {
     pub fn get_active(&self) -> Option<&SpotifyDevice> {
         for device in &self.devices {
             if device.is_active {
-                return Some(&device);
+                return Some(device);
             }
         }
 
@@ -92,5 +136,15 @@ impl SpotifyDevices {
 #[derive(Deserialize, Serialize)]
 pub struct SpotifyPlayback {
     pub device: SpotifyDevice,
-    pub is_playing: bool
-}
\ No newline at end of file
+    pub is_playing: bool,
+    #[serde(default)]
+    pub progress_ms: i64
+}
+
+/// How the app identifies itself to the accounts service.
+pub enum SpotifyClientAuth {
+    /// Basic auth with the base64 encoded `client_id:client_secret`.
+    Secret(String),
+    /// Authorization Code with PKCE, only the client ID is sent and no secret is needed.
+    Pkce { client_id: String }
+}
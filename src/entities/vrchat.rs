@@ -0,0 +1,47 @@
+use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+/// What VRChat writes to `OSC/usr_*/Avatars/avtr_*.json` for every avatar worn with OSC enabled.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct VrchatAvatarConfig {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub parameters: Vec<VrchatAvatarParameter>
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct VrchatAvatarParameter {
+    pub name: String,
+    /// Where VRChat listens for the parameter, missing for parameters OSC can't set.
+    #[serde(default)]
+    pub input: Option<VrchatParameterEndpoint>,
+    /// Where VRChat sends the parameter when it changes.
+    #[serde(default)]
+    pub output: Option<VrchatParameterEndpoint>
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct VrchatParameterEndpoint {
+    pub address: String,
+    #[serde(rename = "type")]
+    pub parameter_type: VrchatParameterType
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VrchatParameterType {
+    Bool,
+    Int,
+    Float
+}
+
+impl Display for VrchatParameterType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VrchatParameterType::Bool => write!(f, "Bool"),
+            VrchatParameterType::Int => write!(f, "Int"),
+            VrchatParameterType::Float => write!(f, "Float")
+        }
+    }
+}
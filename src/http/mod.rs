@@ -1,13 +1,67 @@
-use crate::entities::spotify::SpotifyInfo;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use reqwest::StatusCode;
+use crate::entities::spotify::{SpotifyApiError, SpotifyInfo};
 
 pub mod spotify;
+pub mod rate_limit;
+pub mod oscquery;
 
+#[allow(clippy::upper_case_acronyms)]
 pub enum SpotifyValue {
     INFO(SpotifyInfo),
     EMPTY
 }
 
+#[derive(Debug)]
 pub enum RequestError {
-    UNAUTHORIZED,
-    OTHER
-}
\ No newline at end of file
+    /// The request never got a response.
+    Network(reqwest::Error),
+    /// The access token was rejected, refreshing it may help.
+    Unauthorized(Option<SpotifyApiError>),
+    /// The token doesn't carry a scope the endpoint needs.
+    MissingScope(SpotifyApiError),
+    /// Too many requests, `retry_after` comes from the `Retry-After` header.
+    RateLimited { retry_after: Option<Duration> },
+    /// Any other non-success status, with Spotify's error body when it sent one.
+    Status { status: StatusCode, error: Option<SpotifyApiError> },
+    /// The response body didn't have the expected shape.
+    Deserialize(reqwest::Error)
+}
+
+impl RequestError {
+    /// Network failures and 5xx responses, worth retrying after a short wait.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            RequestError::Network(_) => true,
+            RequestError::Status { status, .. } => status.is_server_error(),
+            _ => false
+        }
+    }
+}
+
+impl Display for RequestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::Network(err) => write!(f, "couldn't reach Spotify: {}", err),
+            RequestError::Unauthorized(_) => write!(f, "Spotify rejected the access token"),
+            RequestError::MissingScope(err) => write!(f, "the Spotify token is missing a required scope: {}", err),
+            RequestError::RateLimited { retry_after: Some(retry_after) } => {
+                write!(f, "rate limited by Spotify, retry after {}s", retry_after.as_secs())
+            }
+            RequestError::RateLimited { retry_after: None } => write!(f, "rate limited by Spotify"),
+            RequestError::Status { status, error: Some(err) } => write!(f, "Spotify returned {}: {}", status, err),
+            RequestError::Status { status, error: None } => write!(f, "Spotify returned {}", status),
+            RequestError::Deserialize(err) => write!(f, "unexpected response from Spotify: {}", err)
+        }
+    }
+}
+
+impl std::error::Error for RequestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RequestError::Network(err) | RequestError::Deserialize(err) => Some(err),
+            _ => None
+        }
+    }
+}
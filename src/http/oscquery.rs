@@ -0,0 +1,30 @@
+use std::time::Duration;
+use reqwest::{Client, StatusCode};
+use crate::entities::oscquery::{OscQueryHostInfo, OscQueryNode};
+
+/// OSCQuery servers are local, anything slower than this isn't answering.
+const OSCQUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub async fn fetch_oscquery_host_info(http: &Client, base_url: &str) -> Result<OscQueryHostInfo, reqwest::Error> {
+    http.get(format!("{}/?HOST_INFO", base_url))
+        .timeout(OSCQUERY_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+/// The node at `path`, `None` when the server doesn't have it.
+pub async fn fetch_oscquery_node(http: &Client, base_url: &str, path: &str) -> Result<Option<OscQueryNode>, reqwest::Error> {
+    let res = http.get(format!("{}{}", base_url, path))
+        .timeout(OSCQUERY_TIMEOUT)
+        .send()
+        .await?;
+
+    if res.status() == StatusCode::NOT_FOUND {
+        return Ok(None)
+    }
+
+    Ok(Some(res.error_for_status()?.json().await?))
+}
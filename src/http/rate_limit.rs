@@ -0,0 +1,37 @@
+use std::time::{Duration, Instant};
+
+/// Used when Spotify answers 429 without a usable `Retry-After` header.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Shared window during which no request should be sent to Spotify.
+#[derive(Default)]
+pub struct RateLimiter {
+    until: std::sync::Mutex<Option<Instant>>
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pauses requests for `retry_after`, never shortening a window that is already running.
+    pub fn limit(&self, retry_after: Option<Duration>) {
+        let until = Instant::now() + retry_after.unwrap_or(DEFAULT_RETRY_AFTER);
+        let mut current = self.until.lock().unwrap();
+
+        if current.map(|current| current < until).unwrap_or(true) {
+            *current = Some(until);
+        }
+    }
+
+    /// Time left until requests may be sent again, `None` when not rate limited.
+    pub fn remaining(&self) -> Option<Duration> {
+        let until = (*self.until.lock().unwrap())?;
+
+        until.checked_duration_since(Instant::now()).filter(|remaining| !remaining.is_zero())
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.remaining().is_some()
+    }
+}
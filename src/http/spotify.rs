@@ -1,62 +1,95 @@
-use reqwest::{Client, Error, StatusCode};
-use crate::entities::spotify::{SpotifyAuthRefreshTokenPayload, SpotifyAuthRefreshTokenResponse, SpotifyAuthTokenPayload, SpotifyAuthTokenResponse, SpotifyDevices, SpotifyInfo, SpotifyPlayback, SpotifySetActivePayload};
+use std::time::Duration;
+use reqwest::{Client, Error, RequestBuilder, Response, StatusCode};
+use crate::entities::spotify::{SpotifyApiError, SpotifyApiErrorResponse, SpotifyAuthErrorResponse, SpotifyAuthRefreshTokenPayload, SpotifyAuthRefreshTokenResponse, SpotifyAuthTokenPayload, SpotifyAuthTokenResponse, SpotifyClientAuth, SpotifyDevices, SpotifyInfo, SpotifyPlayback, SpotifySetActivePayload};
 use crate::http::{RequestError, SpotifyValue};
 
-pub async fn fetch_spotify_info(http: &Client, auth: &String) -> Result<SpotifyValue, RequestError> {
-    let res = http.get("https://api.spotify.com/v1/me/player/currently-playing?market=ES")
-        .header(reqwest::header::AUTHORIZATION, format!("{} {}", "Bearer ", auth))
-        .send()
-        .await;
+async fn check_response(res: Result<Response, Error>) -> Result<Response, RequestError> {
+    let res = res.map_err(RequestError::Network)?;
 
-    match res {
-        Ok(res) => {
-            let code = res.status();
+    let status = res.status();
 
-            if code == StatusCode::NO_CONTENT {
-                return Ok(SpotifyValue::EMPTY)
-            }
+    if status.is_success() {
+        return Ok(res)
+    }
 
-            if code == StatusCode::UNAUTHORIZED {
-                return Err(RequestError::UNAUTHORIZED)
-            }
+    let retry_after = res.headers().get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
 
-            let response = res.json::<SpotifyInfo>().await;
+    let error = read_error(res).await;
 
-            if response.is_err() {
-                return Ok(SpotifyValue::EMPTY)
-            }
+    if status == StatusCode::UNAUTHORIZED {
+        return Err(RequestError::Unauthorized(error))
+    }
 
-            let response = response.unwrap();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return Err(RequestError::RateLimited { retry_after })
+    }
 
-            Ok(SpotifyValue::INFO(response))
-        }
-        Err(_) => {
-            Err(RequestError::OTHER)
+    if status == StatusCode::FORBIDDEN {
+        if let Some(error) = &error {
+            if error.message.to_lowercase().contains("scope") {
+                return Err(RequestError::MissingScope(error.clone()))
+            }
         }
     }
+
+    Err(RequestError::Status { status, error })
+}
+
+/// Reads the error body, which differs between the Web API and the accounts service.
+async fn read_error(res: Response) -> Option<SpotifyApiError> {
+    let status = res.status().as_u16();
+    let body = res.bytes().await.ok()?;
+
+    if let Ok(response) = serde_json::from_slice::<SpotifyApiErrorResponse>(&body) {
+        return Some(response.error)
+    }
+
+    if let Ok(response) = serde_json::from_slice::<SpotifyAuthErrorResponse>(&body) {
+        return Some(SpotifyApiError {
+            status,
+            message: response.error_description.unwrap_or_else(|| String::from(&response.error)),
+            reason: Some(response.error)
+        })
+    }
+
+    None
 }
 
-pub async fn fetch_spotify_devices(http: &Client, auth: &String) -> Result<SpotifyDevices, RequestError> {
-    let res = http.get("https://api.spotify.com/v1/me/player/devices")
+pub async fn fetch_spotify_info(http: &Client, api_url: &str, auth: &String) -> Result<SpotifyValue, RequestError> {
+    let res = http.get(format!("{}/me/player/currently-playing?market=ES", api_url))
         .header(reqwest::header::AUTHORIZATION, format!("{} {}", "Bearer ", auth))
         .send()
         .await;
 
-    match res {
-        Ok(res) => {
-            let response = res.json::<SpotifyDevices>().await;
-            let response = response.unwrap();
+    let res = check_response(res).await?;
 
-            Ok(response)
-        }
-        Err(_) => {
-            Err(RequestError::OTHER)
-        }
+    if res.status() == StatusCode::NO_CONTENT {
+        return Ok(SpotifyValue::EMPTY)
+    }
+
+    // Ads and podcast episodes come without a track, treat them as nothing playing.
+    match res.json::<SpotifyInfo>().await {
+        Ok(response) => Ok(SpotifyValue::INFO(response)),
+        Err(_) => Ok(SpotifyValue::EMPTY)
     }
 }
 
-pub async fn set_spotify_volume(http: &Client, auth: &String, device_id: &String, volume_percent: u16) -> Result<(), RequestError> {
-    let res = http.put("https://api.spotify.com/v1/me/player/volume")
+pub async fn fetch_spotify_devices(http: &Client, api_url: &str, auth: &String) -> Result<SpotifyDevices, RequestError> {
+    let res = http.get(format!("{}/me/player/devices", api_url))
+        .header(reqwest::header::AUTHORIZATION, format!("{} {}", "Bearer ", auth))
+        .send()
+        .await;
+
+    let res = check_response(res).await?;
+
+    res.json::<SpotifyDevices>().await.map_err(RequestError::Deserialize)
+}
+
+pub async fn set_spotify_volume(http: &Client, api_url: &str, auth: &String, device_id: &String, volume_percent: u16) -> Result<(), RequestError> {
+    let res = http.put(format!("{}/me/player/volume", api_url))
         .query(&[("device_id", device_id)])
         .query(&[("volume_percent", volume_percent.to_string())])
         .header(reqwest::header::AUTHORIZATION, format!("{} {}", "Bearer ", auth))
@@ -64,89 +97,78 @@ pub async fn set_spotify_volume(http: &Client, auth: &String, device_id: &String
         .send()
         .await;
 
-    match res {
-        Ok(_) => {
-            Ok(())
-        }
-        Err(_) => {
-            Err(RequestError::OTHER)
-        }
-    }
+    check_response(res).await?;
+
+    Ok(())
 }
 
-pub async fn set_spotify_playback_play(http: &Client, auth: &String, device_id: &String) -> Result<(), RequestError> {
-    let res = http.put("https://api.spotify.com/v1/me/player/play")
+pub async fn set_spotify_seek(http: &Client, api_url: &str, auth: &String, device_id: &String, position_ms: i64) -> Result<(), RequestError> {
+    let res = http.put(format!("{}/me/player/seek", api_url))
         .query(&[("device_id", device_id)])
+        .query(&[("position_ms", position_ms.to_string())])
         .header(reqwest::header::AUTHORIZATION, format!("{} {}", "Bearer ", auth))
         .header(reqwest::header::CONTENT_LENGTH, "0")
         .send()
         .await;
 
-    match res {
-        Ok(_) => {
-            Ok(())
-        }
-        Err(_) => {
-            Err(RequestError::OTHER)
-        }
-    }
+    check_response(res).await?;
+
+    Ok(())
 }
 
-pub async fn set_spotify_playback_stop(http: &Client, auth: &String, device_id: &String) -> Result<(), RequestError> {
-    let res = http.put("https://api.spotify.com/v1/me/player/pause")
+pub async fn set_spotify_playback_play(http: &Client, api_url: &str, auth: &String, device_id: &String) -> Result<(), RequestError> {
+    let res = http.put(format!("{}/me/player/play", api_url))
         .query(&[("device_id", device_id)])
         .header(reqwest::header::AUTHORIZATION, format!("{} {}", "Bearer ", auth))
         .header(reqwest::header::CONTENT_LENGTH, "0")
         .send()
         .await;
 
-    match res {
-        Ok(_) => {
-            Ok(())
-        }
-        Err(_) => {
-            Err(RequestError::OTHER)
-        }
-    }
+    check_response(res).await?;
+
+    Ok(())
 }
 
-pub async fn set_spotify_playback_next(http: &Client, auth: &String, device_id: &String) -> Result<(), RequestError> {
-    let res = http.post("https://api.spotify.com/v1/me/player/next")
+pub async fn set_spotify_playback_stop(http: &Client, api_url: &str, auth: &String, device_id: &String) -> Result<(), RequestError> {
+    let res = http.put(format!("{}/me/player/pause", api_url))
         .query(&[("device_id", device_id)])
         .header(reqwest::header::AUTHORIZATION, format!("{} {}", "Bearer ", auth))
         .header(reqwest::header::CONTENT_LENGTH, "0")
         .send()
         .await;
 
-    match res {
-        Ok(_) => {
-            Ok(())
-        }
-        Err(_) => {
-            Err(RequestError::OTHER)
-        }
-    }
+    check_response(res).await?;
+
+    Ok(())
 }
 
-pub async fn set_spotify_playback_previous(http: &Client, auth: &String, device_id: &String) -> Result<(), RequestError> {
-    let res = http.post("https://api.spotify.com/v1/me/player/previous")
+pub async fn set_spotify_playback_next(http: &Client, api_url: &str, auth: &String, device_id: &String) -> Result<(), RequestError> {
+    let res = http.post(format!("{}/me/player/next", api_url))
         .query(&[("device_id", device_id)])
         .header(reqwest::header::AUTHORIZATION, format!("{} {}", "Bearer ", auth))
         .header(reqwest::header::CONTENT_LENGTH, "0")
         .send()
         .await;
 
-    match res {
-        Ok(_) => {
-            Ok(())
-        }
-        Err(_) => {
-            Err(RequestError::OTHER)
-        }
-    }
+    check_response(res).await?;
+
+    Ok(())
 }
 
-pub async fn set_spotify_active(http: &Client, auth: &String, device_id: &String, keep_state: bool) -> Result<(), RequestError> {
+pub async fn set_spotify_playback_previous(http: &Client, api_url: &str, auth: &String, device_id: &String) -> Result<(), RequestError> {
+    let res = http.post(format!("{}/me/player/previous", api_url))
+        .query(&[("device_id", device_id)])
+        .header(reqwest::header::AUTHORIZATION, format!("{} {}", "Bearer ", auth))
+        .header(reqwest::header::CONTENT_LENGTH, "0")
+        .send()
+        .await;
+
+    check_response(res).await?;
+
+    Ok(())
+}
+
+pub async fn set_spotify_active(http: &Client, api_url: &str, auth: &String, device_id: &String, keep_state: bool) -> Result<(), RequestError> {
 
     let payload = SpotifySetActivePayload {
         device_ids: vec![String::from(device_id)],
@@ -155,104 +177,88 @@ pub async fn set_spotify_active(http: &Client, auth: &String, device_id: &String
 
     let payload_data = serde_json::to_string(&payload).unwrap();
 
-    let res = http.put("https://api.spotify.com/v1/me/player")
+    let res = http.put(format!("{}/me/player", api_url))
         .header(reqwest::header::AUTHORIZATION, format!("{} {}", "Bearer ", auth))
         .header(reqwest::header::CONTENT_TYPE, "application/json")
         .body(payload_data)
         .send()
         .await;
 
-    match res {
-        Ok(_) => {
-            Ok(())
-        }
-        Err(_) => {
-            Err(RequestError::OTHER)
-        }
-    }
+    check_response(res).await?;
+
+    Ok(())
 }
 
-pub async fn get_spotify_playback_state(http: &Client, auth: &String) -> Result<Option<SpotifyPlayback>, RequestError> {
-    let res = http.get("https://api.spotify.com/v1/me/player")
+pub async fn get_spotify_playback_state(http: &Client, api_url: &str, auth: &String) -> Result<Option<SpotifyPlayback>, RequestError> {
+    let res = http.get(format!("{}/me/player", api_url))
         .header(reqwest::header::AUTHORIZATION, format!("{} {}", "Bearer ", auth))
         .header(reqwest::header::CONTENT_LENGTH, "0")
         .send()
         .await;
 
-    match res {
-        Ok(res) => {
-            let status = res.status();
+    let res = check_response(res).await?;
 
-            if status == StatusCode::NO_CONTENT {
-                return Ok(None);
-            }
-
-            if !status.is_success() {
-                return Err(RequestError::OTHER);
-            }
-
-            let response = res.json::<SpotifyPlayback>().await.unwrap();
-
-            Ok(Some(response))
-        }
-        Err(_) => {
-            Err(RequestError::OTHER)
-        }
+    if res.status() == StatusCode::NO_CONTENT {
+        return Ok(None);
     }
+
+    res.json::<SpotifyPlayback>().await.map(Some).map_err(RequestError::Deserialize)
 }
 
-pub async fn authenticate_spotify(http: &Client, code: &String, redirect_uri: String, auth: String) -> Result<SpotifyAuthRefreshTokenResponse, Error> {
+pub async fn authenticate_spotify(http: &Client, accounts_url: &str, code: &String, redirect_uri: String, auth: &SpotifyClientAuth, verifier: Option<String>) -> Result<SpotifyAuthRefreshTokenResponse, RequestError> {
     let payload = SpotifyAuthTokenPayload {
         code: String::from(code),
         redirect_uri,
-        grant_type: "authorization_code".to_string()
+        grant_type: "authorization_code".to_string(),
+        client_id: pkce_client_id(auth),
+        code_verifier: verifier
     };
 
     let payload_data = serde_urlencoded::to_string(payload).unwrap();
 
-    let res = http.post("https://accounts.spotify.com/api/token")
-        .header(reqwest::header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-        .header(reqwest::header::AUTHORIZATION, format!("{} {}", "Basic", auth))
+    let res = token_request(http, accounts_url, auth)
         .body(payload_data)
         .send()
         .await;
 
-    return match res {
-        Ok(res) => {
-
-            let response_data: SpotifyAuthRefreshTokenResponse = res.json().await.unwrap();
+    let res = check_response(res).await?;
 
-            Ok(response_data)
-        }
-        Err(err) => {
-            Err(err)
-        }
-    }
+    res.json::<SpotifyAuthRefreshTokenResponse>().await.map_err(RequestError::Deserialize)
 }
 
-pub async fn refresh_authenticate_spotify(http: &Client, code: String, auth: String) -> Result<SpotifyAuthTokenResponse, Error> {
+pub async fn refresh_authenticate_spotify(http: &Client, accounts_url: &str, code: String, auth: &SpotifyClientAuth) -> Result<SpotifyAuthTokenResponse, RequestError> {
     let payload = SpotifyAuthRefreshTokenPayload {
         refresh_token: code,
-        grant_type: "refresh_token".to_string()
+        grant_type: "refresh_token".to_string(),
+        client_id: pkce_client_id(auth)
     };
 
     let payload_data = serde_urlencoded::to_string(payload).unwrap();
 
-    let res = http.post("https://accounts.spotify.com/api/token")
-        .header(reqwest::header::CONTENT_TYPE, "application/x-www-form-urlencoded")
-        .header(reqwest::header::AUTHORIZATION, format!("{} {}", "Basic", auth))
+    let res = token_request(http, accounts_url, auth)
         .body(payload_data)
         .send()
         .await;
 
-    return match res {
-        Ok(res) => {
-            let response_data: SpotifyAuthTokenResponse = res.json().await.unwrap();
+    let res = check_response(res).await?;
 
-            Ok(response_data)
-        }
-        Err(err) => {
-            Err(err)
-        }
+    res.json::<SpotifyAuthTokenResponse>().await.map_err(RequestError::Deserialize)
+}
+
+/// PKCE clients identify themselves in the body instead of with Basic auth.
+fn pkce_client_id(auth: &SpotifyClientAuth) -> Option<String> {
+    match auth {
+        SpotifyClientAuth::Pkce { client_id } => Some(String::from(client_id)),
+        SpotifyClientAuth::Secret(_) => None
     }
-}
\ No newline at end of file
+}
+
+fn token_request(http: &Client, accounts_url: &str, auth: &SpotifyClientAuth) -> RequestBuilder {
+    let req = http.post(format!("{}/api/token", accounts_url))
+        .header(reqwest::header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+
+    match auth {
+        SpotifyClientAuth::Secret(auth) => req.header(reqwest::header::AUTHORIZATION, format!("{} {}", "Basic", auth)),
+        SpotifyClientAuth::Pkce { .. } => req
+    }
+}
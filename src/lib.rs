@@ -0,0 +1,6 @@
+pub mod utils;
+pub mod entities;
+pub mod routes;
+pub mod http;
+pub mod config;
+pub mod managers;
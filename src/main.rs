@@ -1,27 +1,43 @@
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::{Arc};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use actix_web::{App, HttpServer, web};
+use arc_swap::ArcSwap;
 use log::{error, info, LevelFilter, warn};
-use rosc::{OscPacket, OscType};
+use rosc::OscType;
 use simple_logger::SimpleLogger;
+use tokio::io::BufReader;
 use tokio::net::UdpSocket;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex, Notify};
 use tokio::task::JoinHandle;
-use crate::config::config::Config;
-use crate::entities::config::ConfigFile;
-use crate::entities::spotify::SpotifyInfoArtist;
-use crate::managers::spotify::{Spotify, SpotifyAuthError};
-use crate::routes::spotify::{spotify_callback, spotify_setup};
-use crate::routes::WebData;
-use crate::utils::osc::{encode_packet, send_to_delay};
-
-mod utils;
-mod entities;
-mod routes;
-mod http;
-mod config;
-mod managers;
+use spotify_osc::config::config::Config;
+use spotify_osc::entities::config::{ConfigFile, MediaBackend, OscOutput};
+use spotify_osc::entities::media::NowPlaying;
+use spotify_osc::entities::scope::Scopes;
+use spotify_osc::managers::authorization::Authorizations;
+use spotify_osc::managers::headless::headless_auth;
+use spotify_osc::managers::media::{MediaError, SharedMediaSource};
+use spotify_osc::managers::mpd::Mpd;
+#[cfg(target_os = "linux")]
+use spotify_osc::managers::mpris::Mpris;
+use spotify_osc::managers::profiles::SpotifyProfiles;
+use spotify_osc::entities::osc::OscAction;
+use spotify_osc::managers::avatar::{AvatarChanges, CurrentAvatar};
+use spotify_osc::managers::avatar_configs::{check_avatars, osc_dir};
+use spotify_osc::managers::buttons::{ButtonEvent, Buttons};
+use spotify_osc::managers::forwarder::OscForwarder;
+use spotify_osc::managers::oscquery::OscQuery;
+use spotify_osc::managers::router::{OscRouter, SharedRouter};
+use spotify_osc::managers::targets::OscTargets;
+use spotify_osc::managers::transport::{spawn_udp_receiver, OscSender, OscTcpListener, OscTransport};
+use spotify_osc::managers::vrchat::VrchatDiscovery;
+use spotify_osc::managers::spotify::SpotifyAuthError;
+use spotify_osc::routes::spotify::{spotify_callback, spotify_profile, spotify_profiles, spotify_setup};
+use spotify_osc::routes::WebData;
+use spotify_osc::utils::osc::decode_messages;
+
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
 
 struct Chatbox {
     pub artist: String,
@@ -42,159 +58,124 @@ impl Chatbox {
         !self.id.eq(id)
     }
 
-    pub fn update(&mut self, artists: &Vec<SpotifyInfoArtist>, song: &String, id: &String) {
-        let mut artists_vec = Vec::new();
+    pub fn update(&mut self, now_playing: &NowPlaying) {
+        self.artist = now_playing.artists.join(", ");
 
-        for artist in artists {
-            artists_vec.push(String::from(&artist.name));
-        }
-
-        self.artist = artists_vec.join(", ");
-
-        self.song = String::from(song);
-        self.id = String::from(id)
+        self.song = String::from(&now_playing.title);
+        self.id = String::from(&now_playing.id)
     }
 }
 
-fn task_set_spotify_playback_play(spotify: Arc<Mutex<Spotify>>) -> JoinHandle<()> {
+fn task_set_spotify_playback_play(source: SharedMediaSource) -> JoinHandle<()> {
     tokio::task::spawn({
 
         async move {
-            let mut spotify = spotify.lock().await;
+            let mut source = source.lock().await;
 
-            match spotify.get_playback_state().await {
-                Ok(res) => {
-                    match res {
-                        Some(res) => {
-                            if !res.is_playing {
-                                match spotify.set_playback_play(&res.device.id).await {
-                                    Ok(_) => {}
-                                    Err(_) => {}
-                                }
-                            }
-                        }
-                        None => {
-                            match spotify.get_devices().await {
-                                Ok(devices) => {
-                                    match devices.get_active() {
-                                        Some(device) => {
-                                            match spotify.set_playback_active(&device.id, true).await {
-                                                Ok(_) => {
-
-                                                }
-                                                Err(_) => {}
-                                            }
-                                        }
-                                        None => {}
-                                    }
-                                }
-                                Err(_) => {}
-                            }
-                        }
-                    }
-                }
-                Err(_) => {}
+            if let Err(err) = source.play().await {
+                error!("Couldn't resume playback: {}", err);
             }
         }
     })
 }
 
-fn task_set_spotify_playback_pause(spotify: Arc<Mutex<Spotify>>) -> JoinHandle<()> {
+fn task_set_spotify_playback_pause(source: SharedMediaSource) -> JoinHandle<()> {
     tokio::task::spawn({
 
         async move {
-            let mut spotify = spotify.lock().await;
+            let mut source = source.lock().await;
 
-            match spotify.get_playback_state().await {
-                Ok(res) => {
-                    match res {
-                        Some(res) => {
-                            if res.is_playing {
-                                match spotify.set_playback_pause(&res.device.id).await {
-                                    Ok(_) => {}
-                                    Err(_) => {}
-                                }
-                            }
-                        }
-                        None => {
-                            match spotify.get_devices().await {
-                                Ok(devices) => {
-                                    match devices.get_active() {
-                                        Some(device) => {
-                                            match spotify.set_playback_active(&device.id, false).await {
-                                                Ok(_) => {
-
-                                                }
-                                                Err(_) => {}
-                                            }
-                                        }
-                                        None => {}
-                                    }
-                                }
-                                Err(_) => {}
-                            }
-                        }
-                    }
-                }
-                Err(_) => {}
+            if let Err(err) = source.pause().await {
+                error!("Couldn't pause playback: {}", err);
             }
         }
     })
 }
 
-fn task_set_spotify_playback_next(spotify: Arc<Mutex<Spotify>>) -> JoinHandle<()> {
+fn task_set_spotify_playback_next(source: SharedMediaSource) -> JoinHandle<()> {
     tokio::task::spawn({
 
         async move {
-            let mut spotify = spotify.lock().await;
+            let mut source = source.lock().await;
+
+            if let Err(err) = source.next().await {
+                error!("Couldn't skip to the next track: {}", err);
+            }
+        }
+    })
+}
 
-            match spotify.get_devices().await {
-                Ok(devices) => {
-                    match devices.get_active() {
-                        Some(device) => {
-                            match spotify.set_playback_next(&device.id).await {
-                                Ok(_) => {
+fn task_set_spotify_playback_previous(source: SharedMediaSource) -> JoinHandle<()> {
+    tokio::task::spawn({
 
-                                }
-                                Err(_) => {}
-                            }
-                        }
-                        None => {}
-                    }
-                }
-                Err(_) => {}
+        async move {
+            let mut source = source.lock().await;
+
+            if let Err(err) = source.previous().await {
+                error!("Couldn't go back to the previous track: {}", err);
             }
         }
     })
 }
 
-fn task_set_spotify_playback_previous(spotify: Arc<Mutex<Spotify>>) -> JoinHandle<()> {
+fn task_seek(source: SharedMediaSource, offset_ms: i64) -> JoinHandle<()> {
     tokio::task::spawn({
 
         async move {
-            let mut spotify = spotify.lock().await;
+            let mut source = source.lock().await;
 
-            match spotify.get_devices().await {
-                Ok(devices) => {
-                    match devices.get_active() {
-                        Some(device) => {
-                            match spotify.set_playback_previous(&device.id).await {
-                                Ok(_) => {
+            if let Err(err) = source.seek_by(offset_ms).await {
+                error!("Couldn't seek: {}", err);
+            }
+        }
+    })
+}
 
-                                }
-                                Err(_) => {}
-                            }
-                        }
-                        None => {}
-                    }
+/// Runs a button action, actions that aren't buttons are handled where their value is read.
+fn trigger_action(source: SharedMediaSource, action: OscAction, seek_step_ms: i64) {
+    match action {
+        OscAction::Play => {
+            task_set_spotify_playback_play(source);
+        }
+        OscAction::Pause => {
+            task_set_spotify_playback_pause(source);
+        }
+        OscAction::Next => {
+            task_set_spotify_playback_next(source);
+        }
+        OscAction::Previous => {
+            task_set_spotify_playback_previous(source);
+        }
+        OscAction::SeekForward => {
+            task_seek(source, seek_step_ms);
+        }
+        OscAction::SeekBackward => {
+            task_seek(source, -seek_step_ms);
+        }
+        OscAction::Volume | OscAction::SwitchProfile | OscAction::AvatarChange => {}
+    }
+}
+
+/// Runs the long press action for as long as `press` keeps the button down.
+fn task_hold_button(buttons: Arc<Mutex<Buttons>>, source: SharedMediaSource, router: Arc<OscRouter>,
+                    address: String, press: u64, action: OscAction
+) -> JoinHandle<()> {
+    tokio::task::spawn({
+        async move {
+            loop {
+                tokio::time::sleep(router.long_press_after()).await;
+
+                if !buttons.lock().await.hold(&address, press) {
+                    break;
                 }
-                Err(_) => {}
+
+                trigger_action(source.clone(), action, router.seek_step_ms());
             }
         }
     })
 }
 
-fn task_set_spotify_volume(spotify: Arc<Mutex<Spotify>>,
+fn task_set_spotify_volume(source: SharedMediaSource,
                            spotify_volume: Arc<Mutex<(f32, f32)>>, spotify_volume_task_active: Arc<Mutex<bool>>
 ) -> JoinHandle<()> {
     tokio::task::spawn({
@@ -217,44 +198,131 @@ fn task_set_spotify_volume(spotify: Arc<Mutex<Spotify>>,
 
                 spotify_volume.0 = spotify_volume.1;
 
-                let volume = spotify_volume.clone();
+                let volume = *spotify_volume;
 
                 drop(spotify_volume);
 
-                let mut spotify = spotify.lock().await;
+                let mut source = source.lock().await;
 
-                match spotify.get_devices().await {
-                    Ok(devices) => {
-                        let device = devices.get_active();
+                match source.set_volume(volume.1).await {
+                    Ok(_) => {}
+                    Err(MediaError::NoDevice) => {
+                        continue;
+                    }
+                    Err(err) => {
+                        error!("Couldn't set the volume: {}", err);
+                    }
+                }
 
-                        match device {
-                            Some(device) => {
-                                let volume = (volume.1 * 100_f32) as u16;
+                let mut active = spotify_volume_task_active.lock().await;
+                *active = false;
+            }
+        }
+    })
+}
 
-                                match spotify.set_volume(&device.id, volume).await {
-                                    Ok(_) => {}
-                                    Err(_) => {
-                                        error!("Something went wrong while setting the volume");
-                                    }
-                                }
+fn task_switch_spotify_profile(spotify: Arc<Mutex<SpotifyProfiles>>, index: i32) -> JoinHandle<()> {
+    tokio::task::spawn({
+        async move {
+            if let Err(err) = spotify.lock().await.switch_index(index) {
+                error!("Couldn't switch Spotify profiles: {}", err);
+            }
+        }
+    })
+}
 
-                                let mut active = spotify_volume_task_active.lock().await;
-                                *active = false;
+/// Resolves when a source that pushes changes has a new one, never for sources that don't.
+async fn source_changed(changes: &Option<Arc<Notify>>) {
+    match changes {
+        Some(changes) => changes.notified().await,
+        None => std::future::pending().await
+    }
+}
 
-                            }
-                            None => {}
-                        }
+/// Reloads the config when the file changes and rebuilds the OSC router and forwarder from it.
+fn task_watch_config(config: Arc<Mutex<Config<ConfigFile>>>, router: Arc<SharedRouter>, forwarder: Arc<ArcSwap<OscForwarder>>,
+                     avatar: Arc<CurrentAvatar>, listening: Vec<(OscTransport, SocketAddr)>) -> JoinHandle<()> {
+    tokio::task::spawn({
+        async move {
+            let mut modified = config.lock().await.modified();
 
-                    }
-                    Err(_) => {
+            loop {
+                tokio::time::sleep(CONFIG_POLL_INTERVAL).await;
 
+                let mut config = config.lock().await;
+                let current = config.modified();
+
+                if current == modified {
+                    continue;
+                }
+
+                modified = current;
+
+                match config.reload() {
+                    Ok(_) => {
+                        router.store(OscRouter::for_avatar(&config.cfg, avatar.get().as_deref().map(String::as_str)));
+                        forwarder.store(Arc::new(OscForwarder::new(&config.cfg.general.osc, &listening)));
+
+                        info!("Reloaded the config");
                     }
-                };
+                    Err(err) => {
+                        warn!("Couldn't reload the config, keeping the previous one: {}", err);
+                    }
+                }
             }
         }
     })
 }
 
+/// Logs the configured parameters each avatar VRChat has a config for is missing or has with the wrong type.
+/// Returns false when there were any, or the configs couldn't be read.
+fn report_avatar_configs(cfg: &ConfigFile, startup: bool) -> bool {
+    let dir = match osc_dir(&cfg.general.vrchat) {
+        Some(dir) => dir,
+        None => {
+            warn!("Couldn't find VRChat's OSC folder, set osc_dir under [general.vrchat]");
+            return false;
+        }
+    };
+
+    // Most likely VRChat isn't installed here, no need to mention it every start.
+    if startup && cfg.general.vrchat.osc_dir.is_empty() && !dir.exists() {
+        return true;
+    }
+
+    let reports = match check_avatars(cfg, &dir) {
+        Ok(reports) => reports,
+        Err(err) => {
+            warn!("Couldn't check the avatars in {}: {}", dir.display(), err);
+            return false;
+        }
+    };
+
+    let mut ok = true;
+
+    for report in &reports {
+        if report.problems.is_empty() {
+            if !startup {
+                info!("{} ({}) has every parameter", report.name, report.id);
+            }
+
+            continue;
+        }
+
+        ok = false;
+
+        for problem in &report.problems {
+            warn!("{} ({}): {}", report.name, report.id, problem);
+        }
+    }
+
+    if !startup {
+        info!("Checked {} avatars in {}", reports.len(), dir.display());
+    }
+
+    ok
+}
+
 #[tokio::main]
 async fn main() {
     SimpleLogger::new().with_level(LevelFilter::Info).env().with_colors(true).init().unwrap();
@@ -265,201 +333,380 @@ async fn main() {
 
     let cfg: Config<ConfigFile> = Config::new(PathBuf::from("config.toml"));
 
-    let sock = Arc::new(UdpSocket::bind(&cfg.cfg.general.osc.host_address).await.unwrap());
+    // `spotify-osc auth [profile]` logs in from the terminal and exits, for machines the browser can't reach.
+    if std::env::args().nth(1).as_deref() == Some("auth") {
+        let profile = std::env::args().nth(2).unwrap_or_else(|| cfg.cfg.spotify.profiles()[0].name.to_string());
 
-    let config = Arc::new(Mutex::new(cfg));
+        if cfg.cfg.spotify.profile(&profile).is_none() {
+            error!("There's no Spotify profile named {}", profile);
 
-    let spotify = Arc::new(Mutex::new(Spotify::new(client.clone(), config.clone())));
+            std::process::exit(1);
+        }
 
-    {
-        let spotify = spotify.clone();
+        let mut profiles = SpotifyProfiles::new(client, Arc::new(Mutex::new(cfg))).await;
+        let spotify = profiles.get_mut(&profile).unwrap();
 
-        let mut spotify = spotify.lock().await;
+        let stdin = BufReader::new(tokio::io::stdin());
 
-        match spotify.authenticate().await {
+        match headless_auth(spotify, &Authorizations::default(), stdin, &mut tokio::io::stdout()).await {
             Ok(_) => {
-                info!("Spotify authenticated successfully!");
+                info!("You're now authenticated as {}, the token has been saved.", profile);
             }
             Err(err) => {
-                match err {
-                    SpotifyAuthError::FAILED => {
-                        error!("Something went wrong while authenticating...");
+                error!("Couldn't log in to Spotify: {}", err);
+
+                std::process::exit(1);
+            }
+        }
+
+        return;
+    }
+
+    // `spotify-osc check-avatars` checks the parameters against VRChat's avatar configs and exits.
+    if std::env::args().nth(1).as_deref() == Some("check-avatars") {
+        if !report_avatar_configs(&cfg.cfg, false) {
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    if cfg.cfg.general.vrchat.check_on_startup {
+        report_avatar_configs(&cfg.cfg, true);
+    }
+
+    let oscquery = cfg.cfg.general.oscquery.clone();
+
+    // With OSCQuery any free port will do, VRChat finds it over mDNS.
+    let osc_address = match (oscquery.enabled, cfg.cfg.general.osc.host_address.rsplit_once(':')) {
+        (true, Some((host, _))) => format!("{}:0", host),
+        _ => String::from(&cfg.cfg.general.osc.host_address)
+    };
+
+    let sock = Arc::new(UdpSocket::bind(&osc_address).await.unwrap());
+
+    let sender = Arc::new(OscSender::new(sock.clone()));
+
+    // Packets from UDP and TCP go through the same dispatch.
+    let (packets_tx, mut packets) = mpsc::channel(64);
+
+    spawn_udp_receiver(sock.clone(), packets_tx.clone());
+
+    // Where packets actually arrive, with OSCQuery the port isn't the configured one.
+    let mut listening = vec![(OscTransport::Udp, sock.local_addr().unwrap())];
+
+    if !cfg.cfg.general.osc.tcp_host_address.is_empty() {
+        match OscTcpListener::bind(&cfg.cfg.general.osc.tcp_host_address).await {
+            Ok(listener) => {
+                let address = listener.local_addr().unwrap();
+
+                info!("Receiving OSC over TCP on {}", address);
+
+                listening.push((OscTransport::Tcp, address));
+
+                listener.spawn(packets_tx.clone());
+            }
+            Err(err) => {
+                error!("Couldn't listen for OSC over TCP on {}: {}", cfg.cfg.general.osc.tcp_host_address, err);
+            }
+        }
+    }
+
+    let backend = cfg.cfg.general.backend;
+
+    let config = Arc::new(Mutex::new(cfg));
+
+    let spotify = Arc::new(Mutex::new(SpotifyProfiles::new(client.clone(), config.clone()).await));
+
+    let source: SharedMediaSource = match backend {
+        MediaBackend::Spotify => {
+            for spotify in spotify.lock().await.iter_mut() {
+                let profile = spotify.profile().unwrap_or_default().to_string();
+
+                spotify.tokens().spawn_refresher();
+
+                match spotify.authenticate().await {
+                    Ok(_) => {
+                        info!("Spotify authenticated successfully as {}!", profile);
+
+                        let granted = spotify.tokens().scope().await.unwrap_or_default();
+
+                        let config = config.lock().await;
+                        let missing = Scopes::required_for(&config.cfg).missing(&Scopes::parse(&granted));
+
+                        if !missing.is_empty() {
+                            warn!("The Spotify login of {} is missing permissions needed by the enabled features ({}), visit {}?profile={} to grant them.",
+                                  profile, missing, config.cfg.get_setup_url(), profile);
+                        }
                     }
-                    SpotifyAuthError::ConfigNotInitialized => {
-                        warn!("It appears that you haven't initialized spotify before, don't panic, just make sure to follow the initial setup instructions.");
+                    Err(SpotifyAuthError::ConfigNotInitialized) => {
+                        warn!("It appears that you haven't initialized spotify for {} before, don't panic, just make sure to follow the initial setup instructions.", profile);
+                    }
+                    Err(err) => {
+                        error!("Something went wrong while authenticating {}: {}", profile, err);
                     }
-                    _ => {}
                 }
             }
+
+            spotify.clone()
         }
-    }
+        #[cfg(target_os = "linux")]
+        MediaBackend::Mpris => {
+            info!("Using MPRIS player backend");
+
+            Arc::new(Mutex::new(Mpris::new(config.clone())))
+        }
+        #[cfg(not(target_os = "linux"))]
+        MediaBackend::Mpris => {
+            error!("The MPRIS backend is only available on Linux, falling back to Spotify");
+
+            spotify.clone()
+        }
+        MediaBackend::Mpd => {
+            info!("Using MPD player backend");
+
+            Arc::new(Mutex::new(Mpd::new(config.clone())))
+        }
+    };
+
+    let router = Arc::new(SharedRouter::new(OscRouter::new(&config.lock().await.cfg)));
+
+    let forwarder = Arc::new(ArcSwap::from_pointee(OscForwarder::new(&config.lock().await.cfg.general.osc, &listening)));
+
+    let avatar = Arc::new(CurrentAvatar::new());
+
+    let vrchat = Arc::new(VrchatDiscovery::new());
+
+    task_watch_config(config.clone(), router.clone(), forwarder.clone(), avatar.clone(), listening.clone());
+
+    let avatar_changes = AvatarChanges::spawn(config.clone(), router.clone(), avatar.clone(), vrchat.clone(), client.clone());
 
     tokio::task::spawn({
-        let sock = sock.clone();
+        let sender = sender.clone();
+        let source = source.clone();
+        let router = router.clone();
+        let forwarder = forwarder.clone();
         let spotify = spotify.clone();
-        let config = config.clone();
 
         let spotify_volume = Arc::new(Mutex::new((0_f32, 0_f32)));
         let spotify_volume_task_active = Arc::new(Mutex::new(false));
 
-        let _ = task_set_spotify_volume(spotify.clone(), spotify_volume.clone(), spotify_volume_task_active.clone());
+        let buttons = Arc::new(Mutex::new(Buttons::new()));
+
+        task_set_spotify_volume(source.clone(), spotify_volume.clone(), spotify_volume_task_active.clone());
 
         async move {
-            let mut buf = [0u8; rosc::decoder::MTU];
+            while let Some((buf, from)) = packets.recv().await {
+                let forwarder = forwarder.load();
 
-            loop {
-                match sock.recv_from(&mut buf).await {
-                    Ok((size, _)) => {
-                        let (_, packet) = rosc::decoder::decode_udp(&buf[..size]).unwrap();
-
-                        match packet {
-                            OscPacket::Message(msg) => {
-                                let config = config.lock().await;
-                                let address = msg.addr.to_string();
-
-                                if address.eq(&config.cfg.parameters.spotify_play) {
-                                    let msg = msg.args[0].to_owned();
-
-                                    match msg.bool() {
-                                        None => {}
-                                        Some(res) => {
-                                            if res {
-                                                let _ = task_set_spotify_playback_play(spotify.clone());
-                                            }
-                                        }
-                                    }
+                let messages = match decode_messages(&buf) {
+                    Ok(messages) => messages,
+                    Err(err) => {
+                        warn!("Skipping malformed OSC packet from {}: {}", from, err);
+
+                        // Whoever it's meant for may still understand it.
+                        forwarder.forward(sender.as_ref(), &buf, false).await;
+                        continue;
+                    }
+                };
+
+                let router = router.load();
+
+                if forwarder.is_enabled() {
+                    let matched = messages.iter().any(|msg| router.handles(&msg.addr));
+
+                    forwarder.forward(sender.as_ref(), &buf, matched).await;
+                }
+
+                for msg in messages {
+                    // Messages without arguments carry nothing to act on.
+                    let arg = match msg.args.first() {
+                        Some(arg) => arg,
+                        None => continue
+                    };
+
+                    let coercion = router.coercion(&msg.addr);
+                    let actions = router.route(&msg.addr);
+
+                    if actions.iter().any(OscAction::is_button) {
+                        if let Some(pressed) = coercion.trigger(arg) {
+                            let event = buttons.lock().await.update(&msg.addr, pressed, router.debounce(), Instant::now());
+                            let long_press = router.long_press(&msg.addr);
+
+                            // With a long press action the tap only counts once the button is released early enough.
+                            let tapped = match (event, long_press) {
+                                (Some(ButtonEvent::Pressed { press }), Some(long_press)) => {
+                                    task_hold_button(buttons.clone(), source.clone(), router.clone(), String::from(&msg.addr), press, long_press);
+                                    false
                                 }
-                                if address.eq(&config.cfg.parameters.spotify_stop) {
-                                    let msg = msg.args[0].to_owned();
-
-                                    match msg.bool() {
-                                        None => {}
-                                        Some(res) => {
-                                            if res {
-                                                let _ = task_set_spotify_playback_pause(spotify.clone());
-                                            }
-                                        }
-                                    }
+                                (Some(ButtonEvent::Pressed { .. }), None) => true,
+                                (Some(ButtonEvent::Released { long_pressed }), Some(_)) => !long_pressed,
+                                _ => false
+                            };
+
+                            if tapped {
+                                for action in actions.iter().filter(|action| action.is_button()) {
+                                    trigger_action(source.clone(), *action, router.seek_step_ms());
                                 }
-                                if address.eq(&config.cfg.parameters.spotify_next) {
-                                    let msg = msg.args[0].to_owned();
-
-                                    match msg.bool() {
-                                        None => {}
-                                        Some(res) => {
-                                            if res {
-                                                let _ = task_set_spotify_playback_next(spotify.clone());
-                                            }
-                                        }
-                                    }
+                            }
+                        }
+                    }
+
+                    for action in actions {
+                        match action {
+                            OscAction::SwitchProfile => {
+                                if let Some(index) = coercion.index(arg) {
+                                    task_switch_spotify_profile(spotify.clone(), index);
                                 }
-                                if address.eq(&config.cfg.parameters.spotify_previous) {
-                                    let msg = msg.args[0].to_owned();
-
-                                    match msg.bool() {
-                                        None => {}
-                                        Some(res) => {
-                                            if res {
-                                                let _ = task_set_spotify_playback_previous(spotify.clone());
-                                            }
-                                        }
+                            }
+                            OscAction::Volume => {
+                                if let Some(val) = coercion.volume(arg) {
+                                    {
+                                        let mut spotify_volume = spotify_volume.lock().await;
+                                        spotify_volume.1 = val;
                                     }
-                                }
-                                if address.eq(&config.cfg.parameters.spotify_volume) {
-                                    let msg = msg.args[0].to_owned();
-
-                                    match msg.float() {
-                                        None => {}
-                                        Some(val) => {
-                                            {
-                                                let mut spotify_volume = spotify_volume.lock().await;
-                                                spotify_volume.1 = val;
-                                            }
-
-                                            {
-                                                let mut spotify_volume_task_active = spotify_volume_task_active.lock().await;
-                                                *spotify_volume_task_active = true;
-                                            }
-
-                                        }
+
+                                    {
+                                        let mut spotify_volume_task_active = spotify_volume_task_active.lock().await;
+                                        *spotify_volume_task_active = true;
                                     }
                                 }
                             }
+                            OscAction::AvatarChange => {
+                                if let OscType::String(id) = arg {
+                                    avatar_changes.change(id);
+                                }
+                            }
                             _ => {}
                         }
                     }
-                    Err(_) => {
-
-                    }
                 }
             }
         }
     });
 
     tokio::task::spawn({
-        let sock = sock.clone();
+        let sender = sender.clone();
         let config = config.clone();
-        let spotify = spotify.clone();
+        let source = source.clone();
+        let vrchat = vrchat.clone();
+        let avatar = avatar.clone();
 
         async move {
             let mut chatbox = Chatbox::new();
+            let mut last_error: Option<String> = None;
+            let mut last_rate_limited = false;
+
+            let changes = source.lock().await.changes();
 
             loop {
-                tokio::time::sleep(Duration::from_secs(4)).await;
+                // Sources that push changes are still polled so the seek position keeps moving.
+                tokio::select! {
+                    _ = source_changed(&changes) => {}
+                    _ = avatar.changed() => {
+                        // The new avatar's parameters start out empty, so everything is sent again.
+                        chatbox = Chatbox::new();
+                    }
+                    _ = tokio::time::sleep(Duration::from_secs(4)) => {}
+                }
                 {
-                    let mut spotify = spotify.lock().await;
+                    let mut source = source.lock().await;
 
-                    match spotify.now_playing().await {
-                        Ok(res) => {
-                            let config = config.lock().await;
+                    let res = source.now_playing().await;
 
-                            match res {
-                                Some(res) => {
+                    let rate_limited = source.rate_limited();
 
-                                    let spotify_playing_buff = encode_packet(String::from(&config.cfg.parameters.spotify_playing), vec![OscType::Bool(res.is_playing)]).unwrap();
+                    if rate_limited != last_rate_limited {
+                        let config = config.lock().await;
 
-                                    let seek = res.progress_ms as f32 / res.item.duration_ms as f32;
-                                    let spotify_seek_buff = encode_packet(String::from(&config.cfg.parameters.spotify_seek), vec![OscType::Float(seek)]).unwrap();
+                        let targets = OscTargets::new(&config.cfg.general.osc).with_vrchat(vrchat.get());
 
-                                    send_to_delay(&sock, &spotify_playing_buff, &config.cfg.general.osc.client_address, Duration::from_millis(20)).await;
-                                    send_to_delay(&sock, &spotify_seek_buff, &config.cfg.general.osc.client_address, Duration::from_millis(20)).await;
+                        let parameters = config.cfg.parameters_for(avatar.get().as_deref().map(String::as_str));
 
-                                    if chatbox.changed(&res.item.id) {
-                                        chatbox.update(&res.item.artists, &res.item.name, &res.item.id);
+                        targets.send(sender.as_ref(), OscOutput::RateLimited, &parameters.spotify_rate_limited, vec![OscType::Bool(rate_limited)], Duration::from_millis(20)).await;
 
-                                        let spotify_chatbox_buff = encode_packet(String::from(&config.cfg.parameters.spotify_chatbox),
-                                                                                 vec![OscType::String(format!("[Spotify] Playing: {} - {}", chatbox.artist, chatbox.song)), OscType::Bool(true)]).unwrap();
-                                        send_to_delay(&sock, &spotify_chatbox_buff, &config.cfg.general.osc.client_address, Duration::from_millis(20)).await;
-                                    }
-                                }
-                                None => {
-                                    let spotify_playing_buff = encode_packet(String::from(&config.cfg.parameters.spotify_playing), vec![OscType::Bool(false)]).unwrap();
-                                    let spotify_seek_buff = encode_packet(String::from(&config.cfg.parameters.spotify_seek), vec![OscType::Float(0_f32)]).unwrap();
+                        last_rate_limited = rate_limited;
+                    }
 
-                                    send_to_delay(&sock, &spotify_playing_buff, &config.cfg.general.osc.client_address, Duration::from_millis(20)).await;
-                                    send_to_delay(&sock, &spotify_seek_buff, &config.cfg.general.osc.client_address, Duration::from_millis(20)).await;
-                                }
+                    let res = match res {
+                        Ok(res) => {
+                            last_error = None;
+                            res
+                        }
+                        Err(MediaError::Spotify(SpotifyAuthError::NotInitialized)) => {
+                            continue;
+                        }
+                        Err(err) => {
+                            // Only log when the failure changes, so a dead connection doesn't flood the log.
+                            let message = err.to_string();
+
+                            if last_error.as_ref() != Some(&message) {
+                                warn!("Couldn't read what's playing: {}", message);
+                                last_error = Some(message);
+                            }
+
+                            continue;
+                        }
+                    };
+
+                    let config = config.lock().await;
+                    let targets = OscTargets::new(&config.cfg.general.osc).with_vrchat(vrchat.get());
+                    let parameters = config.cfg.parameters_for(avatar.get().as_deref().map(String::as_str));
+
+                    match res {
+                        Some(res) => {
+                            targets.send(sender.as_ref(), OscOutput::Playing, &parameters.spotify_playing, vec![OscType::Bool(res.is_playing)], Duration::from_millis(20)).await;
+                            targets.send(sender.as_ref(), OscOutput::Seek, &parameters.spotify_seek, vec![OscType::Float(res.seek())], Duration::from_millis(20)).await;
+
+                            if chatbox.changed(&res.id) {
+                                chatbox.update(&res);
+
+                                targets.send(sender.as_ref(), OscOutput::Chatbox, &parameters.spotify_chatbox,
+                                             vec![OscType::String(format!("[Spotify] Playing: {} - {}", chatbox.artist, chatbox.song)), OscType::Bool(true)], Duration::from_millis(20)).await;
                             }
                         }
-                        Err(_) => {}
+                        None => {
+                            targets.send(sender.as_ref(), OscOutput::Playing, &parameters.spotify_playing, vec![OscType::Bool(false)], Duration::from_millis(20)).await;
+                            targets.send(sender.as_ref(), OscOutput::Seek, &parameters.spotify_seek, vec![OscType::Float(0_f32)], Duration::from_millis(20)).await;
+                        }
                     }
                 }
             }
         }
     });
 
+    let _oscquery = if oscquery.enabled {
+        let osc = sock.local_addr().unwrap();
+
+        vrchat.spawn(client.clone(), config.clone(), avatar.clone());
+
+        match OscQuery::start(config.clone(), avatar.clone(), &oscquery.name, osc) {
+            Ok(service) => {
+                info!("Advertising {} over OSCQuery, receiving OSC on port {} and serving queries on port {}", oscquery.name, osc.port(), service.http_port());
+
+                Some(service)
+            }
+            Err(err) => {
+                error!("OSCQuery isn't available: {}", err);
+
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     let cfg = config.clone();
     let cfg = cfg.lock().await;
 
     let http_server = HttpServer::new({
-        let client = client.clone();
         let config = config.clone();
         let spotify = spotify.clone();
 
         let web_data = WebData {
-            client: client.clone(),
             config,
-            spotify
+            spotify,
+            authorizations: Arc::new(Authorizations::default())
         };
 
         move || {
@@ -467,6 +714,8 @@ async fn main() {
                 .app_data(web::Data::new(web_data.clone()))
                 .service(spotify_callback)
                 .service(spotify_setup)
+                .service(spotify_profiles)
+                .service(spotify_profile)
         }
     })
         .bind(cfg.cfg.get_webserver_address())
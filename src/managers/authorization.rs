@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use crate::entities::config::ConfigFile;
+use crate::entities::scope::Scopes;
+use crate::utils::pkce::Pkce;
+
+/// How long a `/setup` attempt stays valid.
+pub const AUTHORIZATION_TTL: Duration = Duration::from_secs(10 * 60);
+
+const STATE_LENGTH: usize = 32;
+
+/// What `/callback` needs to finish an attempt.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Authorization {
+    /// PKCE code verifier, when the PKCE flow is used.
+    pub verifier: Option<String>,
+    /// Profile the tokens are for, the active one when `None`.
+    pub profile: Option<String>
+}
+
+struct PendingAuthorization {
+    authorization: Authorization,
+    started: Instant
+}
+
+/// Authorizations started from `/setup`, keyed by their OAuth `state`.
+///
+/// A `state` can only be used once, so a callback can't be replayed or forged
+/// by a page that never went through `/setup`.
+pub struct Authorizations {
+    pending: std::sync::Mutex<HashMap<String, PendingAuthorization>>,
+    ttl: Duration
+}
+
+impl Authorizations {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            pending: std::sync::Mutex::new(HashMap::new()),
+            ttl
+        }
+    }
+
+    /// Records a new attempt and returns the `state` to send along with it.
+    pub fn start(&self, authorization: Authorization) -> String {
+        let state: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(STATE_LENGTH)
+            .map(char::from)
+            .collect();
+
+        let mut pending = self.pending.lock().unwrap();
+
+        pending.retain(|_, authorization| authorization.started.elapsed() < self.ttl);
+        pending.insert(String::from(&state), PendingAuthorization {
+            authorization,
+            started: Instant::now()
+        });
+
+        state
+    }
+
+    /// Starts an attempt for `profile` and builds the Spotify authorize URL for it, returning the URL and its `state`.
+    pub fn authorize_url(&self, cfg: &ConfigFile, profile: Option<&str>) -> (String, String) {
+        let mut query = vec![
+            ("response_type", "code".to_string()),
+            ("client_id", String::from(&cfg.spotify.client_id)),
+            ("scope", Scopes::required_for(cfg).to_string()),
+            ("redirect_uri", String::from(&cfg.spotify.callback_url))
+        ];
+
+        let mut verifier = None;
+
+        if cfg.uses_pkce() {
+            let pkce = Pkce::new();
+
+            query.push(("code_challenge_method", "S256".to_string()));
+            query.push(("code_challenge", pkce.challenge));
+
+            verifier = Some(pkce.verifier);
+        }
+
+        // With several accounts, let the user pick another one than the browser is logged in with.
+        if cfg.spotify.profiles().len() > 1 {
+            query.push(("show_dialog", "true".to_string()));
+        }
+
+        let state = self.start(Authorization {
+            verifier,
+            profile: profile.map(|profile| profile.to_string())
+        });
+
+        query.push(("state", String::from(&state)));
+
+        let url = format!("{}/authorize?{}", &cfg.spotify.accounts_url, serde_urlencoded::to_string(query).unwrap());
+
+        (url, state)
+    }
+
+    /// Consumes the attempt `state` belongs to.
+    pub fn finish(&self, state: Option<&str>) -> Result<Authorization, AuthorizationError> {
+        let state = state.ok_or(AuthorizationError::MissingState)?;
+
+        let pending = self.pending.lock().unwrap()
+            .remove(state)
+            .ok_or(AuthorizationError::UnknownState)?;
+
+        if pending.started.elapsed() >= self.ttl {
+            return Err(AuthorizationError::Expired)
+        }
+
+        Ok(pending.authorization)
+    }
+
+    /// Drops the attempt `state` belongs to, if any.
+    pub fn cancel(&self, state: Option<&str>) {
+        if let Some(state) = state {
+            self.pending.lock().unwrap().remove(state);
+        }
+    }
+}
+
+impl Default for Authorizations {
+    fn default() -> Self {
+        Self::new(AUTHORIZATION_TTL)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AuthorizationError {
+    /// The callback didn't carry a `state` at all.
+    MissingState,
+    /// The `state` doesn't belong to any attempt started here, or was already used.
+    UnknownState,
+    /// The attempt took longer than the allowed time.
+    Expired
+}
+
+impl Display for AuthorizationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthorizationError::MissingState => write!(f, "the callback is missing its state parameter"),
+            AuthorizationError::UnknownState => write!(f, "the callback's state doesn't match any login started from /setup"),
+            AuthorizationError::Expired => write!(f, "the login took too long and expired")
+        }
+    }
+}
+
+impl std::error::Error for AuthorizationError {}
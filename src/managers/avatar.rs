@@ -0,0 +1,110 @@
+use std::sync::Arc;
+use arc_swap::ArcSwapOption;
+use log::{info, warn};
+use reqwest::Client;
+use tokio::sync::{watch, Mutex, Notify};
+use crate::config::config::Config;
+use crate::entities::config::ConfigFile;
+use crate::managers::router::{OscRouter, SharedRouter};
+use crate::managers::vrchat::{warn_missing_parameters, VrchatDiscovery};
+
+/// The avatar VRChat last reported through `/avatar/change`.
+#[derive(Default)]
+pub struct CurrentAvatar {
+    id: ArcSwapOption<String>,
+    changed: Notify
+}
+
+impl CurrentAvatar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self) -> Option<Arc<String>> {
+        self.id.load_full()
+    }
+
+    /// Records `id` as the current avatar, returns false when it already was.
+    pub fn set(&self, id: &str) -> bool {
+        if self.get().is_some_and(|current| *current == id) {
+            return false;
+        }
+
+        self.id.store(Some(Arc::new(id.to_string())));
+        self.changed.notify_one();
+
+        true
+    }
+
+    /// Resolves once the avatar changes, a change made before waiting is not missed.
+    pub async fn changed(&self) {
+        self.changed.notified().await
+    }
+}
+
+/// Switches to the parameters of the avatar VRChat changed to, one change at a time.
+/// A change reported while the previous one is still being applied replaces it, so an older avatar never wins.
+pub struct AvatarChanges {
+    latest: watch::Sender<Option<String>>
+}
+
+impl AvatarChanges {
+    pub fn spawn(config: Arc<Mutex<Config<ConfigFile>>>, router: Arc<SharedRouter>, avatar: Arc<CurrentAvatar>,
+                 vrchat: Arc<VrchatDiscovery>, http: Arc<Client>) -> Self {
+        let (latest, mut changes) = watch::channel::<Option<String>>(None);
+
+        tokio::task::spawn({
+            async move {
+                // Kept across changes that get replaced, VRChat's copy is only reported as new once.
+                let mut refreshed = false;
+
+                while changes.changed().await.is_ok() {
+                    let id = match changes.borrow_and_update().clone() {
+                        Some(id) => id,
+                        None => continue
+                    };
+
+                    if avatar.get().is_some_and(|current| *current == id) {
+                        continue;
+                    }
+
+                    // VRChat's copy of the avatar's parameters is read first, so the state sent for the new avatar isn't checked against the old one.
+                    refreshed |= match vrchat.refresh_avatar(&http).await {
+                        Ok(refreshed) => refreshed,
+                        Err(err) => {
+                            warn!("Couldn't read the avatar's parameters from VRChat: {}", err);
+                            false
+                        }
+                    };
+
+                    if changes.has_changed().unwrap_or(false) || !avatar.set(&id) {
+                        continue;
+                    }
+
+                    {
+                        let config = config.lock().await;
+
+                        if config.cfg.avatar(&id).is_some() {
+                            info!("Switched to the parameters for avatar {}", id);
+                        } else {
+                            info!("No parameters for avatar {}, using the defaults", id);
+                        }
+
+                        router.store(OscRouter::for_avatar(&config.cfg, Some(&id)));
+                    }
+
+                    if let (true, Some(vrchat)) = (std::mem::take(&mut refreshed), vrchat.get()) {
+                        warn_missing_parameters(&vrchat, &config, &avatar).await;
+                    }
+                }
+            }
+        });
+
+        Self { latest }
+    }
+
+    /// Queues a switch to `id`, replacing any change not applied yet.
+    pub fn change(&self, id: &str) {
+        self.latest.send_replace(Some(id.to_string()));
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use log::warn;
+use crate::entities::config::{ArgumentType, ConfigFile, ConfigFileVrchat};
+use crate::entities::vrchat::{VrchatAvatarConfig, VrchatParameterEndpoint, VrchatParameterType};
+use crate::managers::router::OscRouter;
+use crate::utils::osc::matches_pattern;
+
+/// Only these addresses are avatar parameters, the rest (like the chatbox) aren't listed in the avatar configs.
+const AVATAR_PARAMETERS: &str = "/avatar/parameters/";
+
+/// VRChat's `OSC` folder in the user's profile, `None` when there's no profile to look in.
+pub fn default_osc_dir() -> Option<PathBuf> {
+    let profile = std::env::var_os("USERPROFILE").or_else(|| std::env::var_os("HOME"))?;
+
+    Some(PathBuf::from(profile).join("AppData").join("LocalLow").join("VRChat").join("VRChat").join("OSC"))
+}
+
+/// The configured `OSC` folder, VRChat's default one when left empty.
+pub fn osc_dir(cfg: &ConfigFileVrchat) -> Option<PathBuf> {
+    if cfg.osc_dir.is_empty() {
+        default_osc_dir()
+    } else {
+        Some(PathBuf::from(&cfg.osc_dir))
+    }
+}
+
+pub fn read_avatar_config(path: &Path) -> Result<VrchatAvatarConfig, AvatarConfigError> {
+    let contents = std::fs::read_to_string(path).map_err(AvatarConfigError::Io)?;
+
+    // VRChat writes the files with a byte order mark.
+    serde_json::from_str(contents.trim_start_matches('\u{feff}')).map_err(AvatarConfigError::Json)
+}
+
+/// Every avatar config under `dir/usr_*/Avatars`, by avatar ID.
+/// Files that can't be read are skipped with a warning, an avatar worn by several accounts is read once.
+pub fn read_avatar_configs(dir: &Path) -> Result<BTreeMap<String, VrchatAvatarConfig>, AvatarConfigError> {
+    let mut avatars = BTreeMap::new();
+
+    for user in std::fs::read_dir(dir).map_err(AvatarConfigError::Io)? {
+        let user = user.map_err(AvatarConfigError::Io)?;
+
+        if !user.file_name().to_string_lossy().starts_with("usr_") {
+            continue;
+        }
+
+        let files = match std::fs::read_dir(user.path().join("Avatars")) {
+            Ok(files) => files,
+            Err(_) => continue
+        };
+
+        for file in files.flatten() {
+            let name = file.file_name().to_string_lossy().to_string();
+
+            if !name.starts_with("avtr_") || !name.ends_with(".json") {
+                continue;
+            }
+
+            match read_avatar_config(&file.path()) {
+                Ok(avatar) => {
+                    avatars.entry(String::from(&avatar.id)).or_insert(avatar);
+                }
+                Err(err) => {
+                    warn!("Skipping {}: {}", file.path().display(), err);
+                }
+            }
+        }
+    }
+
+    Ok(avatars)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProblemKind {
+    Missing,
+    Mistyped {
+        expected: VrchatParameterType,
+        found: VrchatParameterType
+    }
+}
+
+/// A configured parameter the avatar can't be driven with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterProblem {
+    /// Name of the setting in `[parameters]`.
+    pub parameter: &'static str,
+    pub address: String,
+    pub kind: ProblemKind
+}
+
+impl Display for ParameterProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ProblemKind::Missing => write!(f, "{} ({}) is missing", self.parameter, self.address),
+            ProblemKind::Mistyped { expected, found } => write!(f, "{} ({}) is {} but should be {}", self.parameter, self.address, found, expected)
+        }
+    }
+}
+
+/// Compares the parameters configured for `avatar` with the ones it has.
+/// Parameters the app sends must be settable on the avatar, the ones it receives must be sent by it.
+pub fn check_avatar(cfg: &ConfigFile, avatar: &VrchatAvatarConfig) -> Vec<ParameterProblem> {
+    let parameters = cfg.parameters_for(Some(&avatar.id));
+    let router = OscRouter::for_avatar(cfg, Some(&avatar.id));
+
+    let sent = [
+        ("spotify_playing", &parameters.spotify_playing, VrchatParameterType::Bool),
+        ("spotify_seek", &parameters.spotify_seek, VrchatParameterType::Float),
+        ("spotify_rate_limited", &parameters.spotify_rate_limited, VrchatParameterType::Bool)
+    ];
+
+    let received = [
+        ("spotify_play", &parameters.spotify_play, VrchatParameterType::Bool),
+        ("spotify_stop", &parameters.spotify_stop, VrchatParameterType::Bool),
+        ("spotify_next", &parameters.spotify_next, VrchatParameterType::Bool),
+        ("spotify_previous", &parameters.spotify_previous, VrchatParameterType::Bool),
+        ("spotify_volume", &parameters.spotify_volume, VrchatParameterType::Float),
+        ("spotify_profile", &parameters.spotify_profile, VrchatParameterType::Int)
+    ];
+
+    let mut problems = vec![];
+
+    for (parameter, address, expected) in sent {
+        let endpoints = avatar.parameters.iter().filter_map(|p| p.input.as_ref());
+
+        problems.extend(check_parameter(parameter, address, Some(expected), endpoints));
+    }
+
+    for (parameter, address, expected) in received {
+        // An override picks the type the app accepts, a string one can't come from an avatar parameter.
+        let expected = match router.coercion(address).argument_type {
+            ArgumentType::Auto => Some(expected),
+            ArgumentType::Bool => Some(VrchatParameterType::Bool),
+            ArgumentType::Int => Some(VrchatParameterType::Int),
+            ArgumentType::Float => Some(VrchatParameterType::Float),
+            ArgumentType::String => None
+        };
+
+        let endpoints = avatar.parameters.iter().filter_map(|p| p.output.as_ref());
+
+        problems.extend(check_parameter(parameter, address, expected, endpoints));
+    }
+
+    problems
+}
+
+fn check_parameter<'a>(parameter: &'static str, address: &str, expected: Option<VrchatParameterType>,
+                       endpoints: impl Iterator<Item = &'a VrchatParameterEndpoint>) -> Option<ParameterProblem> {
+    if !address.starts_with(AVATAR_PARAMETERS) {
+        return None;
+    }
+
+    let matching: Vec<&VrchatParameterEndpoint> = endpoints
+        .filter(|endpoint| matches_pattern(address, &endpoint.address))
+        .collect();
+
+    let kind = match (matching.first(), expected) {
+        (None, _) => ProblemKind::Missing,
+        (Some(_), None) => return None,
+        (Some(_), Some(expected)) => {
+            let found = matching.iter().find(|endpoint| endpoint.parameter_type != expected)?.parameter_type;
+
+            ProblemKind::Mistyped { expected, found }
+        }
+    };
+
+    Some(ParameterProblem {
+        parameter,
+        address: address.to_string(),
+        kind
+    })
+}
+
+/// The problems found on one avatar.
+pub struct AvatarReport {
+    pub id: String,
+    pub name: String,
+    pub problems: Vec<ParameterProblem>
+}
+
+/// Checks every avatar with a config in `dir`.
+pub fn check_avatars(cfg: &ConfigFile, dir: &Path) -> Result<Vec<AvatarReport>, AvatarConfigError> {
+    let avatars = read_avatar_configs(dir)?;
+
+    Ok(avatars.into_values()
+        .map(|avatar| AvatarReport {
+            problems: check_avatar(cfg, &avatar),
+            id: avatar.id,
+            name: avatar.name
+        })
+        .collect())
+}
+
+#[derive(Debug)]
+pub enum AvatarConfigError {
+    Io(std::io::Error),
+    Json(serde_json::Error)
+}
+
+impl Display for AvatarConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AvatarConfigError::Io(err) => write!(f, "couldn't read the avatar configs: {}", err),
+            AvatarConfigError::Json(err) => write!(f, "couldn't parse the avatar config: {}", err)
+        }
+    }
+}
+
+impl std::error::Error for AvatarConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AvatarConfigError::Io(err) => Some(err),
+            AvatarConfigError::Json(err) => Some(err)
+        }
+    }
+}
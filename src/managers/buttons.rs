@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonEvent {
+    /// The button went down, `press` identifies this press for [`Buttons::hold`].
+    Pressed { press: u64 },
+    /// The button went up, `long_pressed` when it was held long enough to run its long press action.
+    Released { long_pressed: bool }
+}
+
+#[derive(Default)]
+struct ButtonState {
+    pressed: bool,
+    /// The current press came too soon after the last one and is ignored, release included.
+    ignored: bool,
+    long_pressed: bool,
+    press: u64,
+    pressed_at: Option<Instant>
+}
+
+/// Turns the stream of button values into presses and releases, per address.
+/// Repeated values aren't edges, so a `true` resent by avatar sync doesn't press again.
+#[derive(Default)]
+pub struct Buttons {
+    states: HashMap<String, ButtonState>,
+    presses: u64
+}
+
+impl Buttons {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the button at `address` as `pressed`, returning an event when that changes its state.
+    /// Presses within `debounce` of the previous one are ignored.
+    pub fn update(&mut self, address: &str, pressed: bool, debounce: Duration, now: Instant) -> Option<ButtonEvent> {
+        let state = self.states.entry(address.to_string()).or_default();
+
+        if state.pressed == pressed {
+            return None
+        }
+
+        state.pressed = pressed;
+
+        if !pressed {
+            if state.ignored {
+                return None
+            }
+
+            return Some(ButtonEvent::Released { long_pressed: state.long_pressed })
+        }
+
+        if let Some(pressed_at) = state.pressed_at {
+            if now.saturating_duration_since(pressed_at) < debounce {
+                state.ignored = true;
+
+                return None
+            }
+        }
+
+        self.presses += 1;
+
+        state.ignored = false;
+        state.long_pressed = false;
+        state.press = self.presses;
+        state.pressed_at = Some(now);
+
+        Some(ButtonEvent::Pressed { press: self.presses })
+    }
+
+    /// Marks `press` as a long press if the button is still held down by it.
+    /// Returns whether it was, the long press action should only run then.
+    pub fn hold(&mut self, address: &str, press: u64) -> bool {
+        match self.states.get_mut(address) {
+            Some(state) if state.pressed && !state.ignored && state.press == press => {
+                state.long_pressed = true;
+
+                true
+            }
+            _ => false
+        }
+    }
+}
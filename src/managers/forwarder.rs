@@ -0,0 +1,77 @@
+use std::net::{SocketAddr, ToSocketAddrs};
+use log::warn;
+use crate::entities::config::{ConfigFileGeneralOsc, ForwardMode};
+use crate::managers::transport::{parse_address, OscSend, OscTransport};
+
+/// Relays received packets untouched, so bundles and argument types reach the other apps as VRChat sent them.
+#[derive(Default)]
+pub struct OscForwarder {
+    mode: ForwardMode,
+    addresses: Vec<String>
+}
+
+/// Whether packets sent to `address` would come back to one of the `listening` sockets.
+fn is_listening(address: &str, listening: &[(OscTransport, SocketAddr)]) -> bool {
+    let (transport, address) = parse_address(address);
+
+    let addresses: Vec<SocketAddr> = match address.to_socket_addrs() {
+        Ok(addresses) => addresses.collect(),
+        Err(_) => return false
+    };
+
+    listening.iter()
+        .filter(|(own_transport, _)| *own_transport == transport)
+        .any(|(_, own)| addresses.iter().any(|address| {
+            // A socket bound to every interface is reached on this machine through loopback.
+            address.port() == own.port()
+                && (address.ip() == own.ip() || (own.ip().is_unspecified() && (address.ip().is_loopback() || address.ip().is_unspecified())))
+        }))
+}
+
+impl OscForwarder {
+    /// `listening` are the sockets the app receives on, as bound rather than as configured.
+    pub fn new(osc: &ConfigFileGeneralOsc, listening: &[(OscTransport, SocketAddr)]) -> Self {
+        let addresses = osc.forward.addresses.iter()
+            .filter(|address| {
+                // Forwarding to ourselves would bounce every packet forever.
+                if is_listening(address, listening) {
+                    warn!("Not forwarding OSC to {}, that's where the app listens", address);
+                    return false
+                }
+
+                true
+            })
+            .map(String::from)
+            .collect();
+
+        Self {
+            mode: osc.forward.mode,
+            addresses
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.addresses.is_empty()
+    }
+
+    /// Whether a packet should be relayed, `matched` when any of its messages was routed to an action.
+    /// Bundles are relayed whole, so in `unmatched` mode one routed message keeps the whole bundle here.
+    pub fn wants(&self, matched: bool) -> bool {
+        match self.mode {
+            ForwardMode::All => true,
+            ForwardMode::Unmatched => !matched
+        }
+    }
+
+    pub async fn forward(&self, sock: &impl OscSend, buf: &[u8], matched: bool) {
+        if !self.wants(matched) {
+            return;
+        }
+
+        for address in &self.addresses {
+            if let Err(err) = sock.send_packet(buf, address).await {
+                warn!("Couldn't forward OSC to {}: {}", address, err);
+            }
+        }
+    }
+}
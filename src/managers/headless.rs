@@ -0,0 +1,126 @@
+use std::fmt::{Display, Formatter};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use crate::entities::spotify::SpotifyCallbackQuery;
+use crate::managers::authorization::{AuthorizationError, Authorizations};
+use crate::managers::spotify::{Spotify, SpotifyAuthError};
+
+/// What the user pasted back: the whole redirect URL, or just the code from it.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PastedRedirect {
+    Url(SpotifyCallbackQuery),
+    Code(String)
+}
+
+impl PastedRedirect {
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+
+        if input.is_empty() {
+            return None
+        }
+
+        match input.split_once('?') {
+            Some((_, query)) => {
+                let query = query.split('#').next().unwrap_or_default();
+
+                serde_urlencoded::from_str(query).ok().map(PastedRedirect::Url)
+            }
+            None if input.contains('/') || input.contains('=') => None,
+            None => Some(PastedRedirect::Code(input.to_string()))
+        }
+    }
+}
+
+/// Logs in without the web server: prints the authorize URL to `output`, reads the
+/// redirect URL or code from `input` and exchanges it like `/callback` would.
+pub async fn headless_auth<R, W>(spotify: &mut Spotify, authorizations: &Authorizations, input: R, output: &mut W) -> Result<(), HeadlessAuthError>
+    where R: AsyncBufRead + Unpin,
+          W: AsyncWrite + Unpin
+{
+    let (url, state) = {
+        let config = spotify.config();
+        let config = config.lock().await;
+
+        authorizations.authorize_url(&config.cfg, spotify.profile())
+    };
+
+    let prompt = format!("Open this URL in any browser and log in to Spotify:\n\n{}\n\n\
+                          The browser then gets redirected to a page that probably won't load, that's fine.\n\
+                          Paste the full address of that page (or just its code) here and press enter:\n", url);
+
+    output.write_all(prompt.as_bytes()).await.map_err(HeadlessAuthError::Io)?;
+    output.flush().await.map_err(HeadlessAuthError::Io)?;
+
+    let mut lines = input.lines();
+
+    let redirect = loop {
+        let line = lines.next_line().await
+            .map_err(HeadlessAuthError::Io)?
+            .ok_or(HeadlessAuthError::NoInput)?;
+
+        match PastedRedirect::parse(&line) {
+            Some(redirect) => break redirect,
+            None if line.trim().is_empty() => {}
+            None => {
+                output.write_all(b"That doesn't look like the redirect URL or a code, try again:\n").await.map_err(HeadlessAuthError::Io)?;
+                output.flush().await.map_err(HeadlessAuthError::Io)?;
+            }
+        }
+    };
+
+    let (code, verifier) = match redirect {
+        PastedRedirect::Url(query) => {
+            if let Some(err) = query.error {
+                authorizations.cancel(query.state.as_deref());
+
+                return Err(HeadlessAuthError::Denied(err))
+            }
+
+            let authorization = authorizations.finish(query.state.as_deref()).map_err(HeadlessAuthError::Authorization)?;
+
+            (query.code.ok_or(HeadlessAuthError::MissingCode)?, authorization.verifier)
+        }
+        // A bare code can't carry a state, it belongs to the attempt we just started.
+        PastedRedirect::Code(code) => (code, authorizations.finish(Some(&state)).map_err(HeadlessAuthError::Authorization)?.verifier)
+    };
+
+    spotify.init_credentials(&code, verifier).await.map_err(HeadlessAuthError::Spotify)
+}
+
+#[derive(Debug)]
+pub enum HeadlessAuthError {
+    Io(std::io::Error),
+    /// Input ended before anything was pasted.
+    NoInput,
+    /// Spotify redirected with an error, `access_denied` when the user cancelled.
+    Denied(String),
+    /// The pasted URL has no `code`.
+    MissingCode,
+    Authorization(AuthorizationError),
+    Spotify(SpotifyAuthError)
+}
+
+impl Display for HeadlessAuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeadlessAuthError::Io(err) => write!(f, "couldn't talk to the terminal: {}", err),
+            HeadlessAuthError::NoInput => write!(f, "nothing was pasted"),
+            HeadlessAuthError::Denied(err) if err == "access_denied" => write!(f, "the login was cancelled"),
+            HeadlessAuthError::Denied(err) => write!(f, "Spotify couldn't authorize the app ({})", err),
+            HeadlessAuthError::MissingCode => write!(f, "the pasted URL doesn't contain an authorization code"),
+            HeadlessAuthError::Authorization(err) => write!(f, "{}", err),
+            HeadlessAuthError::Spotify(err) => write!(f, "{}", err)
+        }
+    }
+}
+
+impl std::error::Error for HeadlessAuthError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HeadlessAuthError::Io(err) => Some(err),
+            HeadlessAuthError::Authorization(err) => Some(err),
+            HeadlessAuthError::Spotify(err) => Some(err),
+            _ => None
+        }
+    }
+}
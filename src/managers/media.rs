@@ -0,0 +1,103 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::sync::{Mutex, Notify};
+use crate::entities::media::{MediaDevice, NowPlaying};
+use crate::managers::spotify::SpotifyAuthError;
+
+pub type SharedMediaSource = Arc<Mutex<dyn MediaSource>>;
+
+/// A player the OSC loops can read from and control, independent of the backend behind it.
+#[async_trait]
+pub trait MediaSource: Send {
+    async fn now_playing(&mut self) -> Result<Option<NowPlaying>, MediaError>;
+
+    async fn play(&mut self) -> Result<(), MediaError>;
+
+    async fn pause(&mut self) -> Result<(), MediaError>;
+
+    async fn next(&mut self) -> Result<(), MediaError>;
+
+    async fn previous(&mut self) -> Result<(), MediaError>;
+
+    /// Sets the volume, `volume` ranges from 0 to 1.
+    async fn set_volume(&mut self, volume: f32) -> Result<(), MediaError>;
+
+    /// Jumps forwards or, with a negative offset, backwards in the current track.
+    async fn seek_by(&mut self, offset_ms: i64) -> Result<(), MediaError>;
+
+    async fn devices(&mut self) -> Result<Vec<MediaDevice>, MediaError>;
+
+    /// Notified whenever the player reports a change, sources without push updates are only polled.
+    fn changes(&mut self) -> Option<Arc<Notify>> {
+        None
+    }
+
+    /// Whether the backend is currently refusing requests because of rate limiting.
+    fn rate_limited(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug)]
+pub enum MediaError {
+    /// There's no player or device to send the command to.
+    NoDevice,
+    Io(std::io::Error),
+    /// MPD answered with an `ACK` error.
+    Mpd(String),
+    Spotify(SpotifyAuthError),
+    #[cfg(target_os = "linux")]
+    Mpris(zbus::Error)
+}
+
+impl Display for MediaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MediaError::NoDevice => write!(f, "no active player found"),
+            MediaError::Io(err) => write!(f, "connection error: {}", err),
+            MediaError::Mpd(err) => write!(f, "MPD error: {}", err),
+            MediaError::Spotify(err) => write!(f, "{}", err),
+            #[cfg(target_os = "linux")]
+            MediaError::Mpris(err) => write!(f, "MPRIS error: {}", err)
+        }
+    }
+}
+
+impl std::error::Error for MediaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MediaError::Io(err) => Some(err),
+            MediaError::Spotify(err) => Some(err),
+            #[cfg(target_os = "linux")]
+            MediaError::Mpris(err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<SpotifyAuthError> for MediaError {
+    fn from(err: SpotifyAuthError) -> Self {
+        MediaError::Spotify(err)
+    }
+}
+
+impl From<std::io::Error> for MediaError {
+    fn from(err: std::io::Error) -> Self {
+        MediaError::Io(err)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<zbus::Error> for MediaError {
+    fn from(err: zbus::Error) -> Self {
+        MediaError::Mpris(err)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<zbus::fdo::Error> for MediaError {
+    fn from(err: zbus::fdo::Error) -> Self {
+        MediaError::Mpris(err.into())
+    }
+}
@@ -1 +1,18 @@
-pub mod spotify;
\ No newline at end of file
+pub mod spotify;
+pub mod token;
+pub mod authorization;
+pub mod headless;
+pub mod profiles;
+pub mod router;
+pub mod buttons;
+pub mod targets;
+pub mod forwarder;
+pub mod transport;
+pub mod oscquery;
+pub mod vrchat;
+pub mod avatar;
+pub mod avatar_configs;
+pub mod media;
+pub mod mpd;
+#[cfg(target_os = "linux")]
+pub mod mpris;
@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use log::warn;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+use crate::config::config::Config;
+use crate::entities::config::ConfigFile;
+use crate::entities::media::{MediaDevice, NowPlaying};
+use crate::managers::media::{MediaError, MediaSource};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+trait MpdStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T> MpdStream for T where T: AsyncRead + AsyncWrite + Unpin + Send {}
+
+/// A single connection speaking the MPD text protocol.
+pub struct MpdConnection {
+    stream: BufReader<Box<dyn MpdStream>>
+}
+
+impl MpdConnection {
+    /// Connects to `address`, which is either `host:port` or the path to a Unix socket.
+    pub async fn connect(address: &str, password: &str) -> Result<Self, MediaError> {
+        let stream: Box<dyn MpdStream> = if address.starts_with('/') {
+            Self::connect_unix(address).await?
+        } else {
+            Box::new(TcpStream::connect(address).await?)
+        };
+
+        let mut connection = Self {
+            stream: BufReader::new(stream)
+        };
+
+        let mut greeting = String::new();
+        connection.stream.read_line(&mut greeting).await?;
+
+        if !greeting.starts_with("OK MPD") {
+            return Err(MediaError::Mpd(format!("Unexpected greeting: {}", greeting.trim())));
+        }
+
+        if !password.is_empty() {
+            connection.command(&format!("password {}", quote(password))).await?;
+        }
+
+        Ok(connection)
+    }
+
+    #[cfg(unix)]
+    async fn connect_unix(path: &str) -> Result<Box<dyn MpdStream>, MediaError> {
+        Ok(Box::new(tokio::net::UnixStream::connect(path).await?))
+    }
+
+    #[cfg(not(unix))]
+    async fn connect_unix(_path: &str) -> Result<Box<dyn MpdStream>, MediaError> {
+        Err(MediaError::Mpd("Unix sockets are not supported on this platform".to_string()))
+    }
+
+    /// Sends a command and collects the `key: value` pairs of its response.
+    pub async fn command(&mut self, command: &str) -> Result<Vec<(String, String)>, MediaError> {
+        self.stream.get_mut().write_all(format!("{}\n", command).as_bytes()).await?;
+
+        let mut pairs = Vec::new();
+
+        loop {
+            let mut line = String::new();
+
+            if self.stream.read_line(&mut line).await? == 0 {
+                return Err(MediaError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+            }
+
+            let line = line.trim_end_matches('\n');
+
+            if line == "OK" {
+                return Ok(pairs);
+            }
+
+            if let Some(err) = line.strip_prefix("ACK ") {
+                return Err(MediaError::Mpd(err.to_string()));
+            }
+
+            if let Some((key, value)) = line.split_once(": ") {
+                pairs.push((key.to_string(), value.to_string()));
+            }
+        }
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn to_map(pairs: Vec<(String, String)>) -> HashMap<String, String> {
+    pairs.into_iter().collect()
+}
+
+fn seconds_to_ms(value: Option<&String>) -> Option<i64> {
+    value.and_then(|value| value.parse::<f64>().ok()).map(|value| (value * 1000_f64) as i64)
+}
+
+/// Media source backed by a Music Player Daemon.
+pub struct Mpd {
+    config: Arc<Mutex<Config<ConfigFile>>>,
+    connection: Option<MpdConnection>,
+    changes: Arc<Notify>,
+    watcher: Option<JoinHandle<()>>
+}
+
+impl Mpd {
+    pub fn new(config: Arc<Mutex<Config<ConfigFile>>>) -> Self {
+        Self {
+            config,
+            connection: None,
+            changes: Arc::new(Notify::new()),
+            watcher: None
+        }
+    }
+
+    async fn settings(config: &Arc<Mutex<Config<ConfigFile>>>) -> (String, String) {
+        let config = config.lock().await;
+
+        (String::from(&config.cfg.mpd.address), String::from(&config.cfg.mpd.password))
+    }
+
+    async fn connection(&mut self) -> Result<&mut MpdConnection, MediaError> {
+        if self.connection.is_none() {
+            let (address, password) = Self::settings(&self.config).await;
+
+            self.connection = Some(MpdConnection::connect(&address, &password).await?);
+        }
+
+        Ok(self.connection.as_mut().unwrap())
+    }
+
+    /// Runs a command, reconnecting once if the server dropped the connection.
+    async fn command(&mut self, command: &str) -> Result<Vec<(String, String)>, MediaError> {
+        match self.connection().await?.command(command).await {
+            Err(MediaError::Io(_)) => {
+                self.connection = None;
+
+                let res = self.connection().await?.command(command).await;
+
+                if let Err(MediaError::Io(_)) = res {
+                    self.connection = None;
+                }
+
+                res
+            }
+            res => res
+        }
+    }
+
+    async fn status(&mut self) -> Result<HashMap<String, String>, MediaError> {
+        Ok(to_map(self.command("status").await?))
+    }
+
+    fn watch(&self) -> JoinHandle<()> {
+        tokio::task::spawn({
+            let config = self.config.clone();
+            let changes = self.changes.clone();
+
+            async move {
+                loop {
+                    let (address, password) = Self::settings(&config).await;
+
+                    match MpdConnection::connect(&address, &password).await {
+                        Ok(mut connection) => {
+                            while connection.command("idle player mixer").await.is_ok() {
+                                changes.notify_one();
+                            }
+                        }
+                        Err(_) => {
+                            warn!("Couldn't connect to MPD at {}, retrying...", address);
+                        }
+                    }
+
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        })
+    }
+}
+
+impl Drop for Mpd {
+    fn drop(&mut self) {
+        if let Some(watcher) = &self.watcher {
+            watcher.abort();
+        }
+    }
+}
+
+#[async_trait]
+impl MediaSource for Mpd {
+    async fn now_playing(&mut self) -> Result<Option<NowPlaying>, MediaError> {
+        let status = self.status().await?;
+
+        let state = status.get("state").map(|state| state.as_str()).unwrap_or("stop");
+
+        if state == "stop" {
+            return Ok(None);
+        }
+
+        let song = self.command("currentsong").await?;
+
+        let mut title = String::new();
+        let mut artists = Vec::new();
+        let mut id = None;
+        let mut file = String::new();
+
+        for (key, value) in song {
+            match key.as_str() {
+                "Title" => title = value,
+                "Artist" => artists.push(value),
+                "Id" => id = Some(value),
+                "file" => file = value,
+                _ => {}
+            }
+        }
+
+        if title.is_empty() {
+            title = String::from(file.rsplit('/').next().unwrap_or_default());
+        }
+
+        let duration_ms = seconds_to_ms(status.get("duration"))
+            .or_else(|| {
+                status.get("time")
+                    .and_then(|time| time.split_once(':'))
+                    .and_then(|(_, total)| total.parse::<i64>().ok())
+                    .map(|total| total * 1000)
+            })
+            .unwrap_or(0);
+
+        Ok(Some(NowPlaying {
+            id: id.unwrap_or(file),
+            title,
+            artists,
+            position_ms: seconds_to_ms(status.get("elapsed")).unwrap_or(0),
+            duration_ms,
+            is_playing: state == "play"
+        }))
+    }
+
+    async fn play(&mut self) -> Result<(), MediaError> {
+        let status = self.status().await?;
+
+        match status.get("state").map(|state| state.as_str()) {
+            Some("pause") => {
+                self.command("pause 0").await?;
+            }
+            Some("play") => {}
+            _ => {
+                self.command("play").await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn pause(&mut self) -> Result<(), MediaError> {
+        let status = self.status().await?;
+
+        if status.get("state").map(|state| state.as_str()) == Some("play") {
+            self.command("pause 1").await?;
+        }
+
+        Ok(())
+    }
+
+    async fn next(&mut self) -> Result<(), MediaError> {
+        self.command("next").await?;
+
+        Ok(())
+    }
+
+    async fn previous(&mut self) -> Result<(), MediaError> {
+        self.command("previous").await?;
+
+        Ok(())
+    }
+
+    async fn set_volume(&mut self, volume: f32) -> Result<(), MediaError> {
+        let volume = (volume.clamp(0_f32, 1_f32) * 100_f32).round() as u16;
+
+        self.command(&format!("setvol {}", volume)).await?;
+
+        Ok(())
+    }
+
+    async fn seek_by(&mut self, offset_ms: i64) -> Result<(), MediaError> {
+        self.command(&format!("seekcur {:+.3}", offset_ms as f64 / 1000_f64)).await?;
+
+        Ok(())
+    }
+
+    async fn devices(&mut self) -> Result<Vec<MediaDevice>, MediaError> {
+        let volume = self.status().await?
+            .get("volume")
+            .and_then(|volume| volume.parse::<i32>().ok())
+            .unwrap_or(0)
+            .max(0) as u16;
+
+        let mut devices: Vec<MediaDevice> = Vec::new();
+
+        for (key, value) in self.command("outputs").await? {
+            match key.as_str() {
+                "outputname" => devices.push(MediaDevice {
+                    id: value,
+                    is_active: false,
+                    volume_percent: volume
+                }),
+                "outputenabled" => {
+                    if let Some(device) = devices.last_mut() {
+                        device.is_active = value == "1";
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(devices)
+    }
+
+    fn changes(&mut self) -> Option<Arc<Notify>> {
+        if self.watcher.is_none() {
+            self.watcher = Some(self.watch());
+        }
+
+        Some(self.changes.clone())
+    }
+}
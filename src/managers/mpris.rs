@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+use zbus::fdo::DBusProxy;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{OwnedValue, Value};
+use zbus::{proxy, Connection};
+use crate::config::config::Config;
+use crate::entities::config::ConfigFile;
+use crate::entities::media::{MediaDevice, NowPlaying};
+use crate::managers::media::{MediaError, MediaSource};
+
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+#[proxy(
+    interface = "org.mpris.MediaPlayer2.Player",
+    default_path = "/org/mpris/MediaPlayer2"
+)]
+trait Player {
+    fn play_pause(&self) -> zbus::Result<()>;
+
+    fn next(&self) -> zbus::Result<()>;
+
+    fn previous(&self) -> zbus::Result<()>;
+
+    fn seek(&self, offset: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn metadata(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+
+    #[zbus(property)]
+    fn position(&self) -> zbus::Result<i64>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn volume(&self) -> zbus::Result<f64>;
+
+    #[zbus(property)]
+    fn set_volume(&self, value: f64) -> zbus::Result<()>;
+}
+
+/// Media source backed by any MPRIS capable player on the D-Bus session bus.
+pub struct Mpris {
+    config: Arc<Mutex<Config<ConfigFile>>>,
+    connection: Option<Connection>,
+    player: Option<String>
+}
+
+impl Mpris {
+    pub fn new(config: Arc<Mutex<Config<ConfigFile>>>) -> Self {
+        Self {
+            config,
+            connection: None,
+            player: None
+        }
+    }
+
+    async fn connection(&mut self) -> Result<Connection, MediaError> {
+        if let Some(connection) = &self.connection {
+            return Ok(connection.clone());
+        }
+
+        let bus_address = {
+            let config = self.config.lock().await;
+
+            String::from(&config.cfg.mpris.bus_address)
+        };
+
+        let connection = if bus_address.is_empty() {
+            Connection::session().await?
+        } else {
+            zbus::connection::Builder::address(bus_address.as_str())?.build().await?
+        };
+
+        self.connection = Some(connection.clone());
+
+        Ok(connection)
+    }
+
+    /// Lists the bus names of every player matching the configured player filter.
+    pub async fn players(&mut self) -> Result<Vec<String>, MediaError> {
+        let connection = self.connection().await?;
+
+        let filter = {
+            let config = self.config.lock().await;
+
+            String::from(&config.cfg.mpris.player)
+        };
+
+        let names = DBusProxy::new(&connection).await?.list_names().await?;
+
+        let mut players: Vec<String> = names.into_iter()
+            .map(|name| name.to_string())
+            .filter(|name| name.starts_with(MPRIS_PREFIX))
+            .filter(|name| {
+                if filter.is_empty() {
+                    return true;
+                }
+
+                let player = &name[MPRIS_PREFIX.len()..];
+
+                player == filter || player.starts_with(&format!("{}.", filter))
+            })
+            .collect();
+
+        players.sort();
+
+        Ok(players)
+    }
+
+    async fn proxy(&mut self, name: String) -> Result<PlayerProxy<'static>, MediaError> {
+        let connection = self.connection().await?;
+
+        Ok(PlayerProxy::builder(&connection)
+            .destination(name)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?)
+    }
+
+    async fn player(&mut self) -> Result<PlayerProxy<'static>, MediaError> {
+        let name = match &self.player {
+            Some(name) => String::from(name),
+            None => {
+                let name = self.players().await?.into_iter().next().ok_or(MediaError::NoDevice)?;
+
+                self.player = Some(String::from(&name));
+
+                name
+            }
+        };
+
+        self.proxy(name).await
+    }
+
+    /// Forgets the selected player when a call fails, so the next call looks it up again.
+    fn forget_on_err<T>(&mut self, res: zbus::Result<T>) -> Result<T, MediaError> {
+        if res.is_err() {
+            self.player = None;
+        }
+
+        Ok(res?)
+    }
+
+    async fn set_playing(&mut self, playing: bool) -> Result<(), MediaError> {
+        let player = self.player().await?;
+
+        let status = player.playback_status().await;
+        let status = self.forget_on_err(status)?;
+
+        if (status == "Playing") != playing {
+            let res = player.play_pause().await;
+            self.forget_on_err(res)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn variant<'a>(value: &'a Value<'a>) -> &'a Value<'a> {
+    match value {
+        Value::Value(inner) => variant(inner),
+        value => value
+    }
+}
+
+fn metadata_string(metadata: &HashMap<String, OwnedValue>, key: &str) -> Option<String> {
+    match metadata.get(key).map(|value| variant(value)) {
+        Some(Value::Str(value)) => Some(value.to_string()),
+        Some(Value::ObjectPath(value)) => Some(value.to_string()),
+        _ => None
+    }
+}
+
+fn metadata_strings(metadata: &HashMap<String, OwnedValue>, key: &str) -> Vec<String> {
+    match metadata.get(key).map(|value| variant(value)) {
+        Some(Value::Array(values)) => values.iter()
+            .filter_map(|value| match variant(value) {
+                Value::Str(value) => Some(value.to_string()),
+                _ => None
+            })
+            .collect(),
+        Some(Value::Str(value)) => vec![value.to_string()],
+        _ => Vec::new()
+    }
+}
+
+fn metadata_i64(metadata: &HashMap<String, OwnedValue>, key: &str) -> Option<i64> {
+    match metadata.get(key).map(|value| variant(value)) {
+        Some(Value::I64(value)) => Some(*value),
+        Some(Value::U64(value)) => Some(*value as i64),
+        Some(Value::I32(value)) => Some(*value as i64),
+        Some(Value::U32(value)) => Some(*value as i64),
+        _ => None
+    }
+}
+
+#[async_trait]
+impl MediaSource for Mpris {
+    async fn now_playing(&mut self) -> Result<Option<NowPlaying>, MediaError> {
+        let player = match self.player().await {
+            Ok(player) => player,
+            Err(MediaError::NoDevice) => return Ok(None),
+            Err(err) => return Err(err)
+        };
+
+        let status = player.playback_status().await;
+        let status = self.forget_on_err(status)?;
+
+        if status == "Stopped" {
+            return Ok(None);
+        }
+
+        let metadata = player.metadata().await;
+        let metadata = self.forget_on_err(metadata)?;
+
+        // Position is optional for players that can't report it.
+        let position = player.position().await.unwrap_or(0);
+
+        let title = metadata_string(&metadata, "xesam:title").unwrap_or_default();
+        let artists = metadata_strings(&metadata, "xesam:artist");
+
+        let id = metadata_string(&metadata, "mpris:trackid")
+            .unwrap_or_else(|| format!("{} - {}", artists.join(", "), title));
+
+        Ok(Some(NowPlaying {
+            id,
+            title,
+            artists,
+            position_ms: position / 1000,
+            duration_ms: metadata_i64(&metadata, "mpris:length").unwrap_or(0) / 1000,
+            is_playing: status == "Playing"
+        }))
+    }
+
+    async fn play(&mut self) -> Result<(), MediaError> {
+        self.set_playing(true).await
+    }
+
+    async fn pause(&mut self) -> Result<(), MediaError> {
+        self.set_playing(false).await
+    }
+
+    async fn next(&mut self) -> Result<(), MediaError> {
+        let player = self.player().await?;
+
+        let res = player.next().await;
+        self.forget_on_err(res)
+    }
+
+    async fn previous(&mut self) -> Result<(), MediaError> {
+        let player = self.player().await?;
+
+        let res = player.previous().await;
+        self.forget_on_err(res)
+    }
+
+    async fn set_volume(&mut self, volume: f32) -> Result<(), MediaError> {
+        let player = self.player().await?;
+
+        let res = player.set_volume(volume.clamp(0_f32, 1_f32) as f64).await;
+        self.forget_on_err(res)
+    }
+
+    async fn seek_by(&mut self, offset_ms: i64) -> Result<(), MediaError> {
+        let player = self.player().await?;
+
+        // MPRIS offsets are in microseconds.
+        let res = player.seek(offset_ms * 1000).await;
+        self.forget_on_err(res)
+    }
+
+    async fn devices(&mut self) -> Result<Vec<MediaDevice>, MediaError> {
+        let players = self.players().await?;
+
+        let active = match &self.player {
+            Some(name) => Some(String::from(name)),
+            None => players.first().cloned()
+        };
+
+        let mut devices = Vec::new();
+
+        for name in players {
+            let volume = match self.proxy(String::from(&name)).await {
+                Ok(player) => player.volume().await.unwrap_or(0_f64),
+                Err(_) => 0_f64
+            };
+
+            devices.push(MediaDevice {
+                is_active: active.as_deref() == Some(name.as_str()),
+                id: name,
+                volume_percent: (volume * 100_f64).round() as u16
+            });
+        }
+
+        Ok(devices)
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use actix_web::dev::ServerHandle;
+use actix_web::{App, HttpServer, web};
+use log::warn;
+use mdns_sd::{IfKind, ServiceDaemon, ServiceInfo};
+use tokio::sync::Mutex;
+use crate::config::config::Config;
+use crate::entities::config::{ArgumentType, ConfigFile};
+use crate::entities::oscquery::{OscQueryHostInfo, OscQueryNode, ACCESS_WRITE};
+use crate::managers::avatar::CurrentAvatar;
+use crate::managers::router::OscRouter;
+use crate::routes::oscquery::oscquery;
+use crate::routes::OscQueryData;
+use crate::utils::osc::is_pattern;
+
+pub const OSCJSON_SERVICE: &str = "_oscjson._tcp.local.";
+pub const OSC_SERVICE: &str = "_osc._udp.local.";
+
+/// Describes the receive addresses of `avatar` as an OSCQuery tree.
+/// Address patterns can't be listed, so those are left out.
+pub fn oscquery_tree(cfg: &ConfigFile, avatar: Option<&str>) -> OscQueryNode {
+    let router = OscRouter::for_avatar(cfg, avatar);
+    let parameters = &cfg.parameters_for(avatar);
+
+    let methods = [
+        (&parameters.spotify_play, "T", "Resumes playback"),
+        (&parameters.spotify_stop, "T", "Pauses playback"),
+        (&parameters.spotify_next, "T", "Skips to the next track"),
+        (&parameters.spotify_previous, "T", "Goes back to the previous track"),
+        (&parameters.spotify_volume, "f", "Sets the volume"),
+        (&parameters.spotify_profile, "i", "Switches the Spotify profile")
+    ];
+
+    let mut root = OscQueryNode::root();
+
+    // VRChat only sends avatar parameters to services listening for avatar changes.
+    root.insert(&parameters.avatar_change, "s", ACCESS_WRITE, "Avatar changes");
+
+    for (address, osc_type, description) in methods {
+        if address.is_empty() || is_pattern(address) {
+            continue;
+        }
+
+        let osc_type = match router.coercion(address).argument_type {
+            ArgumentType::Auto => osc_type,
+            ArgumentType::Bool => "T",
+            ArgumentType::Int => "i",
+            ArgumentType::Float => "f",
+            ArgumentType::String => "s"
+        };
+
+        root.insert(address, osc_type, ACCESS_WRITE, description);
+    }
+
+    root
+}
+
+/// OSCQuery HTTP server for the app, advertised over mDNS until dropped.
+pub struct OscQuery {
+    server: ServerHandle,
+    daemon: ServiceDaemon,
+    http_port: u16
+}
+
+impl OscQuery {
+    /// Serves the address tree on a free port and advertises it together with `osc`, where the app receives OSC.
+    pub fn start(config: Arc<Mutex<Config<ConfigFile>>>, avatar: Arc<CurrentAvatar>, name: &str, osc: SocketAddr) -> Result<Self, OscQueryError> {
+        // Without a specific address, local apps like VRChat reach us on loopback.
+        let ip = if osc.ip().is_unspecified() {
+            IpAddr::V4(Ipv4Addr::LOCALHOST)
+        } else {
+            osc.ip()
+        };
+
+        let data = OscQueryData {
+            config,
+            avatar,
+            host_info: OscQueryHostInfo {
+                name: name.to_string(),
+                osc_ip: ip.to_string(),
+                osc_port: osc.port(),
+                osc_transport: "UDP".to_string(),
+                extensions: BTreeMap::from([
+                    ("ACCESS".to_string(), true),
+                    ("DESCRIPTION".to_string(), true)
+                ])
+            }
+        };
+
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(data.clone()))
+                .default_service(web::get().to(oscquery))
+        })
+            .workers(1)
+            .disable_signals()
+            .bind((osc.ip(), 0))
+            .map_err(OscQueryError::Io)?;
+
+        let http_port = server.addrs()[0].port();
+
+        let server = server.run();
+        let handle = server.handle();
+
+        tokio::task::spawn(server);
+
+        let daemon = ServiceDaemon::new().map_err(OscQueryError::Mdns)?;
+
+        if ip.is_loopback() {
+            daemon.enable_interface(IfKind::LoopbackV4).map_err(OscQueryError::Mdns)?;
+        }
+
+        let host_name = format!("{}.local.", host_label(name));
+
+        for (service, port) in [(OSCJSON_SERVICE, http_port), (OSC_SERVICE, osc.port())] {
+            let info = ServiceInfo::new(service, name, &host_name, ip, port, &[("txtvers", "1")][..])
+                .map_err(OscQueryError::Mdns)?;
+
+            daemon.register(info).map_err(OscQueryError::Mdns)?;
+        }
+
+        Ok(Self {
+            server: handle,
+            daemon,
+            http_port
+        })
+    }
+
+    pub fn http_port(&self) -> u16 {
+        self.http_port
+    }
+}
+
+impl Drop for OscQuery {
+    fn drop(&mut self) {
+        // Both only queue the command, there's nothing to wait for.
+        drop(self.server.stop(false));
+
+        if let Err(err) = self.daemon.shutdown() {
+            warn!("Couldn't stop advertising OSCQuery: {}", err);
+        }
+    }
+}
+
+/// The name as a DNS label, anything but letters, digits and dashes becomes a dash.
+fn host_label(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '-' })
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum OscQueryError {
+    Io(std::io::Error),
+    Mdns(mdns_sd::Error)
+}
+
+impl Display for OscQueryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OscQueryError::Io(err) => write!(f, "couldn't start the OSCQuery server: {}", err),
+            OscQueryError::Mdns(err) => write!(f, "couldn't advertise over mDNS: {}", err)
+        }
+    }
+}
+
+impl std::error::Error for OscQueryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OscQueryError::Io(err) => Some(err),
+            OscQueryError::Mdns(err) => Some(err)
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use async_trait::async_trait;
+use log::info;
+use reqwest::Client;
+use tokio::sync::Mutex;
+use crate::config::config::Config;
+use crate::entities::config::ConfigFile;
+use crate::entities::media::{MediaDevice, NowPlaying};
+use crate::http::rate_limit::RateLimiter;
+use crate::managers::media::{MediaError, MediaSource};
+use crate::managers::spotify::Spotify;
+
+/// One `Spotify` per configured profile, media calls go to the active one.
+pub struct SpotifyProfiles {
+    profiles: Vec<Spotify>,
+    active: usize
+}
+
+impl SpotifyProfiles {
+    pub async fn new(http: Arc<Client>, config: Arc<Mutex<Config<ConfigFile>>>) -> Self {
+        let (names, active_profile) = {
+            let config = config.lock().await;
+
+            let names: Vec<String> = config.cfg.spotify.profiles().into_iter().map(|profile| profile.name).collect();
+
+            (names, String::from(&config.cfg.spotify.active_profile))
+        };
+
+        let rate_limiter = Arc::new(RateLimiter::new());
+
+        let profiles = names.iter()
+            .map(|name| Spotify::with_profile(http.clone(), config.clone(), name, rate_limiter.clone()))
+            .collect();
+
+        Self {
+            active: names.iter().position(|name| *name == active_profile).unwrap_or(0),
+            profiles
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.profiles.iter().map(|spotify| spotify.profile().unwrap_or_default().to_string()).collect()
+    }
+
+    pub fn active_name(&self) -> String {
+        self.names().swap_remove(self.active)
+    }
+
+    pub fn active(&mut self) -> &mut Spotify {
+        &mut self.profiles[self.active]
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Spotify> {
+        self.profiles.iter_mut().find(|spotify| spotify.profile() == Some(name))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Spotify> {
+        self.profiles.iter_mut()
+    }
+
+    pub fn switch(&mut self, name: &str) -> Result<(), ProfileError> {
+        match self.profiles.iter().position(|spotify| spotify.profile() == Some(name)) {
+            Some(index) => self.switch_index(index as i32),
+            None => Err(ProfileError::Unknown(name.to_string()))
+        }
+    }
+
+    /// Switches by position in the config, which is what the OSC parameter sends.
+    pub fn switch_index(&mut self, index: i32) -> Result<(), ProfileError> {
+        if index < 0 || index as usize >= self.profiles.len() {
+            return Err(ProfileError::OutOfRange(index))
+        }
+
+        if self.active != index as usize {
+            self.active = index as usize;
+
+            info!("Switched to Spotify profile {}", self.active_name());
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MediaSource for SpotifyProfiles {
+    async fn now_playing(&mut self) -> Result<Option<NowPlaying>, MediaError> {
+        MediaSource::now_playing(self.active()).await
+    }
+
+    async fn play(&mut self) -> Result<(), MediaError> {
+        self.active().play().await
+    }
+
+    async fn pause(&mut self) -> Result<(), MediaError> {
+        self.active().pause().await
+    }
+
+    async fn next(&mut self) -> Result<(), MediaError> {
+        self.active().next().await
+    }
+
+    async fn previous(&mut self) -> Result<(), MediaError> {
+        self.active().previous().await
+    }
+
+    async fn set_volume(&mut self, volume: f32) -> Result<(), MediaError> {
+        MediaSource::set_volume(self.active(), volume).await
+    }
+
+    async fn seek_by(&mut self, offset_ms: i64) -> Result<(), MediaError> {
+        self.active().seek_by(offset_ms).await
+    }
+
+    async fn devices(&mut self) -> Result<Vec<MediaDevice>, MediaError> {
+        self.active().devices().await
+    }
+
+    fn rate_limited(&self) -> bool {
+        self.profiles[self.active].rate_limited()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProfileError {
+    /// No profile has this name.
+    Unknown(String),
+    /// The OSC parameter pointed past the end of the profile list.
+    OutOfRange(i32)
+}
+
+impl Display for ProfileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProfileError::Unknown(name) => write!(f, "there's no Spotify profile named {}", name),
+            ProfileError::OutOfRange(index) => write!(f, "there's no Spotify profile number {}", index)
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use arc_swap::ArcSwap;
+use crate::entities::config::ConfigFile;
+use crate::entities::osc::OscAction;
+use crate::utils::coerce::Coercion;
+use crate::utils::osc::{is_pattern, matches_pattern};
+
+/// Keeps a held button from repeating its long press action in a tight loop.
+const MIN_LONG_PRESS: Duration = Duration::from_millis(50);
+
+/// Maps incoming addresses to actions.
+/// Configured addresses may be OSC address patterns, so one entry can cover several avatars' parameter names.
+#[derive(Default)]
+pub struct OscRouter {
+    exact: HashMap<String, Vec<OscAction>>,
+    patterns: Vec<(String, OscAction)>,
+    overrides: Vec<(String, Coercion)>,
+    long_press: Vec<(String, OscAction)>,
+    debounce: Duration,
+    long_press_after: Duration,
+    seek_step_ms: i64
+}
+
+impl OscRouter {
+    pub fn new(cfg: &ConfigFile) -> Self {
+        Self::for_avatar(cfg, None)
+    }
+
+    /// Routes the parameters of `avatar`, falling back to `[parameters]` for avatars without a profile.
+    pub fn for_avatar(cfg: &ConfigFile, avatar: Option<&str>) -> Self {
+        let parameters = cfg.parameters_for(avatar);
+
+        let mut router = Self {
+            long_press: cfg.buttons.long_press.iter()
+                .map(|entry| (String::from(&entry.address), entry.action))
+                .collect(),
+            debounce: Duration::from_millis(cfg.buttons.debounce_ms),
+            long_press_after: Duration::from_millis(cfg.buttons.long_press_ms).max(MIN_LONG_PRESS),
+            seek_step_ms: cfg.buttons.seek_step_ms,
+            ..Self::default()
+        };
+
+        router.add(&parameters.spotify_play, OscAction::Play);
+        router.add(&parameters.spotify_stop, OscAction::Pause);
+        router.add(&parameters.spotify_next, OscAction::Next);
+        router.add(&parameters.spotify_previous, OscAction::Previous);
+        router.add(&parameters.spotify_volume, OscAction::Volume);
+        router.add(&parameters.spotify_profile, OscAction::SwitchProfile);
+        router.add(&parameters.avatar_change, OscAction::AvatarChange);
+
+        for entry in &parameters.overrides {
+            router.overrides.push((String::from(&entry.address), Coercion::from(entry)));
+        }
+
+        router
+    }
+
+    /// Routes `address` to `action`, empty addresses are left unrouted.
+    pub fn add(&mut self, address: &str, action: OscAction) {
+        if address.is_empty() {
+            return;
+        }
+
+        if is_pattern(address) {
+            self.patterns.push((address.to_string(), action));
+        } else {
+            self.exact.entry(address.to_string()).or_default().push(action);
+        }
+    }
+
+    /// Every action routed to `address`, exact addresses first and then matching patterns.
+    pub fn route(&self, address: &str) -> Vec<OscAction> {
+        let mut actions = self.exact.get(address).cloned().unwrap_or_default();
+
+        for (pattern, action) in &self.patterns {
+            if matches_pattern(pattern, address) && !actions.contains(action) {
+                actions.push(*action);
+            }
+        }
+
+        actions
+    }
+
+    /// Whether the app acts on `address` itself.
+    /// Avatar changes don't count, the other apps listening to VRChat need them as well.
+    pub fn handles(&self, address: &str) -> bool {
+        self.route(address).iter().any(|action| *action != OscAction::AvatarChange)
+    }
+
+    /// How arguments sent to `address` are read, from the first override matching it.
+    pub fn coercion(&self, address: &str) -> Coercion {
+        self.overrides.iter()
+            .find(|(pattern, _)| matches_pattern(pattern, address))
+            .map(|(_, coercion)| *coercion)
+            .unwrap_or_default()
+    }
+
+    /// Action run while the button at `address` is held, from the first long press entry matching it.
+    pub fn long_press(&self, address: &str) -> Option<OscAction> {
+        self.long_press.iter()
+            .find(|(pattern, _)| matches_pattern(pattern, address))
+            .map(|(_, action)| *action)
+    }
+
+    pub fn debounce(&self) -> Duration {
+        self.debounce
+    }
+
+    pub fn long_press_after(&self) -> Duration {
+        self.long_press_after
+    }
+
+    pub fn seek_step_ms(&self) -> i64 {
+        self.seek_step_ms
+    }
+}
+
+/// The router in use, replaced as a whole when the config changes so dispatch never waits on a lock.
+pub struct SharedRouter {
+    router: ArcSwap<OscRouter>
+}
+
+impl SharedRouter {
+    pub fn new(router: OscRouter) -> Self {
+        Self {
+            router: ArcSwap::from_pointee(router)
+        }
+    }
+
+    pub fn load(&self) -> Arc<OscRouter> {
+        self.router.load_full()
+    }
+
+    pub fn store(&self, router: OscRouter) {
+        self.router.store(Arc::new(router));
+    }
+}
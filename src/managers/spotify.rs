@@ -1,326 +1,323 @@
+use std::fmt::{Display, Formatter};
+use std::future::Future;
 use std::sync::{Arc};
+use std::time::Duration;
+use async_trait::async_trait;
+use log::warn;
 use reqwest::Client;
 use tokio::sync::Mutex;
 use crate::config::config::Config;
+use crate::config::credentials::CredentialsError;
 use crate::entities::config::ConfigFile;
+use crate::entities::media::{MediaDevice, NowPlaying};
 use crate::entities::spotify::{SpotifyDevices, SpotifyInfo, SpotifyPlayback};
-use crate::http::spotify::{authenticate_spotify, fetch_spotify_devices, fetch_spotify_info, get_spotify_playback_state, refresh_authenticate_spotify, set_spotify_active, set_spotify_playback_next, set_spotify_playback_play, set_spotify_playback_previous, set_spotify_playback_stop, set_spotify_volume};
-use crate::http::{SpotifyValue};
-
-const AUTH_ATTEMPTS: usize = 2;
+use crate::http::spotify::{fetch_spotify_devices, fetch_spotify_info, get_spotify_playback_state, set_spotify_active, set_spotify_playback_next, set_spotify_playback_play, set_spotify_playback_previous, set_spotify_playback_stop, set_spotify_seek, set_spotify_volume};
+use crate::http::rate_limit::RateLimiter;
+use crate::http::{RequestError, SpotifyValue};
+use crate::managers::media::{MediaError, MediaSource};
+use crate::managers::token::TokenManager;
+
+const MAX_ATTEMPTS: usize = 3;
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Everything a single Web API request needs, handed to the request closure on every attempt.
+pub struct SpotifyRequest {
+    pub http: Arc<Client>,
+    pub api_url: String,
+    pub token: String
+}
 
 pub struct Spotify {
     http: Arc<Client>,
     config: Arc<Mutex<Config<ConfigFile>>>,
-    pub active: bool,
-    pub token: String
+    rate_limiter: Arc<RateLimiter>,
+    tokens: Arc<TokenManager>,
+    profile: Option<String>,
+    pub active: bool
 }
 
 impl Spotify {
+    /// Spotify using the first profile in the config.
     pub fn new (http: Arc<Client>, config: Arc<Mutex<Config<ConfigFile>>>) -> Self {
         Self {
+            tokens: Arc::new(TokenManager::new(http.clone(), config.clone(), None)),
             http,
             config,
-            active: false,
-            token: "".to_string()
+            rate_limiter: Arc::new(RateLimiter::new()),
+            profile: None,
+            active: false
         }
     }
 
-    pub async fn authenticate(&mut self) -> Result<(), SpotifyAuthError> {
-        let mut config = self.config.lock().await;
-
-        if config.cfg.spotify.refresh_token.is_empty() || config.cfg.spotify.client_id.is_empty()
-            || config.cfg.spotify.client_secret.is_empty() {
-            return Err(SpotifyAuthError::ConfigNotInitialized)
+    /// Spotify logged in as `profile`. Rate limits apply to the whole app, so profiles share a limiter.
+    pub fn with_profile(http: Arc<Client>, config: Arc<Mutex<Config<ConfigFile>>>, profile: &str, rate_limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            tokens: Arc::new(TokenManager::new(http.clone(), config.clone(), Some(profile.to_string()))),
+            http,
+            config,
+            rate_limiter,
+            profile: Some(profile.to_string()),
+            active: false
         }
+    }
 
-        match refresh_authenticate_spotify(&self.http, String::from(&config.cfg.spotify.refresh_token),
-                                           config.cfg.get_auth_base64()).await
-        {
-            Ok(res) => {
-                config.cfg.spotify.token = res.access_token;
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
 
-                config.write();
+    pub fn rate_limiter(&self) -> Arc<RateLimiter> {
+        self.rate_limiter.clone()
+    }
 
-                self.token = String::from(&config.cfg.spotify.token);
-                self.active = true;
-                Ok(())
-            }
-            Err(_) => {
-                Err(SpotifyAuthError::FAILED)
-            }
-        }
+    pub fn config(&self) -> Arc<Mutex<Config<ConfigFile>>> {
+        self.config.clone()
     }
 
-    pub async fn init_credentials(&mut self, code: &String) -> Result<(), SpotifyAuthError> {
-        let mut config = self.config.lock().await;
+    pub fn tokens(&self) -> Arc<TokenManager> {
+        self.tokens.clone()
+    }
 
-        if config.cfg.spotify.client_id.is_empty() || config.cfg.spotify.client_secret.is_empty()
-            || config.cfg.spotify.callback_url.is_empty() {
-            return Err(SpotifyAuthError::ConfigNotInitialized)
-        }
+    /// Refreshes the access token right away using the stored refresh token.
+    pub async fn authenticate(&mut self) -> Result<(), SpotifyAuthError> {
+        self.tokens.refresh(None).await?;
 
-        match authenticate_spotify(&self.http, &code, String::from(&config.cfg.spotify.callback_url), config.cfg.get_auth_base64()).await {
-            Ok(res) => {
-                config.cfg.spotify.token = res.access_token;
-                config.cfg.spotify.refresh_token = res.refresh_token;
+        self.active = true;
+        Ok(())
+    }
 
-                self.token = String::from(&config.cfg.spotify.token);
+    pub async fn init_credentials(&mut self, code: &String, verifier: Option<String>) -> Result<(), SpotifyAuthError> {
+        self.tokens.exchange(code, verifier).await?;
 
-                config.write();
+        self.active = true;
+        Ok(())
+    }
 
-                self.active = true;
-                Ok(())
-            }
+    async fn api_url(&self) -> String {
+        let config = self.config.lock().await;
 
-            Err(_) => {
-                Err(SpotifyAuthError::FAILED)
-            }
-        }
+        String::from(&config.cfg.spotify.api_url)
     }
 
-    pub async fn now_playing(&mut self) -> Result<Option<SpotifyInfo>, SpotifyAuthError> {
-        if !&self.active {
+    /// Runs a Web API request, refreshing the token before it expires or once more on 401,
+    /// and backing off on transient errors.
+    /// While Spotify is rate limiting us, requests fail right away without reaching the network.
+    async fn execute<T, F, Fut>(&mut self, request: F) -> Result<T, SpotifyAuthError>
+        where F: Fn(SpotifyRequest) -> Fut,
+              Fut: Future<Output = Result<T, RequestError>>
+    {
+        if !self.active {
             return Err(SpotifyAuthError::NotInitialized)
         }
 
-        for _ in 0..AUTH_ATTEMPTS {
-            match fetch_spotify_info(&self.http, &self.token).await {
-                Ok(val) => {
-                    return match val {
-                        SpotifyValue::INFO(res) => {
-                            Ok(Some(res))
-                        }
-                        SpotifyValue::EMPTY => {
-                            Ok(None)
-                        }
-                    }
-                }
-                Err(_) => {
-                    match self.authenticate().await {
-                        Ok(_) => {
-                            continue;
-                        }
-                        Err(_) => {
-                            return Err(SpotifyAuthError::FAILED);
-                        }
-                    }
-                }
+        let api_url = self.api_url().await;
+
+        let mut refreshed = false;
+        let mut backoff = RETRY_BACKOFF;
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            if let Some(remaining) = self.rate_limiter.remaining() {
+                return Err(SpotifyAuthError::Request(RequestError::RateLimited { retry_after: Some(remaining) }));
             }
-        }
 
-        return Err(SpotifyAuthError::FAILED)
-    }
+            let token = self.tokens.access_token().await?;
 
-    pub async fn get_devices(&mut self) -> Result<SpotifyDevices, SpotifyAuthError> {
-        if !&self.active {
-            return Err(SpotifyAuthError::NotInitialized)
-        }
+            let ctx = SpotifyRequest {
+                http: self.http.clone(),
+                api_url: String::from(&api_url),
+                token: String::from(&token)
+            };
 
-        for _ in 0..AUTH_ATTEMPTS {
-            match fetch_spotify_devices(&self.http, &self.token).await {
-                Ok(devices) => {
-                    return Ok(devices)
-                }
-                Err(_) => {
-                    match self.authenticate().await {
-                        Ok(_) => {
-                            continue;
-                        }
-                        Err(_) => {
-                            return Err(SpotifyAuthError::FAILED);
-                        }
-                    }
+            match request(ctx).await {
+                Ok(res) => {
+                    return Ok(res);
                 }
-            };
-        }
+                Err(RequestError::RateLimited { retry_after }) => {
+                    warn!("Spotify is rate limiting requests, pausing for {}s", retry_after.map(|retry_after| retry_after.as_secs()).unwrap_or(0));
 
-        return Err(SpotifyAuthError::FAILED);
-    }
+                    self.rate_limiter.limit(retry_after);
 
-    pub async fn set_volume(&mut self, device_id: &String, volume: u16) -> Result<(), SpotifyAuthError> {
-        if !&self.active {
-            return Err(SpotifyAuthError::NotInitialized)
-        }
+                    return Err(SpotifyAuthError::Request(RequestError::RateLimited { retry_after }));
+                }
+                Err(RequestError::Unauthorized(_)) if !refreshed => {
+                    self.tokens.refresh(Some(&token)).await?;
+                    refreshed = true;
+                }
+                Err(err) if err.is_transient() && attempt < MAX_ATTEMPTS => {
+                    warn!("Spotify request failed, retrying in {}ms: {}", backoff.as_millis(), err);
 
-        for _ in 0..AUTH_ATTEMPTS {
-            match set_spotify_volume(&self.http, &self.token, &device_id, volume).await {
-                Ok(_) => {
-                    return Ok(());
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
                 }
-                Err(_) => {
-                    match self.authenticate().await {
-                        Ok(_) => {
-                            continue;
-                        }
-                        Err(_) => {
-                            return Err(SpotifyAuthError::FAILED);
-                        }
-                    }
+                Err(err) => {
+                    return Err(SpotifyAuthError::Request(err));
                 }
-            };
+            }
         }
-
-        return Err(SpotifyAuthError::FAILED);
     }
 
-    pub async fn get_playback_state(&mut self) -> Result<Option<SpotifyPlayback>, SpotifyAuthError> {
-        if !&self.active {
-            return Err(SpotifyAuthError::NotInitialized)
-        }
+    pub async fn now_playing(&mut self) -> Result<Option<SpotifyInfo>, SpotifyAuthError> {
+        let res = self.execute(|ctx| async move { fetch_spotify_info(&ctx.http, &ctx.api_url, &ctx.token).await }).await?;
 
-        for _ in 0..AUTH_ATTEMPTS {
-            match get_spotify_playback_state(&self.http, &self.token).await {
-                Ok(res) => {
-                    return Ok(res);
-                }
-                Err(_) => {
-                    match self.authenticate().await {
-                        Ok(_) => {
-                            continue;
-                        }
-                        Err(_) => {
-                            return Err(SpotifyAuthError::FAILED);
-                        }
-                    }
-                }
-            };
+        match res {
+            SpotifyValue::INFO(res) => Ok(Some(res)),
+            SpotifyValue::EMPTY => Ok(None)
         }
+    }
 
-        return Err(SpotifyAuthError::FAILED);
+    pub async fn get_devices(&mut self) -> Result<SpotifyDevices, SpotifyAuthError> {
+        self.execute(|ctx| async move { fetch_spotify_devices(&ctx.http, &ctx.api_url, &ctx.token).await }).await
     }
 
-    pub async fn set_playback_active(&mut self, device_id: &String, keep_state: bool) -> Result<(), SpotifyAuthError> {
-        if !&self.active {
-            return Err(SpotifyAuthError::NotInitialized)
-        }
+    pub async fn set_volume(&mut self, device_id: &String, volume: u16) -> Result<(), SpotifyAuthError> {
+        self.execute(|ctx| async move { set_spotify_volume(&ctx.http, &ctx.api_url, &ctx.token, device_id, volume).await }).await
+    }
 
-        for _ in 0..AUTH_ATTEMPTS {
-            match set_spotify_active(&self.http, &self.token, &device_id, keep_state).await {
-                Ok(_) => {
-                    return Ok(());
-                }
-                Err(_) => {
-                    match self.authenticate().await {
-                        Ok(_) => {
-                            continue;
-                        }
-                        Err(_) => {
-                            return Err(SpotifyAuthError::FAILED);
-                        }
-                    }
-                }
-            };
-        }
+    pub async fn get_playback_state(&mut self) -> Result<Option<SpotifyPlayback>, SpotifyAuthError> {
+        self.execute(|ctx| async move { get_spotify_playback_state(&ctx.http, &ctx.api_url, &ctx.token).await }).await
+    }
 
-        return Err(SpotifyAuthError::FAILED);
+    pub async fn set_playback_active(&mut self, device_id: &String, keep_state: bool) -> Result<(), SpotifyAuthError> {
+        self.execute(|ctx| async move { set_spotify_active(&ctx.http, &ctx.api_url, &ctx.token, device_id, keep_state).await }).await
     }
 
     pub async fn set_playback_play(&mut self, device_id: &String) -> Result<(), SpotifyAuthError> {
-        if !&self.active {
-            return Err(SpotifyAuthError::NotInitialized)
-        }
+        self.execute(|ctx| async move { set_spotify_playback_play(&ctx.http, &ctx.api_url, &ctx.token, device_id).await }).await
+    }
 
-        for _ in 0..AUTH_ATTEMPTS {
-            match set_spotify_playback_play(&self.http, &self.token, &device_id).await {
-                Ok(_) => {
-                    return Ok(());
-                }
-                Err(_) => {
-                    match self.authenticate().await {
-                        Ok(_) => {
-                            continue;
-                        }
-                        Err(_) => {
-                            return Err(SpotifyAuthError::FAILED);
-                        }
-                    }
-                }
-            };
-        }
+    pub async fn set_playback_next(&mut self, device_id: &String) -> Result<(), SpotifyAuthError> {
+        self.execute(|ctx| async move { set_spotify_playback_next(&ctx.http, &ctx.api_url, &ctx.token, device_id).await }).await
+    }
 
-        return Err(SpotifyAuthError::FAILED);
+    pub async fn set_playback_previous(&mut self, device_id: &String) -> Result<(), SpotifyAuthError> {
+        self.execute(|ctx| async move { set_spotify_playback_previous(&ctx.http, &ctx.api_url, &ctx.token, device_id).await }).await
     }
 
-    pub async fn set_playback_next(&mut self, device_id: &String) -> Result<(), SpotifyAuthError> {
-        if !&self.active {
-            return Err(SpotifyAuthError::NotInitialized)
+    pub async fn set_playback_pause(&mut self, device_id: &String) -> Result<(), SpotifyAuthError> {
+        self.execute(|ctx| async move { set_spotify_playback_stop(&ctx.http, &ctx.api_url, &ctx.token, device_id).await }).await
+    }
+
+    pub async fn set_seek(&mut self, device_id: &String, position_ms: i64) -> Result<(), SpotifyAuthError> {
+        self.execute(|ctx| async move { set_spotify_seek(&ctx.http, &ctx.api_url, &ctx.token, device_id, position_ms).await }).await
+    }
+}
+
+impl Spotify {
+    async fn active_device_id(&mut self) -> Result<String, MediaError> {
+        let devices = self.get_devices().await?;
+
+        match devices.get_active() {
+            Some(device) => Ok(String::from(&device.id)),
+            None => Err(MediaError::NoDevice)
         }
+    }
 
-        for _ in 0..AUTH_ATTEMPTS {
-            match set_spotify_playback_next(&self.http, &self.token, &device_id).await {
-                Ok(_) => {
-                    return Ok(());
-                }
-                Err(_) => {
-                    match self.authenticate().await {
-                        Ok(_) => {
-                            continue;
-                        }
-                        Err(_) => {
-                            return Err(SpotifyAuthError::FAILED);
-                        }
+    async fn set_playing(&mut self, playing: bool) -> Result<(), MediaError> {
+        match self.get_playback_state().await? {
+            Some(state) => {
+                if state.is_playing != playing {
+                    if playing {
+                        self.set_playback_play(&state.device.id).await?;
+                    } else {
+                        self.set_playback_pause(&state.device.id).await?;
                     }
                 }
-            };
+            }
+            None => {
+                let device_id = self.active_device_id().await?;
+
+                self.set_playback_active(&device_id, playing).await?;
+            }
         }
 
-        return Err(SpotifyAuthError::FAILED);
+        Ok(())
     }
+}
 
-    pub async fn set_playback_previous(&mut self, device_id: &String) -> Result<(), SpotifyAuthError> {
-        if !&self.active {
-            return Err(SpotifyAuthError::NotInitialized)
-        }
+#[async_trait]
+impl MediaSource for Spotify {
+    async fn now_playing(&mut self) -> Result<Option<NowPlaying>, MediaError> {
+        Ok(Spotify::now_playing(self).await?.map(NowPlaying::from))
+    }
 
-        for _ in 0..AUTH_ATTEMPTS {
-            match set_spotify_playback_previous(&self.http, &self.token, &device_id).await {
-                Ok(_) => {
-                    return Ok(());
-                }
-                Err(_) => {
-                    match self.authenticate().await {
-                        Ok(_) => {
-                            continue;
-                        }
-                        Err(_) => {
-                            return Err(SpotifyAuthError::FAILED);
-                        }
-                    }
-                }
-            };
-        }
+    async fn play(&mut self) -> Result<(), MediaError> {
+        self.set_playing(true).await
+    }
 
-        return Err(SpotifyAuthError::FAILED);
+    async fn pause(&mut self) -> Result<(), MediaError> {
+        self.set_playing(false).await
     }
 
-    pub async fn set_playback_pause(&mut self, device_id: &String) -> Result<(), SpotifyAuthError> {
-        if !&self.active {
-            return Err(SpotifyAuthError::NotInitialized)
-        }
+    async fn next(&mut self) -> Result<(), MediaError> {
+        let device_id = self.active_device_id().await?;
 
-        for _ in 0..AUTH_ATTEMPTS {
-            match set_spotify_playback_stop(&self.http, &self.token, &device_id).await {
-                Ok(_) => {
-                    return Ok(());
-                }
-                Err(_) => {
-                    match self.authenticate().await {
-                        Ok(_) => {
-                            continue;
-                        }
-                        Err(_) => {
-                            return Err(SpotifyAuthError::FAILED);
-                        }
-                    }
-                }
-            };
+        Ok(self.set_playback_next(&device_id).await?)
+    }
+
+    async fn previous(&mut self) -> Result<(), MediaError> {
+        let device_id = self.active_device_id().await?;
+
+        Ok(self.set_playback_previous(&device_id).await?)
+    }
+
+    async fn set_volume(&mut self, volume: f32) -> Result<(), MediaError> {
+        let device_id = self.active_device_id().await?;
+
+        Ok(Spotify::set_volume(self, &device_id, (volume * 100_f32) as u16).await?)
+    }
+
+    async fn seek_by(&mut self, offset_ms: i64) -> Result<(), MediaError> {
+        match self.get_playback_state().await? {
+            Some(state) => Ok(self.set_seek(&state.device.id, (state.progress_ms + offset_ms).max(0)).await?),
+            None => Err(MediaError::NoDevice)
         }
+    }
+
+    async fn devices(&mut self) -> Result<Vec<MediaDevice>, MediaError> {
+        let devices = self.get_devices().await?;
+
+        Ok(devices.devices.iter().map(MediaDevice::from).collect())
+    }
 
-        return Err(SpotifyAuthError::FAILED);
+    fn rate_limited(&self) -> bool {
+        self.rate_limiter.is_limited()
     }
 }
 
+#[derive(Debug)]
 pub enum SpotifyAuthError {
-    FAILED, NotInitialized, ConfigNotInitialized
+    /// The request to Spotify itself failed.
+    Request(RequestError),
+    /// No successful authentication has happened yet.
+    NotInitialized,
+    /// The client credentials or refresh token are missing from the config.
+    ConfigNotInitialized,
+    /// A PKCE callback arrived without a matching `/setup` visit, so there's no code verifier.
+    MissingVerifier,
+    /// The credentials file couldn't be read or written.
+    Credentials(CredentialsError)
+}
+
+impl Display for SpotifyAuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpotifyAuthError::Request(err) => write!(f, "{}", err),
+            SpotifyAuthError::NotInitialized => write!(f, "Spotify isn't authenticated yet"),
+            SpotifyAuthError::ConfigNotInitialized => write!(f, "Spotify hasn't been set up, visit /setup first"),
+            SpotifyAuthError::MissingVerifier => write!(f, "no authorization is in progress, start again from /setup"),
+            SpotifyAuthError::Credentials(err) => write!(f, "{}", err)
+        }
+    }
+}
+
+impl std::error::Error for SpotifyAuthError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SpotifyAuthError::Request(err) => Some(err),
+            SpotifyAuthError::Credentials(err) => Some(err),
+            _ => None
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+use std::time::Duration;
+use log::warn;
+use rosc::OscType;
+use crate::entities::config::{ConfigFileGeneralOsc, ConfigFileOscTarget, OscOutput};
+use crate::managers::transport::OscSend;
+use crate::managers::vrchat::Vrchat;
+use crate::utils::osc::encode_packet;
+
+pub struct OscTarget {
+    address: String,
+    outputs: Vec<OscOutput>,
+    /// Prefix replacements, longest prefix first.
+    remap: Vec<(String, String)>,
+    /// Set when the target is VRChat, avatar parameters it lacks aren't sent.
+    vrchat: Option<Arc<Vrchat>>
+}
+
+impl From<&ConfigFileOscTarget> for OscTarget {
+    fn from(target: &ConfigFileOscTarget) -> Self {
+        let mut remap: Vec<(String, String)> = target.remap.iter()
+            .map(|(from, to)| (String::from(from), String::from(to)))
+            .collect();
+
+        remap.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+
+        Self {
+            address: String::from(&target.address),
+            outputs: target.outputs.clone(),
+            remap,
+            vrchat: None
+        }
+    }
+}
+
+impl OscTarget {
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn wants(&self, output: OscOutput) -> bool {
+        self.outputs.contains(&output)
+    }
+
+    /// The address as this target expects it, with the longest matching prefix replaced.
+    pub fn remap(&self, address: &str) -> String {
+        for (from, to) in &self.remap {
+            if let Some(rest) = address.strip_prefix(from.as_str()) {
+                return format!("{}{}", to, rest);
+            }
+        }
+
+        address.to_string()
+    }
+}
+
+/// Sends every output to each target that wants it.
+pub struct OscTargets {
+    targets: Vec<OscTarget>,
+    /// No targets are configured, only `client_address`.
+    client_address: bool
+}
+
+impl OscTargets {
+    pub fn new(osc: &ConfigFileGeneralOsc) -> Self {
+        Self {
+            targets: osc.targets().iter().map(OscTarget::from).collect(),
+            client_address: osc.targets.is_empty()
+        }
+    }
+
+    /// Sends to VRChat where OSCQuery found it instead of `client_address`.
+    /// Configured targets are kept as they are, the one at VRChat's address only gets the avatar's parameters.
+    pub fn with_vrchat(mut self, vrchat: Option<Arc<Vrchat>>) -> Self {
+        let vrchat = match vrchat {
+            Some(vrchat) => vrchat,
+            None => return self
+        };
+
+        for target in &mut self.targets {
+            if self.client_address {
+                target.address = String::from(&vrchat.osc_address);
+            }
+
+            if target.address == vrchat.osc_address {
+                target.vrchat = Some(vrchat.clone());
+            }
+        }
+
+        self
+    }
+
+    pub fn targets(&self) -> &[OscTarget] {
+        &self.targets
+    }
+
+    /// Sends the message to the targets wanting `output`, then waits `delay` so receivers aren't flooded.
+    /// A target that can't be reached doesn't keep the others from getting the message.
+    /// An empty address, as given or once remapped, turns the output off.
+    pub async fn send(&self, sock: &impl OscSend, output: OscOutput, address: &str, args: Vec<OscType>, delay: Duration) {
+        if address.is_empty() {
+            return;
+        }
+
+        let mut sent = false;
+
+        for target in self.targets.iter().filter(|target| target.wants(output)) {
+            let address = target.remap(address);
+
+            if address.is_empty() {
+                continue;
+            }
+
+            if let Some(vrchat) = &target.vrchat {
+                if !vrchat.has_parameter(&address) {
+                    continue;
+                }
+            }
+
+            let buf = match encode_packet(address, args.clone()) {
+                Ok(buf) => buf,
+                Err(err) => {
+                    warn!("Couldn't encode the OSC message for {}: {}", target.address, err);
+                    continue;
+                }
+            };
+
+            if let Err(err) = sock.send_packet(&buf, &target.address).await {
+                warn!("Couldn't send OSC to {}: {}", target.address, err);
+            }
+
+            sent = true;
+        }
+
+        if sent {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use log::{info, warn};
+use reqwest::Client;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+use crate::config::config::Config;
+use crate::config::credentials::{Credentials, CredentialsStore};
+use crate::entities::config::ConfigFile;
+use crate::http::spotify::{authenticate_spotify, refresh_authenticate_spotify};
+use crate::managers::spotify::SpotifyAuthError;
+
+/// How long before expiry a token gets refreshed, capped at half its lifetime.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+const REFRESH_RETRY: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct TokenState {
+    access_token: String,
+    refresh_at: Option<Instant>,
+    store: Option<CredentialsStore>,
+    credentials: Credentials
+}
+
+/// Owns the Spotify access token, refreshing it before it expires.
+///
+/// The state lock is held for the whole refresh, so concurrent callers wait on
+/// the refresh already in flight instead of starting their own.
+pub struct TokenManager {
+    http: Arc<Client>,
+    config: Arc<Mutex<Config<ConfigFile>>>,
+    /// Profile whose tokens are managed, the first one when `None`.
+    profile: Option<String>,
+    state: Mutex<TokenState>,
+    updated: Notify
+}
+
+impl TokenManager {
+    pub fn new(http: Arc<Client>, config: Arc<Mutex<Config<ConfigFile>>>, profile: Option<String>) -> Self {
+        Self {
+            http,
+            config,
+            profile,
+            state: Mutex::new(TokenState::default()),
+            updated: Notify::new()
+        }
+    }
+
+    fn refresh_at(expires_in: i32) -> Instant {
+        let expires_in = Duration::from_secs(expires_in.max(0) as u64);
+
+        Instant::now() + expires_in - REFRESH_MARGIN.min(expires_in / 2)
+    }
+
+    fn update(&self, state: &mut TokenState, access_token: String, expires_in: i32) -> Result<(), SpotifyAuthError> {
+        state.access_token = String::from(&access_token);
+        state.refresh_at = Some(Self::refresh_at(expires_in));
+        state.credentials.token = access_token;
+
+        self.updated.notify_one();
+
+        Self::save(state)
+    }
+
+    fn save(state: &TokenState) -> Result<(), SpotifyAuthError> {
+        match &state.store {
+            Some(store) => store.save(&state.credentials).map_err(SpotifyAuthError::Credentials),
+            None => Ok(())
+        }
+    }
+
+    /// Opens the credentials store on first use, moving tokens still kept in `config.toml` into it.
+    fn open(&self, state: &mut TokenState, config: &mut Config<ConfigFile>) -> Result<(), SpotifyAuthError> {
+        if state.store.is_some() {
+            return Ok(())
+        }
+
+        let store = CredentialsStore::from_config(&config.cfg, self.profile.as_deref());
+        let mut credentials = store.load().map_err(SpotifyAuthError::Credentials)?;
+
+        // Tokens from before profiles existed belong to the first one.
+        let first = match &self.profile {
+            Some(profile) => config.cfg.spotify.profiles()[0].name == *profile,
+            None => true
+        };
+
+        let legacy = &mut config.cfg.spotify;
+
+        if first && (!legacy.token.is_empty() || !legacy.refresh_token.is_empty() || !legacy.scope.is_empty()) {
+            if credentials.refresh_token.is_empty() {
+                credentials = Credentials {
+                    token: std::mem::take(&mut legacy.token),
+                    refresh_token: std::mem::take(&mut legacy.refresh_token),
+                    scope: std::mem::take(&mut legacy.scope)
+                };
+
+                store.save(&credentials).map_err(SpotifyAuthError::Credentials)?;
+
+                info!("Moved the Spotify tokens from the config into {}", store.path().display());
+            } else {
+                info!("Dropped the Spotify tokens left in the config, {} already has tokens", store.path().display());
+            }
+
+            legacy.token.clear();
+            legacy.refresh_token.clear();
+            legacy.scope.clear();
+
+            config.write();
+        }
+
+        state.store = Some(store);
+        state.credentials = credentials;
+
+        Ok(())
+    }
+
+    /// Loads the stored credentials, migrating them out of the config if needed.
+    pub async fn load(&self) -> Result<(), SpotifyAuthError> {
+        let mut state = self.state.lock().await;
+        let mut config = self.config.lock().await;
+
+        self.open(&mut state, &mut config)
+    }
+
+    /// Scopes granted with the current token.
+    pub async fn scope(&self) -> Result<String, SpotifyAuthError> {
+        self.load().await?;
+
+        Ok(String::from(&self.state.lock().await.credentials.scope))
+    }
+
+    /// Returns a usable access token, refreshing it first when it's about to expire.
+    pub async fn access_token(&self) -> Result<String, SpotifyAuthError> {
+        let mut state = self.state.lock().await;
+
+        match state.refresh_at {
+            Some(refresh_at) if refresh_at > Instant::now() => Ok(String::from(&state.access_token)),
+            _ => self.refresh_locked(&mut state).await
+        }
+    }
+
+    /// Refreshes the access token after `rejected` was turned down by Spotify.
+    /// If another caller already replaced that token, the new one is returned without another round trip.
+    pub async fn refresh(&self, rejected: Option<&str>) -> Result<String, SpotifyAuthError> {
+        let mut state = self.state.lock().await;
+
+        if let Some(rejected) = rejected {
+            if !state.access_token.is_empty() && state.access_token != rejected {
+                return Ok(String::from(&state.access_token));
+            }
+        }
+
+        self.refresh_locked(&mut state).await
+    }
+
+    async fn refresh_locked(&self, state: &mut TokenState) -> Result<String, SpotifyAuthError> {
+        // The config is only needed up front, everything else waiting on it shouldn't wait for Spotify as well.
+        let (accounts_url, auth) = {
+            let mut config = self.config.lock().await;
+
+            self.open(state, &mut config)?;
+
+            if state.credentials.refresh_token.is_empty() || config.cfg.spotify.client_id.is_empty() {
+                return Err(SpotifyAuthError::ConfigNotInitialized)
+            }
+
+            (String::from(&config.cfg.spotify.accounts_url), config.cfg.get_client_auth())
+        };
+
+        let res = refresh_authenticate_spotify(&self.http, &accounts_url, String::from(&state.credentials.refresh_token), &auth).await
+            .map_err(SpotifyAuthError::Request)?;
+
+        state.credentials.scope = res.scope;
+
+        // Spotify may rotate the refresh token, the old one stops working once it does.
+        if let Some(refresh_token) = res.refresh_token {
+            state.credentials.refresh_token = refresh_token;
+        }
+
+        self.update(state, res.access_token, res.expires_in)?;
+
+        Ok(String::from(&state.access_token))
+    }
+
+    /// Exchanges an authorization code from `/callback` for a fresh pair of tokens.
+    /// The PKCE flow also needs the verifier generated when the authorization started.
+    pub async fn exchange(&self, code: &String, verifier: Option<String>) -> Result<(), SpotifyAuthError> {
+        let mut state = self.state.lock().await;
+
+        let (accounts_url, callback_url, auth) = {
+            let mut config = self.config.lock().await;
+
+            self.open(&mut state, &mut config)?;
+
+            if config.cfg.spotify.client_id.is_empty() || config.cfg.spotify.callback_url.is_empty() {
+                return Err(SpotifyAuthError::ConfigNotInitialized)
+            }
+
+            if config.cfg.uses_pkce() && verifier.is_none() {
+                return Err(SpotifyAuthError::MissingVerifier)
+            }
+
+            (String::from(&config.cfg.spotify.accounts_url), String::from(&config.cfg.spotify.callback_url), config.cfg.get_client_auth())
+        };
+
+        let res = authenticate_spotify(&self.http, &accounts_url, code, callback_url, &auth, verifier).await
+            .map_err(SpotifyAuthError::Request)?;
+
+        state.credentials.refresh_token = res.refresh_token;
+        state.credentials.scope = res.scope;
+
+        self.update(&mut state, res.access_token, res.expires_in)
+    }
+
+    /// Keeps the token fresh in the background, refreshing it shortly before it expires.
+    pub fn spawn_refresher(self: &Arc<Self>) -> JoinHandle<()> {
+        tokio::task::spawn({
+            let tokens = self.clone();
+
+            async move {
+                loop {
+                    let refresh_at = tokens.state.lock().await.refresh_at;
+
+                    match refresh_at {
+                        Some(refresh_at) => {
+                            tokio::select! {
+                                _ = tokio::time::sleep_until(refresh_at.into()) => {}
+                                _ = tokens.updated.notified() => {
+                                    continue;
+                                }
+                            }
+                        }
+                        None => {
+                            tokens.updated.notified().await;
+                            continue;
+                        }
+                    }
+
+                    let mut state = tokens.state.lock().await;
+
+                    // Someone else may have refreshed while we waited for the lock.
+                    if state.refresh_at.map(|refresh_at| refresh_at > Instant::now()).unwrap_or(false) {
+                        continue;
+                    }
+
+                    match tokens.refresh_locked(&mut state).await {
+                        Ok(_) => {
+                            info!("Spotify access token refreshed");
+                        }
+                        Err(err) => {
+                            warn!("Couldn't refresh the Spotify access token, retrying in {}s: {}", REFRESH_RETRY.as_secs(), err);
+
+                            state.refresh_at = Some(Instant::now() + REFRESH_RETRY);
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::utils::slip::{slip_encode, SlipDecoder};
+
+pub const TCP_SCHEME: &str = "tcp://";
+pub const UDP_SCHEME: &str = "udp://";
+
+/// Larger frames are dropped, so a peer that never sends an `END` can't use up the memory.
+pub const MAX_TCP_PACKET: usize = 64 * 1024;
+
+const TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A peer that doesn't read for this long is disconnected.
+const TCP_WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Packets waiting for a TCP target, further ones are dropped while it's full.
+const TCP_QUEUE: usize = 64;
+
+/// A received packet and who sent it.
+pub type OscPacketSender = mpsc::Sender<(Vec<u8>, SocketAddr)>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OscTransport {
+    Udp,
+    Tcp
+}
+
+/// Splits `tcp://host:port` into its transport and address, addresses without a scheme are UDP.
+pub fn parse_address(address: &str) -> (OscTransport, &str) {
+    if let Some(address) = address.strip_prefix(TCP_SCHEME) {
+        (OscTransport::Tcp, address)
+    } else {
+        (OscTransport::Udp, address.strip_prefix(UDP_SCHEME).unwrap_or(address))
+    }
+}
+
+/// Sends an encoded OSC packet to an address as [`parse_address`] reads it.
+#[async_trait]
+pub trait OscSend: Sync {
+    async fn send_packet(&self, buf: &[u8], address: &str) -> std::io::Result<()>;
+}
+
+#[async_trait]
+impl OscSend for UdpSocket {
+    async fn send_packet(&self, buf: &[u8], address: &str) -> std::io::Result<()> {
+        match parse_address(address) {
+            (OscTransport::Udp, address) => self.send_to(buf, address).await.map(|_| ()),
+            (OscTransport::Tcp, _) => Err(std::io::Error::new(ErrorKind::Unsupported, "a UDP socket can't send over TCP"))
+        }
+    }
+}
+
+/// Sends over the app's UDP socket, or SLIP framed over a TCP connection kept open per address.
+/// Each TCP target gets its own task and queue, so a slow one doesn't hold up the sender.
+pub struct OscSender {
+    udp: Arc<UdpSocket>,
+    tcp: std::sync::Mutex<HashMap<String, mpsc::Sender<Vec<u8>>>>
+}
+
+impl OscSender {
+    pub fn new(udp: Arc<UdpSocket>) -> Self {
+        Self {
+            udp,
+            tcp: std::sync::Mutex::new(HashMap::new())
+        }
+    }
+
+    /// Queues the packet for the connection to `address`, dropping it right away when the queue is full.
+    /// The connection's task logs when it stops and starts working, the dropped packets are only logged at debug.
+    fn send_tcp(&self, buf: &[u8], address: &str) -> std::io::Result<()> {
+        let mut connections = self.tcp.lock().unwrap();
+
+        let queue = connections.entry(address.to_string())
+            .or_insert_with(|| task_tcp_connection(address.to_string()));
+
+        match queue.try_send(slip_encode(buf)) {
+            Ok(_) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                debug!("Dropped an OSC packet for {}, too many are waiting for the connection", address);
+                Ok(())
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                connections.remove(address);
+                Err(std::io::Error::new(ErrorKind::BrokenPipe, "the connection went away"))
+            }
+        }
+    }
+}
+
+/// Writes the queued frames to `address`, connecting again after a failure.
+/// Stops once the sender is dropped.
+fn task_tcp_connection(address: String) -> mpsc::Sender<Vec<u8>> {
+    let (queue, mut frames) = mpsc::channel::<Vec<u8>>(TCP_QUEUE);
+
+    tokio::task::spawn({
+        async move {
+            let mut connection: Option<OwnedWriteHalf> = None;
+            // Only changes are logged, so an absent or stalled target doesn't flood the log.
+            let mut failing = false;
+
+            while let Some(frame) = frames.recv().await {
+                if connection.is_none() {
+                    match connect(&address).await {
+                        Ok(connected) => connection = Some(connected),
+                        Err(err) => {
+                            if !failing {
+                                warn!("Couldn't connect to {} for OSC: {}", address, err);
+                                failing = true;
+                            }
+
+                            debug!("Dropped an OSC packet for {}, it isn't connected", address);
+                            continue;
+                        }
+                    }
+                }
+
+                if let Some(writer) = &mut connection {
+                    let res = match tokio::time::timeout(TCP_WRITE_TIMEOUT, writer.write_all(&frame)).await {
+                        Ok(res) => res,
+                        Err(_) => Err(std::io::Error::new(ErrorKind::TimedOut, "the other side stopped reading"))
+                    };
+
+                    match res {
+                        Ok(_) => {
+                            if failing {
+                                info!("Sending OSC to {} again", address);
+                                failing = false;
+                            }
+                        }
+                        Err(err) => {
+                            if !failing {
+                                warn!("Lost the OSC connection to {}: {}", address, err);
+                                failing = true;
+                            }
+
+                            connection = None;
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    queue
+}
+
+async fn connect(address: &str) -> std::io::Result<OwnedWriteHalf> {
+    let stream = tokio::time::timeout(TCP_CONNECT_TIMEOUT, TcpStream::connect(address)).await
+        .map_err(|_| std::io::Error::new(ErrorKind::TimedOut, "connecting timed out"))??;
+
+    stream.set_nodelay(true)?;
+
+    // Nothing is read from targets, only the write half is kept.
+    let (_, connection) = stream.into_split();
+
+    Ok(connection)
+}
+
+#[async_trait]
+impl OscSend for OscSender {
+    async fn send_packet(&self, buf: &[u8], address: &str) -> std::io::Result<()> {
+        match parse_address(address) {
+            (OscTransport::Udp, address) => self.udp.send_to(buf, address).await.map(|_| ()),
+            (OscTransport::Tcp, address) => self.send_tcp(buf, address)
+        }
+    }
+}
+
+/// Passes every datagram received on `sock` on to `packets`, until nobody receives them anymore.
+pub fn spawn_udp_receiver(sock: Arc<UdpSocket>, packets: OscPacketSender) -> JoinHandle<()> {
+    tokio::task::spawn({
+        async move {
+            let mut buf = [0u8; rosc::decoder::MTU];
+
+            loop {
+                if let Ok((size, from)) = sock.recv_from(&mut buf).await {
+                    if packets.send((buf[..size].to_vec(), from)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Accepts OSC 1.1 connections, each carrying SLIP framed packets.
+pub struct OscTcpListener {
+    listener: TcpListener
+}
+
+impl OscTcpListener {
+    pub async fn bind(address: &str) -> std::io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(parse_address(address).1).await?
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Passes the packets of every connection on to `packets`, until nobody receives them anymore.
+    pub fn spawn(self, packets: OscPacketSender) -> JoinHandle<()> {
+        tokio::task::spawn({
+            async move {
+                loop {
+                    let (stream, from) = match self.listener.accept().await {
+                        Ok(connection) => connection,
+                        Err(err) => {
+                            warn!("Couldn't accept an OSC connection: {}", err);
+                            continue;
+                        }
+                    };
+
+                    if packets.is_closed() {
+                        return;
+                    }
+
+                    task_read_connection(stream, from, packets.clone());
+                }
+            }
+        })
+    }
+}
+
+fn task_read_connection(mut stream: TcpStream, from: SocketAddr, packets: OscPacketSender) -> JoinHandle<()> {
+    tokio::task::spawn({
+        async move {
+            let mut decoder = SlipDecoder::new(MAX_TCP_PACKET);
+            let mut buf = [0u8; 4096];
+
+            loop {
+                let size = match stream.read(&mut buf).await {
+                    Ok(0) => return,
+                    Ok(size) => size,
+                    Err(err) => {
+                        warn!("Lost the OSC connection from {}: {}", from, err);
+                        return;
+                    }
+                };
+
+                for packet in decoder.push(&buf[..size]) {
+                    if packets.send((packet, from)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    })
+}
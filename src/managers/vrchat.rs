@@ -0,0 +1,211 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use arc_swap::ArcSwapOption;
+use log::{info, warn};
+use mdns_sd::{IfKind, ServiceDaemon, ServiceEvent};
+use reqwest::{Client, Url};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use crate::config::config::Config;
+use crate::entities::config::ConfigFile;
+use crate::entities::oscquery::OscQueryNode;
+use crate::http::oscquery::{fetch_oscquery_host_info, fetch_oscquery_node};
+use crate::managers::avatar::CurrentAvatar;
+use crate::managers::oscquery::OSCJSON_SERVICE;
+
+/// How often the avatar's parameters are read again while VRChat is around.
+const AVATAR_REFRESH: Duration = Duration::from_secs(10);
+
+/// A running VRChat client found over OSCQuery.
+pub struct Vrchat {
+    /// Where its OSCQuery server answers.
+    pub base_url: String,
+    /// Where it receives OSC.
+    pub osc_address: String,
+    /// The current avatar's `/avatar` tree, `None` when it couldn't be read.
+    pub avatar: Option<OscQueryNode>
+}
+
+impl Vrchat {
+    /// Whether the current avatar has the parameter at `address`.
+    /// Addresses outside `/avatar/`, or an avatar whose parameters are unknown, count as present.
+    pub fn has_parameter(&self, address: &str) -> bool {
+        if !address.starts_with("/avatar/") {
+            return true
+        }
+
+        match &self.avatar {
+            Some(avatar) => avatar.find(&address["/avatar".len()..]).is_some(),
+            None => true
+        }
+    }
+}
+
+/// Keeps track of the VRChat client on this network, if any.
+#[derive(Default)]
+pub struct VrchatDiscovery {
+    current: ArcSwapOption<Vrchat>
+}
+
+impl VrchatDiscovery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self) -> Option<Arc<Vrchat>> {
+        self.current.load_full()
+    }
+
+    pub fn forget(&self) {
+        self.current.store(None);
+    }
+
+    /// Reads where VRChat receives OSC and the current avatar from its OSCQuery server at `base_url`.
+    pub async fn connect(&self, http: &Client, base_url: &str) -> Result<Arc<Vrchat>, reqwest::Error> {
+        let host_info = fetch_oscquery_host_info(http, base_url).await?;
+
+        // VRChat may listen everywhere, it's reachable where its OSCQuery server is then.
+        let osc_ip = match host_info.osc_ip.parse::<IpAddr>() {
+            Ok(ip) if !ip.is_unspecified() => host_info.osc_ip,
+            _ => Url::parse(base_url).ok()
+                .and_then(|url| url.host_str().map(String::from))
+                .unwrap_or_else(|| "127.0.0.1".to_string())
+        };
+
+        let vrchat = Arc::new(Vrchat {
+            base_url: base_url.to_string(),
+            osc_address: format!("{}:{}", osc_ip, host_info.osc_port),
+            avatar: fetch_oscquery_node(http, base_url, "/avatar").await?
+        });
+
+        self.current.store(Some(vrchat.clone()));
+
+        Ok(vrchat)
+    }
+
+    /// Reads the current avatar's parameters again, returning whether they changed.
+    pub async fn refresh_avatar(&self, http: &Client) -> Result<bool, reqwest::Error> {
+        let current = match self.get() {
+            Some(current) => current,
+            None => return Ok(false)
+        };
+
+        let avatar = fetch_oscquery_node(http, &current.base_url, "/avatar").await?;
+
+        if avatar == current.avatar {
+            return Ok(false)
+        }
+
+        self.current.store(Some(Arc::new(Vrchat {
+            base_url: String::from(&current.base_url),
+            osc_address: String::from(&current.osc_address),
+            avatar
+        })));
+
+        Ok(true)
+    }
+
+    /// Browses mDNS for VRChat, following it as it comes and goes and as avatars change.
+    pub fn spawn(self: &Arc<Self>, http: Arc<Client>, config: Arc<Mutex<Config<ConfigFile>>>, avatar: Arc<CurrentAvatar>) -> JoinHandle<()> {
+        tokio::task::spawn({
+            let discovery = self.clone();
+
+            async move {
+                let daemon = match ServiceDaemon::new() {
+                    Ok(daemon) => daemon,
+                    Err(err) => {
+                        warn!("Can't look for VRChat over mDNS: {}", err);
+                        return;
+                    }
+                };
+
+                if let Err(err) = daemon.enable_interface(IfKind::LoopbackV4) {
+                    warn!("Can't look for VRChat on this machine over mDNS: {}", err);
+                }
+
+                let events = match daemon.browse(OSCJSON_SERVICE) {
+                    Ok(events) => events,
+                    Err(err) => {
+                        warn!("Can't look for VRChat over mDNS: {}", err);
+                        return;
+                    }
+                };
+
+                let mut refresh = tokio::time::interval(AVATAR_REFRESH);
+
+                loop {
+                    tokio::select! {
+                        event = events.recv_async() => {
+                            let prefix = String::from(&config.lock().await.cfg.general.oscquery.vrchat_service);
+
+                            match event {
+                                Ok(ServiceEvent::ServiceResolved(service)) if service.get_fullname().starts_with(&prefix) => {
+                                    let ip = match service.get_addresses_v4().into_iter().next() {
+                                        Some(ip) => ip.to_string(),
+                                        None => continue
+                                    };
+
+                                    let base_url = format!("http://{}:{}", ip, service.get_port());
+
+                                    match discovery.connect(&http, &base_url).await {
+                                        Ok(vrchat) => {
+                                            info!("Found VRChat at {}, sending OSC to {}", base_url, vrchat.osc_address);
+
+                                            warn_missing_parameters(&vrchat, &config, &avatar).await;
+                                        }
+                                        Err(err) => {
+                                            warn!("Found VRChat at {} but couldn't query it: {}", base_url, err);
+                                        }
+                                    }
+                                }
+                                Ok(ServiceEvent::ServiceRemoved(_, fullname)) if fullname.starts_with(&prefix) => {
+                                    info!("VRChat went away, sending OSC to the configured addresses again");
+
+                                    discovery.forget();
+                                }
+                                Ok(_) => {}
+                                Err(_) => {
+                                    warn!("Stopped looking for VRChat, the mDNS daemon went away");
+                                    return;
+                                }
+                            }
+                        }
+                        _ = refresh.tick() => {
+                            match discovery.refresh_avatar(&http).await {
+                                Ok(true) => {
+                                    if let Some(vrchat) = discovery.get() {
+                                        warn_missing_parameters(&vrchat, &config, &avatar).await;
+                                    }
+                                }
+                                Ok(false) => {}
+                                Err(err) => {
+                                    warn!("Couldn't read the avatar's parameters from VRChat: {}", err);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Warns about the parameters the avatar lacks, those aren't sent while it's worn.
+pub async fn warn_missing_parameters(vrchat: &Vrchat, config: &Arc<Mutex<Config<ConfigFile>>>, avatar: &CurrentAvatar) {
+    if vrchat.avatar.is_none() {
+        return;
+    }
+
+    let config = config.lock().await;
+    let parameters = &config.cfg.parameters_for(avatar.get().as_deref().map(String::as_str));
+
+    let missing: Vec<&str> = [&parameters.spotify_playing, &parameters.spotify_seek].into_iter()
+        .filter(|address| !address.is_empty() && !vrchat.has_parameter(address))
+        .map(|address| address.as_str())
+        .collect();
+
+    if !missing.is_empty() {
+        warn!("The current avatar doesn't have {}, those won't be sent to VRChat until you switch to an avatar that does", missing.join(", "));
+    }
+}
@@ -1,15 +1,26 @@
 use std::sync::Arc;
-use reqwest::Client;
 use tokio::sync::Mutex;
 use crate::config::config::Config;
 use crate::entities::config::ConfigFile;
-use crate::managers::spotify::Spotify;
+use crate::entities::oscquery::OscQueryHostInfo;
+use crate::managers::authorization::Authorizations;
+use crate::managers::avatar::CurrentAvatar;
+use crate::managers::profiles::SpotifyProfiles;
 
 pub mod spotify;
+pub mod oscquery;
 
 #[derive(Clone)]
 pub struct WebData {
-    pub client: Arc<Client>,
     pub config: Arc<Mutex<Config<ConfigFile>>>,
-    pub spotify: Arc<Mutex<Spotify>>
-}
\ No newline at end of file
+    pub spotify: Arc<Mutex<SpotifyProfiles>>,
+    pub authorizations: Arc<Authorizations>
+}
+
+#[derive(Clone)]
+pub struct OscQueryData {
+    pub config: Arc<Mutex<Config<ConfigFile>>>,
+    /// The tree lists the addresses of the avatar worn.
+    pub avatar: Arc<CurrentAvatar>,
+    pub host_info: OscQueryHostInfo
+}
@@ -0,0 +1,31 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde_json::json;
+use crate::managers::oscquery::oscquery_tree;
+use crate::routes::OscQueryData;
+
+/// Answers OSCQuery requests: `?HOST_INFO`, any node of the address tree, or a single attribute of one like `?TYPE`.
+pub async fn oscquery(req: HttpRequest, data: web::Data<OscQueryData>) -> HttpResponse {
+    let attribute = req.query_string();
+
+    if attribute == "HOST_INFO" {
+        return HttpResponse::Ok().json(&data.host_info)
+    }
+
+    let tree = oscquery_tree(&data.config.lock().await.cfg, data.avatar.get().as_deref().map(String::as_str));
+
+    let node = match tree.find(req.path()) {
+        Some(node) => node,
+        None => {
+            return HttpResponse::NotFound().finish()
+        }
+    };
+
+    if attribute.is_empty() {
+        return HttpResponse::Ok().json(node)
+    }
+
+    match serde_json::to_value(node).unwrap().get(attribute) {
+        Some(value) => HttpResponse::Ok().json(json!({ attribute: value })),
+        None => HttpResponse::NoContent().finish()
+    }
+}
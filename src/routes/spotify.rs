@@ -1,35 +1,122 @@
-use actix_web::{HttpResponse, Responder, web, get};
+use actix_web::{HttpResponse, Responder, web, get, post};
+use log::{error, warn};
 use reqwest::header;
+use serde::Deserialize;
 use crate::entities::spotify::{SpotifyCallbackQuery};
+use crate::managers::profiles::SpotifyProfiles;
 use crate::routes::WebData;
 
+#[derive(Debug, Deserialize)]
+pub struct ProfileQuery {
+    pub profile: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileForm {
+    pub profile: String
+}
+
 #[get("/callback")]
 pub async fn spotify_callback(query: web::Query<SpotifyCallbackQuery>, data: web::Data<WebData>) -> impl Responder {
-    let spotify = data.spotify.clone();
+    if let Some(err) = &query.error {
+        data.authorizations.cancel(query.state.as_deref());
+
+        return if err == "access_denied" {
+            HttpResponse::Ok().body("Authorization was cancelled, nothing has been changed. Visit /setup to try again.")
+        } else {
+            error!("Spotify returned an error on the callback: {}", err);
+
+            HttpResponse::BadRequest().body(format!("Spotify couldn't authorize the app ({}), visit /setup to try again.", err))
+        }
+    }
 
-    let mut spotify = spotify.lock().await;
+    let authorization = match data.authorizations.finish(query.state.as_deref()) {
+        Ok(authorization) => authorization,
+        Err(err) => {
+            warn!("Rejected a Spotify callback: {}", err);
 
-    match spotify.init_credentials(&query.code).await {
+            return HttpResponse::BadRequest().body(format!("This login link isn't valid: {}. Visit /setup to start over.", err))
+        }
+    };
+
+    let code = match &query.code {
+        Some(code) => code,
+        None => {
+            return HttpResponse::BadRequest().body("The callback is missing the authorization code, visit /setup to start over.")
+        }
+    };
+
+    let mut profiles = data.spotify.lock().await;
+
+    let spotify = match &authorization.profile {
+        Some(profile) => match profiles.get_mut(profile) {
+            Some(spotify) => spotify,
+            None => {
+                return HttpResponse::BadRequest().body(format!("The profile {} doesn't exist anymore.", profile))
+            }
+        },
+        None => profiles.active()
+    };
+
+    match spotify.init_credentials(code, authorization.verifier).await {
         Ok(_) => {
-            HttpResponse::Ok().body("You're now authenticated, token has been saved into the configuration file.")
+            HttpResponse::Ok().body("You're now authenticated, the token has been saved.")
         }
-        Err(_) => {
-            HttpResponse::Ok().body("Something went wrong, try again UnU")
+        Err(err) => {
+            error!("Couldn't exchange the authorization code: {}", err);
+
+            HttpResponse::Ok().body(format!("Something went wrong, try again UnU ({})", err))
         }
     }
 }
 
 #[get("/setup")]
-pub async fn spotify_setup(data: web::Data<WebData>) -> impl Responder {
+pub async fn spotify_setup(query: web::Query<ProfileQuery>, data: web::Data<WebData>) -> impl Responder {
+    let profile = match &query.profile {
+        Some(profile) => String::from(profile),
+        None => data.spotify.lock().await.active_name()
+    };
 
     let config = data.config.clone();
 
     let config = config.lock().await;
 
-    let url = format!("https://accounts.spotify.com/authorize?response_type=code&client_id={}&scope=user-read-currently-playing&redirect_uri={}",
-                      &config.cfg.spotify.client_id,
-                      &config.cfg.spotify.callback_url);
+    if config.cfg.spotify.profile(&profile).is_none() {
+        return HttpResponse::BadRequest().body(format!("There's no Spotify profile named {}.", profile))
+    }
+
+    let (url, _) = data.authorizations.authorize_url(&config.cfg, Some(&profile));
+
+    // Not permanent, every visit needs a fresh state.
+    HttpResponse::Found().insert_header((header::LOCATION, url)).finish()
+}
+
+/// Lists the profiles.
+#[get("/profile")]
+pub async fn spotify_profiles(data: web::Data<WebData>) -> impl Responder {
+    let profiles = data.spotify.lock().await;
+
+    HttpResponse::Ok().body(profile_list(&profiles))
+}
 
+/// Switches to the posted profile, a plain link or prefetch can't.
+#[post("/profile")]
+pub async fn spotify_profile(form: web::Form<ProfileForm>, data: web::Data<WebData>) -> impl Responder {
+    let mut profiles = data.spotify.lock().await;
 
-    HttpResponse::PermanentRedirect().insert_header((header::LOCATION, url)).finish()
-}
\ No newline at end of file
+    if let Err(err) = profiles.switch(&form.profile) {
+        return HttpResponse::NotFound().body(format!("Couldn't switch profiles: {}.", err))
+    }
+
+    HttpResponse::Ok().body(profile_list(&profiles))
+}
+
+fn profile_list(profiles: &SpotifyProfiles) -> String {
+    let active = profiles.active_name();
+
+    let list: Vec<String> = profiles.names().iter().enumerate()
+        .map(|(index, name)| format!("{} {}: {}", if *name == active { "*" } else { " " }, index, name))
+        .collect();
+
+    format!("Active Spotify profile: {}\n\n{}\n", active, list.join("\n"))
+}
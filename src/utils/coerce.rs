@@ -0,0 +1,101 @@
+use rosc::OscType;
+use crate::entities::config::{default_trigger_threshold, ArgumentType, ConfigFileParameterOverride};
+
+/// Reads control arguments whatever type the sender picked.
+/// VRChat sends Int for int parameters while controllers like TouchOSC send 0.0/1.0 floats for buttons.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Coercion {
+    pub argument_type: ArgumentType,
+    pub threshold: f32
+}
+
+impl Default for Coercion {
+    fn default() -> Self {
+        Self {
+            argument_type: ArgumentType::Auto,
+            threshold: default_trigger_threshold()
+        }
+    }
+}
+
+impl From<&ConfigFileParameterOverride> for Coercion {
+    fn from(value: &ConfigFileParameterOverride) -> Self {
+        Self {
+            argument_type: value.argument_type,
+            threshold: value.threshold
+        }
+    }
+}
+
+impl Coercion {
+    fn accepts(&self, arg: &OscType) -> bool {
+        match self.argument_type {
+            ArgumentType::Auto => true,
+            ArgumentType::Bool => matches!(arg, OscType::Bool(_)),
+            ArgumentType::Int => matches!(arg, OscType::Int(_) | OscType::Long(_)),
+            ArgumentType::Float => matches!(arg, OscType::Float(_) | OscType::Double(_)),
+            ArgumentType::String => matches!(arg, OscType::String(_))
+        }
+    }
+
+    /// Whether a button argument means pressed, `None` when it can't be read as one.
+    /// Numbers are compared against the threshold, strings accept `true`/`1` and `false`/`0`.
+    pub fn trigger(&self, arg: &OscType) -> Option<bool> {
+        if !self.accepts(arg) {
+            return None
+        }
+
+        match arg {
+            OscType::Bool(value) => Some(*value),
+            OscType::Int(value) => Some(*value as f32 >= self.threshold),
+            OscType::Long(value) => Some(*value as f32 >= self.threshold),
+            OscType::Float(value) => Some(*value >= self.threshold),
+            OscType::Double(value) => Some(*value as f32 >= self.threshold),
+            OscType::String(value) => {
+                match value.trim().to_lowercase().as_str() {
+                    "true" | "1" => Some(true),
+                    "false" | "0" => Some(false),
+                    _ => None
+                }
+            }
+            _ => None
+        }
+    }
+
+    /// Volume between 0 and 1, ints are read as a percentage.
+    pub fn volume(&self, arg: &OscType) -> Option<f32> {
+        if !self.accepts(arg) {
+            return None
+        }
+
+        let volume = match arg {
+            OscType::Int(value) => *value as f32 / 100_f32,
+            OscType::Long(value) => *value as f32 / 100_f32,
+            OscType::Float(value) => *value,
+            OscType::Double(value) => *value as f32,
+            _ => return None
+        };
+
+        if volume.is_nan() {
+            return None
+        }
+
+        Some(volume.clamp(0_f32, 1_f32))
+    }
+
+    /// A list position, floats are rounded to the nearest one.
+    pub fn index(&self, arg: &OscType) -> Option<i32> {
+        if !self.accepts(arg) {
+            return None
+        }
+
+        match arg {
+            OscType::Int(value) => Some(*value),
+            OscType::Long(value) => i32::try_from(*value).ok(),
+            OscType::Float(value) if value.is_finite() => Some(value.round() as i32),
+            OscType::Double(value) if value.is_finite() => Some(value.round() as i32),
+            OscType::String(value) => value.trim().parse().ok(),
+            _ => None
+        }
+    }
+}
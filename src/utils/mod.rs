@@ -1 +1,4 @@
-pub mod osc;
\ No newline at end of file
+pub mod osc;
+pub mod pkce;
+pub mod coerce;
+pub mod slip;
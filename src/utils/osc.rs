@@ -1,6 +1,4 @@
-use std::time::Duration;
-use rosc::{encoder, OscMessage, OscPacket, OscType};
-use tokio::net::UdpSocket;
+use rosc::{decoder, encoder, OscError, OscMessage, OscPacket, OscTime, OscType};
 
 pub fn encode_packet(address: String, data: Vec<OscType>) -> rosc::Result<Vec<u8>> {
     encoder::encode(&OscPacket::Message(OscMessage {
@@ -9,7 +7,130 @@ pub fn encode_packet(address: String, data: Vec<OscType>) -> rosc::Result<Vec<u8
     }))
 }
 
-pub async fn send_to_delay(sock: &UdpSocket, buf: &[u8], address: &String, delay: Duration) {
-    sock.send_to(&buf, &address).await.unwrap();
-    tokio::time::sleep(delay).await;
-}
\ No newline at end of file
+/// Decodes a datagram into its messages, see [`flatten_packet`].
+pub fn decode_messages(buf: &[u8]) -> Result<Vec<OscMessage>, OscError> {
+    let (_, packet) = decoder::decode_udp(buf)?;
+
+    Ok(flatten_packet(packet))
+}
+
+/// Unpacks bundles recursively, returning their messages ordered by timetag.
+/// Messages sharing a timetag keep the order they were sent in.
+pub fn flatten_packet(packet: OscPacket) -> Vec<OscMessage> {
+    let mut messages = Vec::new();
+
+    collect_messages(packet, None, &mut messages);
+
+    messages.sort_by_key(|(timetag, _)| *timetag);
+
+    messages.into_iter().map(|(_, message)| message).collect()
+}
+
+fn collect_messages(packet: OscPacket, timetag: Option<OscTime>, messages: &mut Vec<(Option<OscTime>, OscMessage)>) {
+    match packet {
+        OscPacket::Message(message) => messages.push((timetag, message)),
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                collect_messages(packet, Some(bundle.timetag), messages);
+            }
+        }
+    }
+}
+
+/// Matches an address against an OSC 1.0 address pattern.
+/// `*` and `?` never cross a `/`, `[a-z]` and `[!a-z]` match a character class and `{foo,bar}` any of its strings.
+/// Malformed patterns, like an unclosed `[`, match nothing.
+pub fn matches_pattern(pattern: &str, address: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let address: Vec<char> = address.chars().collect();
+
+    match_from(&pattern, &address)
+}
+
+/// Whether the pattern uses any wildcards, plain addresses can be compared directly.
+pub fn is_pattern(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'])
+}
+
+fn match_from(pattern: &[char], address: &[char]) -> bool {
+    let Some((&first, rest)) = pattern.split_first() else {
+        return address.is_empty()
+    };
+
+    match first {
+        '*' => {
+            // Try every split up to the next path separator.
+            for skip in 0..=address.len() {
+                if match_from(rest, &address[skip..]) {
+                    return true
+                }
+
+                if skip < address.len() && address[skip] == '/' {
+                    return false
+                }
+            }
+
+            false
+        }
+        '?' => {
+            match address.split_first() {
+                Some((&c, address)) if c != '/' => match_from(rest, address),
+                _ => false
+            }
+        }
+        '[' => {
+            let Some(end) = rest.iter().position(|&c| c == ']') else {
+                return false
+            };
+
+            match address.split_first() {
+                Some((&c, address)) if c != '/' && matches_class(&rest[..end], c) => match_from(&rest[end + 1..], address),
+                _ => false
+            }
+        }
+        '{' => {
+            let Some(end) = rest.iter().position(|&c| c == '}') else {
+                return false
+            };
+
+            rest[..end].split(|&c| c == ',').any(|option| {
+                address.starts_with(option) && match_from(&rest[end + 1..], &address[option.len()..])
+            })
+        }
+        c => {
+            match address.split_first() {
+                Some((&a, address)) if a == c => match_from(rest, address),
+                _ => false
+            }
+        }
+    }
+}
+
+fn matches_class(class: &[char], c: char) -> bool {
+    let (negated, class) = match class.split_first() {
+        Some(('!', class)) => (true, class),
+        _ => (false, class)
+    };
+
+    let mut matched = false;
+    let mut i = 0;
+
+    while i < class.len() {
+        // A `-` at either end is taken literally.
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            if class[i] <= c && c <= class[i + 2] {
+                matched = true;
+            }
+
+            i += 3;
+        } else {
+            if class[i] == c {
+                matched = true;
+            }
+
+            i += 1;
+        }
+    }
+
+    matched != negated
+}
@@ -0,0 +1,37 @@
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+const VERIFIER_LENGTH: usize = 64;
+
+/// A PKCE code verifier and the S256 challenge derived from it.
+pub struct Pkce {
+    pub verifier: String,
+    pub challenge: String
+}
+
+impl Pkce {
+    pub fn new() -> Self {
+        let verifier: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(VERIFIER_LENGTH)
+            .map(char::from)
+            .collect();
+
+        Self {
+            challenge: challenge_for(&verifier),
+            verifier
+        }
+    }
+}
+
+impl Default for Pkce {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Base64url encoded SHA-256 of the verifier, as the `S256` method expects.
+pub fn challenge_for(verifier: &str) -> String {
+    base64::encode_config(Sha256::digest(verifier.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
@@ -0,0 +1,87 @@
+/// SLIP framing (RFC 1055) as OSC 1.1 uses it over stream transports.
+const END: u8 = 0xC0;
+const ESC: u8 = 0xDB;
+const ESC_END: u8 = 0xDC;
+const ESC_ESC: u8 = 0xDD;
+
+/// Frames a packet, with an `END` on both sides so line noise before it ends up in a frame of its own.
+pub fn slip_encode(packet: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(packet.len() + 2);
+
+    frame.push(END);
+
+    for byte in packet {
+        match *byte {
+            END => frame.extend_from_slice(&[ESC, ESC_END]),
+            ESC => frame.extend_from_slice(&[ESC, ESC_ESC]),
+            byte => frame.push(byte)
+        }
+    }
+
+    frame.push(END);
+
+    frame
+}
+
+/// Splits a byte stream into packets, bytes can arrive in any chunks.
+pub struct SlipDecoder {
+    frame: Vec<u8>,
+    escaped: bool,
+    /// Set once the current frame grew past `max_len`, the rest of it is thrown away.
+    overflowed: bool,
+    max_len: usize
+}
+
+impl SlipDecoder {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            frame: Vec::new(),
+            escaped: false,
+            overflowed: false,
+            max_len
+        }
+    }
+
+    /// Feeds in the next bytes, returning the packets they complete.
+    /// Empty frames are skipped, as are frames longer than `max_len`.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        let mut packets = Vec::new();
+
+        for byte in bytes {
+            if *byte == END {
+                if !self.frame.is_empty() && !self.overflowed {
+                    packets.push(std::mem::take(&mut self.frame));
+                }
+
+                self.frame.clear();
+                self.escaped = false;
+                self.overflowed = false;
+                continue;
+            }
+
+            // A stray escape is kept as it is rather than dropping the frame.
+            let byte = match (self.escaped, *byte) {
+                (true, ESC_END) => END,
+                (true, ESC_ESC) => ESC,
+                (false, ESC) => {
+                    self.escaped = true;
+                    continue;
+                }
+                (_, byte) => byte
+            };
+
+            self.escaped = false;
+
+            if self.frame.len() >= self.max_len {
+                self.overflowed = true;
+                self.frame.clear();
+            }
+
+            if !self.overflowed {
+                self.frame.push(byte);
+            }
+        }
+
+        packets
+    }
+}
@@ -0,0 +1,117 @@
+use std::path::Path;
+use spotify_osc::entities::config::{ArgumentType, ConfigFile, ConfigFileAvatar, ConfigFileParameterOverride};
+use spotify_osc::entities::vrchat::{VrchatAvatarConfig, VrchatParameterType};
+use spotify_osc::managers::avatar_configs::{check_avatar, check_avatars, read_avatar_configs, ParameterProblem, ProblemKind};
+
+/// An avatar config as VRChat writes it, every parameter both settable and sent.
+fn avatar_json(id: &str, parameters: &[(&str, &str)]) -> String {
+    let parameters: Vec<String> = parameters.iter()
+        .map(|(name, kind)| format!(
+            r#"{{"name":"{name}","input":{{"address":"/avatar/parameters/{name}","type":"{kind}"}},"output":{{"address":"/avatar/parameters/{name}","type":"{kind}"}}}}"#
+        ))
+        .collect();
+
+    format!("\u{feff}{{\"id\":\"{}\",\"name\":\"Test\",\"parameters\":[{}]}}", id, parameters.join(","))
+}
+
+fn avatar(id: &str, parameters: &[(&str, &str)]) -> VrchatAvatarConfig {
+    serde_json::from_str(avatar_json(id, parameters).trim_start_matches('\u{feff}')).unwrap()
+}
+
+const COMPLETE: &[(&str, &str)] = &[
+    ("spotify_playing", "Bool"),
+    ("spotify_seek", "Float"),
+    ("spotify_rate_limited", "Bool"),
+    ("spotify_play", "Bool"),
+    ("spotify_stop", "Bool"),
+    ("spotify_next", "Bool"),
+    ("spotify_previous", "Bool"),
+    ("spotify_volume", "Float"),
+    ("spotify_profile", "Int")
+];
+
+fn write_avatar(dir: &Path, user: &str, id: &str, json: &str) {
+    let avatars = dir.join(user).join("Avatars");
+
+    std::fs::create_dir_all(&avatars).unwrap();
+    std::fs::write(avatars.join(format!("{}.json", id)), json).unwrap();
+}
+
+#[test]
+fn complete_avatar_has_no_problems() {
+    let cfg = ConfigFile::default();
+
+    assert!(check_avatar(&cfg, &avatar("avtr_a", COMPLETE)).is_empty());
+}
+
+#[test]
+fn reports_missing_and_mistyped_parameters() {
+    let cfg = ConfigFile::default();
+
+    let parameters: Vec<(&str, &str)> = COMPLETE.iter()
+        .filter(|(name, _)| *name != "spotify_volume")
+        .map(|(name, kind)| if *name == "spotify_seek" { (*name, "Int") } else { (*name, *kind) })
+        .collect();
+
+    let problems = check_avatar(&cfg, &avatar("avtr_a", &parameters));
+
+    assert_eq!(problems, vec![
+        ParameterProblem {
+            parameter: "spotify_seek",
+            address: "/avatar/parameters/spotify_seek".to_string(),
+            kind: ProblemKind::Mistyped { expected: VrchatParameterType::Float, found: VrchatParameterType::Int }
+        },
+        ParameterProblem {
+            parameter: "spotify_volume",
+            address: "/avatar/parameters/spotify_volume".to_string(),
+            kind: ProblemKind::Missing
+        }
+    ]);
+    assert_eq!(problems[0].to_string(), "spotify_seek (/avatar/parameters/spotify_seek) is Int but should be Float");
+}
+
+#[test]
+fn uses_the_avatars_parameters_and_overrides() {
+    let mut cfg = ConfigFile::default();
+
+    cfg.avatars.push(ConfigFileAvatar {
+        id: "avtr_a".to_string(),
+        spotify_play: Some("/avatar/parameters/music_play".to_string()),
+        overrides: vec![ConfigFileParameterOverride {
+            address: "/avatar/parameters/music_play".to_string(),
+            argument_type: ArgumentType::Int,
+            threshold: 1.0
+        }],
+        ..ConfigFileAvatar::default()
+    });
+
+    let mut parameters: Vec<(&str, &str)> = COMPLETE.iter().copied().filter(|(name, _)| *name != "spotify_play").collect();
+    parameters.push(("music_play", "Int"));
+
+    assert!(check_avatar(&cfg, &avatar("avtr_a", &parameters)).is_empty());
+
+    // Other avatars still use [parameters].
+    let problems = check_avatar(&cfg, &avatar("avtr_b", &parameters));
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].parameter, "spotify_play");
+}
+
+#[test]
+fn reads_avatar_configs_from_the_osc_folder() {
+    let dir = tempfile::tempdir().unwrap();
+
+    write_avatar(dir.path(), "usr_1", "avtr_a", &avatar_json("avtr_a", COMPLETE));
+    write_avatar(dir.path(), "usr_2", "avtr_a", &avatar_json("avtr_a", COMPLETE));
+    write_avatar(dir.path(), "usr_2", "avtr_b", &avatar_json("avtr_b", &[("spotify_playing", "Bool")]));
+    write_avatar(dir.path(), "usr_2", "avtr_broken", "{");
+    write_avatar(dir.path(), "other", "avtr_c", &avatar_json("avtr_c", COMPLETE));
+
+    let avatars = read_avatar_configs(dir.path()).unwrap();
+    assert_eq!(avatars.keys().collect::<Vec<_>>(), vec!["avtr_a", "avtr_b"]);
+
+    let reports = check_avatars(&ConfigFile::default(), dir.path()).unwrap();
+    assert!(reports[0].problems.is_empty());
+    assert_eq!(reports[1].problems.len(), 8);
+
+    assert!(check_avatars(&ConfigFile::default(), &dir.path().join("missing")).is_err());
+}
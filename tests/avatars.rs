@@ -0,0 +1,134 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+use rosc::OscType;
+use spotify_osc::config::config::Config;
+use spotify_osc::entities::config::{ArgumentType, ConfigFile, ConfigFileAvatar, ConfigFileOscTarget, ConfigFileParameterOverride, OscOutput};
+use spotify_osc::entities::osc::OscAction;
+use spotify_osc::managers::avatar::CurrentAvatar;
+use spotify_osc::managers::oscquery::oscquery_tree;
+use spotify_osc::managers::router::OscRouter;
+use spotify_osc::managers::targets::OscTargets;
+use tokio::net::UdpSocket;
+use common::{config_with, message, receive_message, udp_receiver};
+
+fn with_avatar() -> ConfigFile {
+    let mut cfg = ConfigFile::default();
+
+    cfg.avatars.push(ConfigFileAvatar {
+        id: "avtr_music".to_string(),
+        spotify_play: Some("/avatar/parameters/music_play".to_string()),
+        spotify_chatbox: Some("".to_string()),
+        overrides: vec![ConfigFileParameterOverride {
+            address: "/avatar/parameters/music_play".to_string(),
+            argument_type: ArgumentType::Int,
+            threshold: 1.0
+        }],
+        ..ConfigFileAvatar::default()
+    });
+
+    cfg
+}
+
+#[test]
+fn avatar_parameters_fall_back_to_the_defaults() {
+    let cfg = with_avatar();
+
+    let parameters = cfg.parameters_for(Some("avtr_music"));
+    assert_eq!(parameters.spotify_play, "/avatar/parameters/music_play");
+    assert_eq!(parameters.spotify_chatbox, "");
+    assert_eq!(parameters.spotify_stop, cfg.parameters.spotify_stop);
+    assert_eq!(parameters.overrides.len(), 1);
+
+    let parameters = cfg.parameters_for(Some("avtr_other"));
+    assert_eq!(parameters.spotify_play, cfg.parameters.spotify_play);
+
+    let parameters = cfg.parameters_for(None);
+    assert_eq!(parameters.spotify_play, cfg.parameters.spotify_play);
+    assert!(parameters.overrides.is_empty());
+}
+
+#[test]
+fn router_follows_the_avatar() {
+    let cfg = with_avatar();
+
+    let router = OscRouter::for_avatar(&cfg, Some("avtr_music"));
+    assert_eq!(router.route("/avatar/parameters/music_play"), vec![OscAction::Play]);
+    assert!(router.route("/avatar/parameters/spotify_play").is_empty());
+    assert_eq!(router.route("/avatar/parameters/spotify_stop"), vec![OscAction::Pause]);
+    assert_eq!(router.route("/avatar/change"), vec![OscAction::AvatarChange]);
+    assert_eq!(router.coercion("/avatar/parameters/music_play").argument_type, ArgumentType::Int);
+
+    let router = OscRouter::new(&cfg);
+    assert_eq!(router.route("/avatar/parameters/spotify_play"), vec![OscAction::Play]);
+    assert!(router.route("/avatar/parameters/music_play").is_empty());
+}
+
+#[test]
+fn oscquery_tree_lists_the_avatars_addresses() {
+    let cfg = with_avatar();
+
+    let tree = oscquery_tree(&cfg, Some("avtr_music"));
+    assert_eq!(tree.find("/avatar/parameters/music_play").unwrap().osc_type.as_deref(), Some("i"));
+    assert!(tree.find("/avatar/parameters/spotify_play").is_none());
+
+    let tree = oscquery_tree(&cfg, None);
+    assert!(tree.find("/avatar/parameters/spotify_play").is_some());
+    assert!(tree.find("/avatar/parameters/music_play").is_none());
+}
+
+#[tokio::test]
+async fn empty_addresses_are_not_sent() {
+    let (receiver, address) = udp_receiver().await;
+
+    let mut cfg = with_avatar();
+    cfg.general.osc.targets = vec![ConfigFileOscTarget {
+        address,
+        outputs: OscOutput::all(),
+        remap: BTreeMap::from([("/avatar/parameters/spotify_seek".to_string(), "".to_string())])
+    }];
+
+    let parameters = cfg.parameters_for(Some("avtr_music"));
+    let targets = OscTargets::new(&cfg.general.osc);
+    let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    targets.send(&sock, OscOutput::Chatbox, &parameters.spotify_chatbox, vec![OscType::String("Song".to_string())], Duration::ZERO).await;
+    targets.send(&sock, OscOutput::Seek, &parameters.spotify_seek, vec![OscType::Float(0.5)], Duration::ZERO).await;
+    assert_eq!(receive_message(&receiver).await, None);
+
+    targets.send(&sock, OscOutput::Playing, &parameters.spotify_playing, vec![OscType::Bool(true)], Duration::ZERO).await;
+    assert_eq!(receive_message(&receiver).await, Some(message(&parameters.spotify_playing, vec![OscType::Bool(true)])));
+}
+
+#[tokio::test]
+async fn current_avatar_notifies_on_change() {
+    let avatar = CurrentAvatar::new();
+
+    assert!(avatar.get().is_none());
+    assert!(avatar.set("avtr_music"));
+    assert_eq!(avatar.get().as_deref().map(String::as_str), Some("avtr_music"));
+
+    // The change happened before waiting, it still wakes the waiter.
+    tokio::time::timeout(std::time::Duration::from_secs(1), avatar.changed()).await.unwrap();
+
+    assert!(!avatar.set("avtr_music"));
+    assert!(tokio::time::timeout(std::time::Duration::from_millis(50), avatar.changed()).await.is_err());
+}
+
+#[tokio::test]
+async fn avatars_round_trip() {
+    let (dir, config) = config_with(|cfg| {
+        cfg.avatars = with_avatar().avatars;
+    });
+
+    let path = config.lock().await.path.clone();
+    let loaded: Config<ConfigFile> = Config::new(path);
+
+    let avatar = loaded.cfg.avatar("avtr_music").unwrap();
+    assert_eq!(avatar.spotify_play.as_deref(), Some("/avatar/parameters/music_play"));
+    assert!(avatar.spotify_stop.is_none());
+    assert_eq!(avatar.overrides.len(), 1);
+
+    drop(dir);
+}
@@ -0,0 +1,66 @@
+use std::time::{Duration, Instant};
+use spotify_osc::managers::buttons::{ButtonEvent, Buttons};
+
+const DEBOUNCE: Duration = Duration::from_millis(150);
+const ADDRESS: &str = "/avatar/parameters/spotify_next";
+
+#[test]
+fn repeated_values_are_not_edges() {
+    let mut buttons = Buttons::new();
+    let now = Instant::now();
+
+    assert!(matches!(buttons.update(ADDRESS, true, DEBOUNCE, now), Some(ButtonEvent::Pressed { .. })));
+    assert_eq!(buttons.update(ADDRESS, true, DEBOUNCE, now + Duration::from_millis(300)), None);
+    assert_eq!(buttons.update(ADDRESS, false, DEBOUNCE, now + Duration::from_millis(400)), Some(ButtonEvent::Released { long_pressed: false }));
+    assert_eq!(buttons.update(ADDRESS, false, DEBOUNCE, now + Duration::from_millis(500)), None);
+}
+
+#[test]
+fn presses_within_debounce_are_ignored() {
+    let mut buttons = Buttons::new();
+    let now = Instant::now();
+
+    assert!(buttons.update(ADDRESS, true, DEBOUNCE, now).is_some());
+    assert!(buttons.update(ADDRESS, false, DEBOUNCE, now + Duration::from_millis(20)).is_some());
+
+    // Chatter right after the press, neither edge counts.
+    assert_eq!(buttons.update(ADDRESS, true, DEBOUNCE, now + Duration::from_millis(40)), None);
+    assert_eq!(buttons.update(ADDRESS, false, DEBOUNCE, now + Duration::from_millis(60)), None);
+
+    assert!(matches!(buttons.update(ADDRESS, true, DEBOUNCE, now + Duration::from_millis(200)), Some(ButtonEvent::Pressed { .. })));
+}
+
+#[test]
+fn addresses_are_tracked_separately() {
+    let mut buttons = Buttons::new();
+    let now = Instant::now();
+
+    assert!(buttons.update(ADDRESS, true, DEBOUNCE, now).is_some());
+    assert!(buttons.update("/avatar/parameters/spotify_play", true, DEBOUNCE, now).is_some());
+}
+
+#[test]
+fn holding_marks_a_long_press() {
+    let mut buttons = Buttons::new();
+    let now = Instant::now();
+
+    let press = match buttons.update(ADDRESS, true, DEBOUNCE, now) {
+        Some(ButtonEvent::Pressed { press }) => press,
+        event => panic!("unexpected event {:?}", event)
+    };
+
+    assert!(buttons.hold(ADDRESS, press));
+    assert_eq!(buttons.update(ADDRESS, false, DEBOUNCE, now + Duration::from_secs(1)), Some(ButtonEvent::Released { long_pressed: true }));
+
+    // The press is over, a late hold check must not run the action again.
+    assert!(!buttons.hold(ADDRESS, press));
+
+    let next = match buttons.update(ADDRESS, true, DEBOUNCE, now + Duration::from_secs(2)) {
+        Some(ButtonEvent::Pressed { press }) => press,
+        event => panic!("unexpected event {:?}", event)
+    };
+
+    assert!(!buttons.hold(ADDRESS, press));
+    assert_eq!(buttons.update(ADDRESS, false, DEBOUNCE, now + Duration::from_millis(2100)), Some(ButtonEvent::Released { long_pressed: false }));
+    assert!(!buttons.hold(ADDRESS, next));
+}
@@ -0,0 +1,62 @@
+use rosc::OscType;
+use spotify_osc::entities::config::{ArgumentType, ConfigFile, ConfigFileParameterOverride};
+use spotify_osc::managers::router::OscRouter;
+use spotify_osc::utils::coerce::Coercion;
+
+#[test]
+fn triggers_accept_any_type() {
+    let coercion = Coercion::default();
+
+    assert_eq!(coercion.trigger(&OscType::Bool(true)), Some(true));
+    assert_eq!(coercion.trigger(&OscType::Bool(false)), Some(false));
+    assert_eq!(coercion.trigger(&OscType::Int(1)), Some(true));
+    assert_eq!(coercion.trigger(&OscType::Int(0)), Some(false));
+    assert_eq!(coercion.trigger(&OscType::Float(1.0)), Some(true));
+    assert_eq!(coercion.trigger(&OscType::Float(0.2)), Some(false));
+    assert_eq!(coercion.trigger(&OscType::String("true".to_string())), Some(true));
+    assert_eq!(coercion.trigger(&OscType::String("1".to_string())), Some(true));
+    assert_eq!(coercion.trigger(&OscType::String("0".to_string())), Some(false));
+    assert_eq!(coercion.trigger(&OscType::String("maybe".to_string())), None);
+    assert_eq!(coercion.trigger(&OscType::Nil), None);
+}
+
+#[test]
+fn volume_reads_percentages_and_fractions() {
+    let coercion = Coercion::default();
+
+    assert_eq!(coercion.volume(&OscType::Int(50)), Some(0.5));
+    assert_eq!(coercion.volume(&OscType::Int(150)), Some(1.0));
+    assert_eq!(coercion.volume(&OscType::Float(0.25)), Some(0.25));
+    assert_eq!(coercion.volume(&OscType::Float(-1.0)), Some(0.0));
+    assert_eq!(coercion.volume(&OscType::Float(f32::NAN)), None);
+    assert_eq!(coercion.volume(&OscType::Bool(true)), None);
+}
+
+#[test]
+fn forced_types_ignore_other_arguments() {
+    let coercion = Coercion { argument_type: ArgumentType::Int, threshold: 0.5 };
+
+    assert_eq!(coercion.trigger(&OscType::Int(1)), Some(true));
+    assert_eq!(coercion.trigger(&OscType::Bool(true)), None);
+    assert_eq!(coercion.volume(&OscType::Float(0.5)), None);
+    assert_eq!(coercion.index(&OscType::Int(2)), Some(2));
+}
+
+#[test]
+fn router_applies_overrides_by_address() {
+    let mut cfg = ConfigFile::default();
+    cfg.parameters.overrides.push(ConfigFileParameterOverride {
+        address: "/avatar/parameters/spotify_{play,stop}".to_string(),
+        argument_type: ArgumentType::Float,
+        threshold: 0.9
+    });
+
+    let router = OscRouter::new(&cfg);
+
+    let play = router.coercion("/avatar/parameters/spotify_play");
+    assert_eq!(play.trigger(&OscType::Float(0.8)), Some(false));
+    assert_eq!(play.trigger(&OscType::Float(0.95)), Some(true));
+    assert_eq!(play.trigger(&OscType::Bool(true)), None);
+
+    assert_eq!(router.coercion("/avatar/parameters/spotify_next"), Coercion::default());
+}
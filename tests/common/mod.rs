@@ -0,0 +1,424 @@
+#![allow(dead_code)]
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use actix_web::dev::ServerHandle;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use rosc::{OscMessage, OscPacket, OscType};
+use serde_json::{json, Value};
+use spotify_osc::config::config::Config;
+use spotify_osc::config::credentials::{Credentials, CredentialsStore};
+use spotify_osc::entities::config::ConfigFile;
+use spotify_osc::managers::authorization::Authorizations;
+use spotify_osc::managers::profiles::SpotifyProfiles;
+use spotify_osc::managers::transport::OscTcpListener;
+use spotify_osc::routes::WebData;
+use spotify_osc::utils::osc::decode_messages;
+use tempfile::TempDir;
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, Mutex};
+
+#[derive(Clone, Debug)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub authorization: Option<String>,
+    pub body: String
+}
+
+pub struct MockState {
+    pub requests: std::sync::Mutex<Vec<MockRequest>>,
+    pub access_token: std::sync::Mutex<String>,
+    pub issued: std::sync::Mutex<usize>,
+    pub now_playing: std::sync::Mutex<Option<Value>>,
+    pub devices: std::sync::Mutex<Value>,
+    pub failures: std::sync::Mutex<VecDeque<MockFailure>>,
+    pub expires_in: std::sync::Mutex<u64>,
+    pub rotated_refresh_token: std::sync::Mutex<Option<String>>,
+    pub granted_scope: std::sync::Mutex<String>,
+    pub token_delay: std::sync::Mutex<Duration>
+}
+
+pub struct MockFailure {
+    pub status: u16,
+    pub message: String,
+    pub retry_after: Option<u64>
+}
+
+impl MockState {
+    fn new() -> Self {
+        Self {
+            requests: std::sync::Mutex::new(Vec::new()),
+            access_token: std::sync::Mutex::new("access-0".to_string()),
+            issued: std::sync::Mutex::new(0),
+            now_playing: std::sync::Mutex::new(None),
+            devices: std::sync::Mutex::new(json!({
+                "devices": [
+                    { "id": "device-1", "is_active": false, "volume_percent": 20 },
+                    { "id": "device-2", "is_active": true, "volume_percent": 50 }
+                ]
+            })),
+            failures: std::sync::Mutex::new(VecDeque::new()),
+            expires_in: std::sync::Mutex::new(3600),
+            rotated_refresh_token: std::sync::Mutex::new(None),
+            granted_scope: std::sync::Mutex::new("user-read-currently-playing".to_string()),
+            token_delay: std::sync::Mutex::new(Duration::ZERO)
+        }
+    }
+
+    fn issue_token(&self) -> String {
+        let mut issued = self.issued.lock().unwrap();
+        *issued += 1;
+
+        let token = format!("access-{}", issued);
+        *self.access_token.lock().unwrap() = String::from(&token);
+
+        token
+    }
+}
+
+/// Minimal stand-in for the Spotify Web API and accounts service.
+pub struct MockSpotify {
+    pub url: String,
+    pub state: Arc<MockState>,
+    handle: ServerHandle
+}
+
+impl MockSpotify {
+    pub async fn start() -> Self {
+        let state = Arc::new(MockState::new());
+
+        let server = HttpServer::new({
+            let state = state.clone();
+
+            move || {
+                App::new()
+                    .app_data(web::Data::new(state.clone()))
+                    .default_service(web::to(handle))
+            }
+        })
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+
+        let url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+
+        tokio::spawn(server);
+
+        Self {
+            url,
+            state,
+            handle
+        }
+    }
+
+    pub fn api_url(&self) -> String {
+        format!("{}/v1", self.url)
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    pub fn requests_to(&self, path: &str) -> Vec<MockRequest> {
+        self.requests().into_iter().filter(|req| req.path == path).collect()
+    }
+
+    pub fn set_now_playing(&self, value: Option<Value>) {
+        *self.state.now_playing.lock().unwrap() = value;
+    }
+
+    /// Makes the next Web API requests answer with the given statuses, in order.
+    pub fn fail_next(&self, statuses: &[u16]) {
+        let failures = statuses.iter().map(|status| MockFailure {
+            status: *status,
+            message: "Mocked failure".to_string(),
+            retry_after: None
+        });
+
+        self.state.failures.lock().unwrap().extend(failures);
+    }
+
+    pub fn fail_next_with(&self, status: u16, message: &str) {
+        self.state.failures.lock().unwrap().push_back(MockFailure {
+            status,
+            message: message.to_string(),
+            retry_after: None
+        });
+    }
+
+    pub fn rate_limit_next(&self, retry_after: u64) {
+        self.state.failures.lock().unwrap().push_back(MockFailure {
+            status: 429,
+            message: "API rate limit exceeded".to_string(),
+            retry_after: Some(retry_after)
+        });
+    }
+
+    /// Lifetime in seconds of the access tokens issued from now on.
+    pub fn set_expires_in(&self, expires_in: u64) {
+        *self.state.expires_in.lock().unwrap() = expires_in;
+    }
+
+    /// Makes token refreshes hand out a new refresh token as well.
+    pub fn rotate_refresh_token(&self, refresh_token: &str) {
+        *self.state.rotated_refresh_token.lock().unwrap() = Some(refresh_token.to_string());
+    }
+
+    /// Scope reported in token responses from now on.
+    pub fn set_granted_scope(&self, scope: &str) {
+        *self.state.granted_scope.lock().unwrap() = scope.to_string();
+    }
+
+    /// Makes the accounts service take `delay` to answer.
+    pub fn delay_token(&self, delay: Duration) {
+        *self.state.token_delay.lock().unwrap() = delay;
+    }
+
+    pub fn expire_token(&self) {
+        *self.state.access_token.lock().unwrap() = "expired".to_string();
+    }
+
+    pub async fn stop(self) {
+        self.handle.stop(false).await;
+    }
+}
+
+async fn handle(req: HttpRequest, body: web::Bytes, state: web::Data<Arc<MockState>>) -> HttpResponse {
+    let body = String::from_utf8_lossy(&body).to_string();
+    let query: HashMap<String, String> = serde_urlencoded::from_str(req.query_string()).unwrap_or_default();
+    let authorization = req.headers().get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+
+    state.requests.lock().unwrap().push(MockRequest {
+        method: req.method().to_string(),
+        path: req.path().to_string(),
+        query,
+        authorization: authorization.clone(),
+        body: String::from(&body)
+    });
+
+    if req.path() == "/api/token" {
+        let delay = *state.token_delay.lock().unwrap();
+        tokio::time::sleep(delay).await;
+
+        let form: HashMap<String, String> = serde_urlencoded::from_str(&body).unwrap_or_default();
+
+        // Without Basic auth the client is using PKCE and has to identify itself in the body.
+        let pkce_ok = form.contains_key("client_id")
+            && (form.get("grant_type").map(|grant| grant.as_str()) != Some("authorization_code") || form.contains_key("code_verifier"));
+
+        if authorization.is_none() && !pkce_ok {
+            return HttpResponse::BadRequest().json(json!({
+                "error": "invalid_client",
+                "error_description": "Invalid client"
+            }))
+        }
+
+        return match form.get("grant_type").map(|grant| grant.as_str()) {
+            Some("authorization_code") => {
+                HttpResponse::Ok().json(json!({
+                    "access_token": state.issue_token(),
+                    "token_type": "Bearer",
+                    "expires_in": *state.expires_in.lock().unwrap(),
+                    "refresh_token": "refresh-1",
+                    "scope": *state.granted_scope.lock().unwrap()
+                }))
+            }
+            Some("refresh_token") if form.get("refresh_token").map(|token| token.as_str()) == Some("revoked") => {
+                HttpResponse::BadRequest().json(json!({
+                    "error": "invalid_grant",
+                    "error_description": "Refresh token revoked"
+                }))
+            }
+            Some("refresh_token") => {
+                let mut res = json!({
+                    "access_token": state.issue_token(),
+                    "token_type": "Bearer",
+                    "expires_in": *state.expires_in.lock().unwrap(),
+                    "scope": *state.granted_scope.lock().unwrap()
+                });
+
+                if let Some(refresh_token) = &*state.rotated_refresh_token.lock().unwrap() {
+                    res["refresh_token"] = json!(refresh_token);
+                }
+
+                HttpResponse::Ok().json(res)
+            }
+            _ => HttpResponse::BadRequest().json(json!({ "error": "unsupported_grant_type" }))
+        }
+    }
+
+    if let Some(failure) = state.failures.lock().unwrap().pop_front() {
+        let mut res = HttpResponse::build(actix_web::http::StatusCode::from_u16(failure.status).unwrap());
+
+        if let Some(retry_after) = failure.retry_after {
+            res.insert_header(("Retry-After", retry_after.to_string()));
+        }
+
+        return res.json(json!({
+            "error": { "status": failure.status, "message": failure.message, "reason": "MOCKED" }
+        }))
+    }
+
+    let token = String::from(&*state.access_token.lock().unwrap());
+    let authorized = req.headers().get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.starts_with("Bearer") && value.ends_with(&format!(" {}", token)))
+        .unwrap_or(false);
+
+    if !authorized {
+        return HttpResponse::Unauthorized().json(json!({
+            "error": { "status": 401, "message": "The access token expired" }
+        }))
+    }
+
+    match (req.method().as_str(), req.path()) {
+        ("GET", "/v1/me/player/currently-playing") => {
+            match &*state.now_playing.lock().unwrap() {
+                Some(value) => HttpResponse::Ok().json(value),
+                None => HttpResponse::NoContent().finish()
+            }
+        }
+        ("GET", "/v1/me/player/devices") => {
+            HttpResponse::Ok().json(&*state.devices.lock().unwrap())
+        }
+        ("GET", "/v1/me/player") => {
+            match &*state.now_playing.lock().unwrap() {
+                Some(value) => HttpResponse::Ok().json(json!({
+                    "device": { "id": "device-2", "is_active": true, "volume_percent": 50 },
+                    "is_playing": value["is_playing"],
+                    "progress_ms": value["progress_ms"]
+                })),
+                None => HttpResponse::NoContent().finish()
+            }
+        }
+        _ => HttpResponse::NoContent().finish()
+    }
+}
+
+pub fn now_playing_json(id: &str, is_playing: bool) -> Value {
+    json!({
+        "progress_ms": 30000,
+        "is_playing": is_playing,
+        "item": {
+            "id": id,
+            "name": "Song",
+            "duration_ms": 120000,
+            "artists": [{ "name": "Artist A" }, { "name": "Artist B" }]
+        }
+    })
+}
+
+/// Creates a config file in a temporary directory, letting the caller adjust it first.
+pub fn config_with<F>(setup: F) -> (TempDir, Arc<Mutex<Config<ConfigFile>>>)
+    where F: FnOnce(&mut ConfigFile)
+{
+    let dir = tempfile::tempdir().unwrap();
+    let path: PathBuf = dir.path().join("config.toml");
+
+    let mut config: Config<ConfigFile> = Config::new(path);
+
+    config.cfg.spotify.credentials_path = dir.path().join("credentials.json").display().to_string();
+
+    setup(&mut config.cfg);
+    config.write();
+
+    (dir, Arc::new(Mutex::new(config)))
+}
+
+/// Creates a config file in a temporary directory pointing at the mock server.
+pub fn config_for(mock: &MockSpotify) -> (TempDir, Arc<Mutex<Config<ConfigFile>>>) {
+    config_with(|cfg| {
+        cfg.spotify.client_id = "client-id".to_string();
+        cfg.spotify.client_secret = "client-secret".to_string();
+        cfg.spotify.refresh_token = "refresh-0".to_string();
+        cfg.spotify.api_url = mock.api_url();
+        cfg.spotify.accounts_url = String::from(&mock.url);
+    })
+}
+
+/// Shared state for the web routes, backed by `config`.
+pub async fn web_data(config: Arc<Mutex<Config<ConfigFile>>>, authorizations: Authorizations) -> WebData {
+    let client = Arc::new(reqwest::Client::new());
+
+    WebData {
+        config: config.clone(),
+        spotify: Arc::new(Mutex::new(SpotifyProfiles::new(client, config).await)),
+        authorizations: Arc::new(authorizations)
+    }
+}
+
+/// Query parameters of a redirect's `Location`.
+pub fn location_query(location: &str) -> HashMap<String, String> {
+    let query = location.split_once('?').map(|(_, query)| query).unwrap_or_default();
+
+    serde_urlencoded::from_str(query).unwrap()
+}
+
+/// Reads back what the token manager saved to the credentials file.
+pub async fn stored_credentials(config: &Arc<Mutex<Config<ConfigFile>>>) -> Credentials {
+    CredentialsStore::from_config(&config.lock().await.cfg, None).load().unwrap()
+}
+
+pub async fn stored_profile_credentials(config: &Arc<Mutex<Config<ConfigFile>>>, profile: &str) -> Credentials {
+    CredentialsStore::from_config(&config.lock().await.cfg, Some(profile)).load().unwrap()
+}
+
+/// A UDP socket on loopback and its address, standing in for an OSC app.
+pub async fn udp_receiver() -> (UdpSocket, String) {
+    let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = sock.local_addr().unwrap().to_string();
+
+    (sock, address)
+}
+
+/// The next datagram, `None` when nothing arrives within 200ms.
+pub async fn receive_bytes(sock: &UdpSocket) -> Option<Vec<u8>> {
+    let mut buf = [0u8; rosc::decoder::MTU];
+
+    match tokio::time::timeout(Duration::from_millis(200), sock.recv(&mut buf)).await {
+        Ok(size) => Some(buf[..size.unwrap()].to_vec()),
+        Err(_) => None
+    }
+}
+
+/// The next datagram as a single OSC message.
+pub async fn receive_message(sock: &UdpSocket) -> Option<OscMessage> {
+    match rosc::decoder::decode_udp(&receive_bytes(sock).await?).unwrap().1 {
+        OscPacket::Message(message) => Some(message),
+        OscPacket::Bundle(_) => panic!("unexpected bundle")
+    }
+}
+
+/// A TCP listener on loopback passing its packets on to the returned channel, and its address.
+pub async fn tcp_receiver() -> (mpsc::Receiver<(Vec<u8>, SocketAddr)>, String) {
+    let listener = OscTcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let (tx, rx) = mpsc::channel(16);
+    listener.spawn(tx);
+
+    (rx, address)
+}
+
+/// The first message of the next packet on the channel, `None` when nothing arrives within a second.
+pub async fn receive_queued(packets: &mut mpsc::Receiver<(Vec<u8>, SocketAddr)>) -> Option<OscMessage> {
+    let (buf, _) = tokio::time::timeout(Duration::from_secs(1), packets.recv()).await.ok()??;
+
+    decode_messages(&buf).unwrap().into_iter().next()
+}
+
+pub fn message(address: &str, args: Vec<OscType>) -> OscMessage {
+    OscMessage {
+        addr: address.to_string(),
+        args
+    }
+}
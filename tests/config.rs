@@ -0,0 +1,82 @@
+mod common;
+
+use spotify_osc::config::config::Config;
+use std::collections::BTreeMap;
+use spotify_osc::entities::config::{ConfigFile, ConfigFileOscTarget, MediaBackend, OscOutput};
+use common::config_with;
+
+#[tokio::test]
+async fn default_config_round_trips() {
+    let (dir, config) = config_with(|cfg| {
+        cfg.general.backend = MediaBackend::Mpris;
+    });
+
+    let path = config.lock().await.path.clone();
+    let loaded: Config<ConfigFile> = Config::new(path);
+
+    assert_eq!(loaded.cfg.general.backend, MediaBackend::Mpris);
+    assert_eq!(loaded.cfg.spotify.api_url, "https://api.spotify.com/v1");
+
+    drop(dir);
+}
+
+#[test]
+fn missing_sections_use_defaults() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+
+    std::fs::write(&path, r#"
+[general.osc]
+host_address = "127.0.0.1:9001"
+client_address = "127.0.0.1:9000"
+
+[general.web_server]
+host_address = "127.0.0.1"
+port = 8080
+
+[spotify]
+client_id = "id"
+client_secret = "secret"
+callback_url = "http://localhost:8080/callback"
+token = ""
+refresh_token = ""
+
+[parameters]
+spotify_playing = "/avatar/parameters/spotify_playing"
+spotify_seek = "/avatar/parameters/spotify_seek"
+spotify_chatbox = "/chatbox/input"
+spotify_play = "/avatar/parameters/spotify_play"
+spotify_stop = "/avatar/parameters/spotify_stop"
+spotify_next = "/avatar/parameters/spotify_next"
+spotify_previous = "/avatar/parameters/spotify_previous"
+spotify_volume = "/avatar/parameters/spotify_volume"
+"#).unwrap();
+
+    let loaded: Config<ConfigFile> = Config::new(path);
+
+    assert_eq!(loaded.cfg.general.backend, MediaBackend::Spotify);
+    assert_eq!(loaded.cfg.spotify.accounts_url, "https://accounts.spotify.com");
+    assert!(loaded.cfg.mpris.bus_address.is_empty());
+}
+
+#[tokio::test]
+async fn osc_targets_round_trip() {
+    let (dir, config) = config_with(|cfg| {
+        cfg.general.osc.targets = vec![ConfigFileOscTarget {
+            address: "127.0.0.1:9100".to_string(),
+            outputs: vec![OscOutput::Chatbox],
+            remap: BTreeMap::from([("/chatbox/input".to_string(), "/overlay/text".to_string())])
+        }];
+    });
+
+    let path = config.lock().await.path.clone();
+
+    let loaded: Config<ConfigFile> = Config::new(path);
+    let targets = loaded.cfg.general.osc.targets();
+
+    assert_eq!(targets.len(), 1);
+    assert_eq!(targets[0].outputs, vec![OscOutput::Chatbox]);
+    assert_eq!(targets[0].remap.get("/chatbox/input").unwrap(), "/overlay/text");
+
+    drop(dir);
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use std::sync::Arc;
+use spotify_osc::config::config::Config;
+use spotify_osc::config::credentials::{Credentials, CredentialsError, CredentialsKey, CredentialsStore};
+use spotify_osc::entities::config::ConfigFile;
+use spotify_osc::managers::spotify::{Spotify, SpotifyAuthError};
+use common::{config_for, stored_credentials, MockSpotify};
+
+fn credentials() -> Credentials {
+    Credentials {
+        token: "access".to_string(),
+        refresh_token: "refresh".to_string(),
+        scope: "user-read-currently-playing".to_string()
+    }
+}
+
+#[tokio::test]
+async fn moves_tokens_out_of_the_config() {
+    let mock = MockSpotify::start().await;
+    let (dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config.clone());
+    spotify.authenticate().await.unwrap();
+
+    assert!(mock.requests_to("/api/token")[0].body.contains("refresh_token=refresh-0"));
+    assert_eq!(stored_credentials(&config).await.refresh_token, "refresh-0");
+
+    let saved = std::fs::read_to_string(dir.path().join("config.toml")).unwrap();
+    assert!(!saved.contains("refresh_token"));
+
+    let saved: Config<ConfigFile> = Config::new(dir.path().join("config.toml"));
+    assert!(saved.cfg.spotify.refresh_token.is_empty());
+    assert!(saved.cfg.spotify.token.is_empty());
+
+    mock.stop().await;
+}
+
+#[cfg(unix)]
+#[test]
+fn file_is_owner_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials.json");
+
+    let store = CredentialsStore::new(path.clone(), None);
+    store.save(&credentials()).unwrap();
+
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    assert_eq!(store.load().unwrap(), credentials());
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+}
+
+#[test]
+fn missing_file_is_empty() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let store = CredentialsStore::new(dir.path().join("credentials.json"), None);
+
+    assert_eq!(store.load().unwrap(), Credentials::default());
+}
+
+#[test]
+fn encrypts_with_passphrase() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials.json");
+
+    let store = CredentialsStore::new(path.clone(), Some(CredentialsKey::Passphrase("hunter2".to_string())));
+    store.save(&credentials()).unwrap();
+
+    let raw = std::fs::read_to_string(&path).unwrap();
+    assert!(!raw.contains("refresh"));
+    assert!(raw.contains("ciphertext"));
+
+    assert_eq!(store.load().unwrap(), credentials());
+
+    let wrong = CredentialsStore::new(path.clone(), Some(CredentialsKey::Passphrase("hunter3".to_string())));
+    assert!(matches!(wrong.load(), Err(CredentialsError::Decrypt)));
+
+    let locked = CredentialsStore::new(path, None);
+    assert!(matches!(locked.load(), Err(CredentialsError::Locked)));
+}
+
+#[test]
+fn encrypts_with_key_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_file = dir.path().join("key");
+
+    std::fs::write(&key_file, "0123456789abcdef\n").unwrap();
+
+    let store = CredentialsStore::new(dir.path().join("credentials.json"), Some(CredentialsKey::KeyFile(key_file.clone())));
+    store.save(&credentials()).unwrap();
+
+    // The trailing newline isn't part of the key.
+    std::fs::write(&key_file, "0123456789abcdef").unwrap();
+    assert_eq!(store.load().unwrap(), credentials());
+
+    std::fs::remove_file(&key_file).unwrap();
+    assert!(matches!(store.load(), Err(CredentialsError::KeyFile(_))));
+}
+
+#[tokio::test]
+async fn wrong_key_is_reported_without_touching_the_file() {
+    let mock = MockSpotify::start().await;
+    let (dir, config) = config_for(&mock);
+
+    let key_file = dir.path().join("key");
+    std::fs::write(&key_file, "right").unwrap();
+
+    config.lock().await.cfg.spotify.credentials_key_file = key_file.display().to_string();
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config.clone());
+    spotify.authenticate().await.unwrap();
+
+    let path = dir.path().join("credentials.json");
+    let encrypted = std::fs::read(&path).unwrap();
+
+    std::fs::write(&key_file, "wrong").unwrap();
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+
+    assert!(matches!(spotify.authenticate().await, Err(SpotifyAuthError::Credentials(CredentialsError::Decrypt))));
+    assert_eq!(std::fs::read(&path).unwrap(), encrypted);
+    assert_eq!(mock.requests_to("/api/token").len(), 1);
+
+    mock.stop().await;
+}
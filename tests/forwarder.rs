@@ -0,0 +1,98 @@
+mod common;
+
+use rosc::{encoder, OscBundle, OscMessage, OscPacket, OscTime, OscType};
+use spotify_osc::entities::config::{ConfigFile, ForwardMode};
+use spotify_osc::managers::forwarder::OscForwarder;
+use spotify_osc::managers::router::OscRouter;
+use spotify_osc::managers::transport::OscTransport;
+use tokio::net::UdpSocket;
+use common::{receive_bytes, udp_receiver};
+
+fn bundle() -> Vec<u8> {
+    encoder::encode(&OscPacket::Bundle(OscBundle {
+        timetag: OscTime { seconds: 1, fractional: 2 },
+        content: vec![OscPacket::Message(OscMessage {
+            addr: "/avatar/parameters/FaceTracking".to_string(),
+            args: vec![OscType::Double(0.25), OscType::Long(7)]
+        })]
+    })).unwrap()
+}
+
+#[tokio::test]
+async fn relays_raw_bytes_to_every_address() {
+    let (first, first_address) = udp_receiver().await;
+    let (second, second_address) = udp_receiver().await;
+
+    let mut cfg = ConfigFile::default();
+    cfg.general.osc.forward.addresses = vec![first_address, second_address];
+
+    let forwarder = OscForwarder::new(&cfg.general.osc, &[]);
+    assert!(forwarder.is_enabled());
+
+    let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let packet = bundle();
+
+    forwarder.forward(&sock, &packet, true).await;
+    forwarder.forward(&sock, b"not osc", false).await;
+
+    assert_eq!(receive_bytes(&first).await, Some(packet.clone()));
+    assert_eq!(receive_bytes(&second).await, Some(packet));
+    assert_eq!(receive_bytes(&first).await, Some(b"not osc".to_vec()));
+}
+
+#[tokio::test]
+async fn unmatched_mode_skips_routed_packets() {
+    let (receiver, address) = udp_receiver().await;
+
+    let mut cfg = ConfigFile::default();
+    cfg.general.osc.forward.mode = ForwardMode::Unmatched;
+    cfg.general.osc.forward.addresses = vec![address];
+
+    let forwarder = OscForwarder::new(&cfg.general.osc, &[]);
+    let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    forwarder.forward(&sock, &bundle(), true).await;
+    assert_eq!(receive_bytes(&receiver).await, None);
+
+    forwarder.forward(&sock, &bundle(), false).await;
+    assert_eq!(receive_bytes(&receiver).await, Some(bundle()));
+}
+
+#[test]
+fn never_forwards_to_itself() {
+    let mut cfg = ConfigFile::default();
+    cfg.general.osc.forward.addresses = vec![String::from(&cfg.general.osc.host_address)];
+
+    let listening = [(OscTransport::Udp, cfg.general.osc.host_address.parse().unwrap())];
+
+    assert!(!OscForwarder::new(&cfg.general.osc, &listening).is_enabled());
+    assert!(!OscForwarder::new(&ConfigFile::default().general.osc, &listening).is_enabled());
+
+    // With OSCQuery the app listens on whatever port it was given, host_address is free for others then.
+    let listening = [(OscTransport::Udp, "127.0.0.1:54321".parse().unwrap())];
+    assert!(OscForwarder::new(&cfg.general.osc, &listening).is_enabled());
+
+    cfg.general.osc.forward.addresses = vec!["127.0.0.1:54321".to_string()];
+    assert!(!OscForwarder::new(&cfg.general.osc, &listening).is_enabled());
+
+    // Sockets bound to every interface are reached over loopback, other machines are someone else.
+    let listening = [
+        (OscTransport::Udp, "0.0.0.0:9001".parse().unwrap()),
+        (OscTransport::Tcp, "0.0.0.0:9010".parse().unwrap())
+    ];
+
+    cfg.general.osc.forward.addresses = vec!["127.0.0.1:9001".to_string(), "tcp://localhost:9010".to_string()];
+    assert!(!OscForwarder::new(&cfg.general.osc, &listening).is_enabled());
+
+    cfg.general.osc.forward.addresses = vec!["192.168.1.20:9001".to_string(), "tcp://127.0.0.1:9001".to_string(), "127.0.0.1:9010".to_string()];
+    assert!(OscForwarder::new(&cfg.general.osc, &listening).is_enabled());
+}
+
+#[test]
+fn avatar_changes_are_relayed_in_unmatched_mode() {
+    let router = OscRouter::new(&ConfigFile::default());
+
+    assert!(router.handles("/avatar/parameters/spotify_play"));
+    assert!(!router.handles("/avatar/change"));
+    assert!(!router.handles("/avatar/parameters/VelocityX"));
+}
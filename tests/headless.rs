@@ -0,0 +1,117 @@
+mod common;
+
+use std::sync::Arc;
+use spotify_osc::entities::spotify::SpotifyCallbackQuery;
+use spotify_osc::managers::authorization::{AuthorizationError, Authorizations};
+use spotify_osc::managers::headless::{headless_auth, HeadlessAuthError, PastedRedirect};
+use spotify_osc::managers::spotify::Spotify;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use common::{config_for, location_query, stored_credentials, MockSpotify};
+
+#[test]
+fn parses_pasted_input() {
+    assert_eq!(PastedRedirect::parse("  abc123 \n"), Some(PastedRedirect::Code("abc123".to_string())));
+
+    assert_eq!(PastedRedirect::parse("http://localhost:8080/callback?code=abc&state=xyz"), Some(PastedRedirect::Url(SpotifyCallbackQuery {
+        code: Some("abc".to_string()),
+        state: Some("xyz".to_string()),
+        error: None
+    })));
+
+    assert_eq!(PastedRedirect::parse("http://localhost:8080/callback?error=access_denied&state=xyz#_=_"), Some(PastedRedirect::Url(SpotifyCallbackQuery {
+        code: None,
+        state: Some("xyz".to_string()),
+        error: Some("access_denied".to_string())
+    })));
+
+    assert_eq!(PastedRedirect::parse(""), None);
+    assert_eq!(PastedRedirect::parse("http://localhost:8080/callback"), None);
+}
+
+#[tokio::test]
+async fn exchanges_bare_code() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config.clone());
+    let mut output = Vec::new();
+
+    headless_auth(&mut spotify, &Authorizations::default(), "\nauth-code\n".as_bytes(), &mut output).await.unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains(&format!("{}/authorize?", mock.url)));
+
+    let token_requests = mock.requests_to("/api/token");
+    assert_eq!(token_requests.len(), 1);
+    assert!(token_requests[0].body.contains("code=auth-code"));
+    assert_eq!(stored_credentials(&config).await.refresh_token, "refresh-1");
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn exchanges_pasted_redirect_url() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    config.lock().await.cfg.spotify.client_secret = "".to_string();
+
+    let (input, mut input_writer) = tokio::io::duplex(4096);
+    let (mut output, output_reader) = tokio::io::duplex(4096);
+
+    let task = tokio::spawn({
+        let config = config.clone();
+
+        async move {
+            let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+
+            headless_auth(&mut spotify, &Authorizations::default(), BufReader::new(input), &mut output).await
+        }
+    });
+
+    let mut lines = BufReader::new(output_reader).lines();
+
+    let url = loop {
+        let line = lines.next_line().await.unwrap().unwrap();
+
+        if line.contains("/authorize?") {
+            break line;
+        }
+    };
+
+    let query = location_query(&url);
+    let redirect = format!("http://localhost:8080/callback?code=auth-code&state={}\n", query["state"]);
+
+    input_writer.write_all(redirect.as_bytes()).await.unwrap();
+
+    task.await.unwrap().unwrap();
+
+    let token_requests = mock.requests_to("/api/token");
+    assert!(token_requests[0].body.contains("code_verifier="));
+    assert_eq!(stored_credentials(&config).await.refresh_token, "refresh-1");
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn rejects_foreign_state_and_cancelled_logins() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+
+    let res = headless_auth(&mut spotify, &Authorizations::default(),
+                            "http://localhost:8080/callback?code=auth-code&state=forged\n".as_bytes(), &mut Vec::new()).await;
+    assert!(matches!(res, Err(HeadlessAuthError::Authorization(AuthorizationError::UnknownState))));
+
+    let res = headless_auth(&mut spotify, &Authorizations::default(),
+                            "http://localhost:8080/callback?error=access_denied\n".as_bytes(), &mut Vec::new()).await;
+    assert_eq!(res.err().unwrap().to_string(), "the login was cancelled");
+
+    let res = headless_auth(&mut spotify, &Authorizations::default(), "".as_bytes(), &mut Vec::new()).await;
+    assert!(matches!(res, Err(HeadlessAuthError::NoInput)));
+
+    assert!(mock.requests_to("/api/token").is_empty());
+
+    mock.stop().await;
+}
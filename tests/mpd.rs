@@ -0,0 +1,238 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+use spotify_osc::managers::media::MediaSource;
+use spotify_osc::managers::mpd::Mpd;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::Notify;
+use common::config_with;
+
+struct FakeMpdState {
+    state: String,
+    volume: u16,
+    commands: Vec<String>
+}
+
+/// Tiny MPD server that understands the handful of commands the backend uses.
+struct FakeMpd {
+    state: Arc<std::sync::Mutex<FakeMpdState>>,
+    changed: Arc<Notify>
+}
+
+impl FakeMpd {
+    fn new(state: &str) -> Self {
+        Self {
+            state: Arc::new(std::sync::Mutex::new(FakeMpdState {
+                state: state.to_string(),
+                volume: 40,
+                commands: Vec::new()
+            })),
+            changed: Arc::new(Notify::new())
+        }
+    }
+
+    async fn serve_tcp(&self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let state = self.state.clone();
+        let changed = self.changed.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+
+                tokio::spawn(handle(stream, state.clone(), changed.clone()));
+            }
+        });
+
+        address
+    }
+
+    #[cfg(unix)]
+    fn serve_unix(&self, path: &std::path::Path) {
+        let listener = tokio::net::UnixListener::bind(path).unwrap();
+
+        let state = self.state.clone();
+        let changed = self.changed.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+
+                tokio::spawn(handle(stream, state.clone(), changed.clone()));
+            }
+        });
+    }
+
+    fn commands(&self) -> Vec<String> {
+        self.state.lock().unwrap().commands.clone()
+    }
+}
+
+async fn handle<S>(stream: S, state: Arc<std::sync::Mutex<FakeMpdState>>, changed: Arc<Notify>)
+    where S: AsyncRead + AsyncWrite + Unpin
+{
+    let mut stream = BufReader::new(stream);
+
+    stream.get_mut().write_all(b"OK MPD 0.23.5\n").await.unwrap();
+
+    loop {
+        let mut line = String::new();
+
+        if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+            return;
+        }
+
+        let command = line.trim().to_string();
+
+        let response = match command.as_str() {
+            "idle player mixer" => {
+                changed.notified().await;
+
+                "changed: player\nOK\n".to_string()
+            }
+            "status" => {
+                let state = state.lock().unwrap();
+
+                format!("volume: {}\nstate: {}\nelapsed: 30.000\nduration: 120.000\nOK\n", state.volume, state.state)
+            }
+            "currentsong" => {
+                "file: music/artist/song.flac\nArtist: Artist A\nArtist: Artist B\nTitle: Song\nId: 7\nOK\n".to_string()
+            }
+            "outputs" => {
+                "outputid: 0\noutputname: Pulse\nplugin: pulse\noutputenabled: 1\noutputid: 1\noutputname: HTTP\nplugin: httpd\noutputenabled: 0\nOK\n".to_string()
+            }
+            "bogus" => "ACK [5@0] {bogus} unknown command \"bogus\"\n".to_string(),
+            _ => {
+                let mut state = state.lock().unwrap();
+
+                match command.as_str() {
+                    "play" | "pause 0" => state.state = "play".to_string(),
+                    "pause 1" => state.state = "pause".to_string(),
+                    _ => {}
+                }
+
+                if let Some(volume) = command.strip_prefix("setvol ") {
+                    state.volume = volume.parse().unwrap();
+                }
+
+                state.commands.push(String::from(&command));
+
+                "OK\n".to_string()
+            }
+        };
+
+        stream.get_mut().write_all(response.as_bytes()).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn reads_now_playing() {
+    let server = FakeMpd::new("play");
+    let address = server.serve_tcp().await;
+
+    let (_dir, config) = config_with(|cfg| cfg.mpd.address = String::from(&address));
+
+    let mut mpd = Mpd::new(config);
+
+    let now_playing = mpd.now_playing().await.unwrap().unwrap();
+    assert_eq!(now_playing.id, "7");
+    assert_eq!(now_playing.title, "Song");
+    assert_eq!(now_playing.artists, vec!["Artist A", "Artist B"]);
+    assert_eq!(now_playing.position_ms, 30_000);
+    assert_eq!(now_playing.duration_ms, 120_000);
+    assert!(now_playing.is_playing);
+}
+
+#[tokio::test]
+async fn stopped_player_has_nothing_playing() {
+    let server = FakeMpd::new("stop");
+    let address = server.serve_tcp().await;
+
+    let (_dir, config) = config_with(|cfg| cfg.mpd.address = String::from(&address));
+
+    let mut mpd = Mpd::new(config);
+
+    assert!(mpd.now_playing().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn controls_playback_and_volume() {
+    let server = FakeMpd::new("stop");
+    let address = server.serve_tcp().await;
+
+    let (_dir, config) = config_with(|cfg| cfg.mpd.address = String::from(&address));
+
+    let mut mpd = Mpd::new(config);
+
+    mpd.play().await.unwrap();
+    mpd.play().await.unwrap();
+    mpd.pause().await.unwrap();
+    mpd.play().await.unwrap();
+    mpd.next().await.unwrap();
+    mpd.previous().await.unwrap();
+    mpd.set_volume(0.75).await.unwrap();
+    mpd.seek_by(10_000).await.unwrap();
+    mpd.seek_by(-2_500).await.unwrap();
+
+    assert_eq!(server.commands(), vec!["play", "pause 1", "pause 0", "next", "previous", "setvol 75", "seekcur +10.000", "seekcur -2.500"]);
+
+    let devices = mpd.devices().await.unwrap();
+    assert_eq!(devices.len(), 2);
+    assert_eq!(devices[0].id, "Pulse");
+    assert!(devices[0].is_active);
+    assert!(!devices[1].is_active);
+    assert_eq!(devices[0].volume_percent, 75);
+}
+
+#[tokio::test]
+async fn idle_notifies_changes() {
+    let server = FakeMpd::new("play");
+    let address = server.serve_tcp().await;
+
+    let (_dir, config) = config_with(|cfg| cfg.mpd.address = String::from(&address));
+
+    let mut mpd = Mpd::new(config);
+
+    let changes = mpd.changes().unwrap();
+
+    // Give the watcher time to connect and enter idle before the player changes.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    server.changed.notify_one();
+
+    tokio::time::timeout(Duration::from_secs(2), changes.notified()).await.unwrap();
+}
+
+#[tokio::test]
+async fn reports_command_errors() {
+    let server = FakeMpd::new("play");
+    let address = server.serve_tcp().await;
+
+    let mut connection = spotify_osc::managers::mpd::MpdConnection::connect(&address, "").await.unwrap();
+
+    assert!(connection.command("bogus").await.is_err());
+    assert!(connection.command("status").await.is_ok());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn connects_over_unix_socket() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("mpd.sock");
+
+    let server = FakeMpd::new("pause");
+    server.serve_unix(&path);
+
+    let (_dir, config) = config_with(|cfg| cfg.mpd.address = path.display().to_string());
+
+    let mut mpd = Mpd::new(config);
+
+    let now_playing = mpd.now_playing().await.unwrap().unwrap();
+    assert!(!now_playing.is_playing);
+
+    mpd.play().await.unwrap();
+    assert_eq!(server.commands(), vec!["pause 0"]);
+}
@@ -0,0 +1,221 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use spotify_osc::managers::media::MediaSource;
+use spotify_osc::managers::mpris::Mpris;
+use tempfile::TempDir;
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+use common::config_with;
+
+/// Private dbus-daemon that is killed when dropped.
+struct DbusDaemon {
+    child: Child,
+    address: String,
+    _dir: TempDir
+}
+
+impl DbusDaemon {
+    fn start() -> Option<Self> {
+        let dir = tempfile::tempdir().unwrap();
+
+        let child = Command::new("dbus-daemon")
+            .arg("--session")
+            .arg("--nofork")
+            .arg("--print-address")
+            .arg(format!("--address=unix:path={}", dir.path().join("bus").display()))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+
+        let mut child = match child {
+            Ok(child) => child,
+            Err(_) => {
+                eprintln!("dbus-daemon is not available, skipping");
+                return None;
+            }
+        };
+
+        let mut address = String::new();
+        BufReader::new(child.stdout.take().unwrap()).read_line(&mut address).unwrap();
+
+        Some(Self {
+            child,
+            address: address.trim().to_string(),
+            _dir: dir
+        })
+    }
+}
+
+impl Drop for DbusDaemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct MockPlayerState {
+    status: String,
+    volume: f64,
+    calls: Vec<String>
+}
+
+struct MockPlayer {
+    state: Arc<std::sync::Mutex<MockPlayerState>>
+}
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+impl MockPlayer {
+    fn play_pause(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        state.status = if state.status == "Playing" { "Paused" } else { "Playing" }.to_string();
+        state.calls.push("PlayPause".to_string());
+    }
+
+    fn next(&self) {
+        self.state.lock().unwrap().calls.push("Next".to_string());
+    }
+
+    fn previous(&self) {
+        self.state.lock().unwrap().calls.push("Previous".to_string());
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let mut metadata = HashMap::new();
+
+        metadata.insert("mpris:trackid".to_string(), Value::from(ObjectPath::try_from("/org/mock/track/1").unwrap()).try_into().unwrap());
+        metadata.insert("mpris:length".to_string(), Value::from(200_000_000_i64).try_into().unwrap());
+        metadata.insert("xesam:title".to_string(), Value::from("Mock Song").try_into().unwrap());
+        metadata.insert("xesam:artist".to_string(), Value::from(vec!["Artist A", "Artist B"]).try_into().unwrap());
+
+        metadata
+    }
+
+    #[zbus(property)]
+    fn position(&self) -> i64 {
+        50_000_000
+    }
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        String::from(&self.state.lock().unwrap().status)
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.state.lock().unwrap().volume
+    }
+
+    #[zbus(property)]
+    fn set_volume(&mut self, value: f64) {
+        self.state.lock().unwrap().volume = value;
+    }
+}
+
+async fn serve_player(address: &str, name: &str, status: &str) -> (zbus::Connection, Arc<std::sync::Mutex<MockPlayerState>>) {
+    let state = Arc::new(std::sync::Mutex::new(MockPlayerState {
+        status: status.to_string(),
+        volume: 0.5,
+        calls: Vec::new()
+    }));
+
+    let connection = zbus::connection::Builder::address(address).unwrap()
+        .name(format!("org.mpris.MediaPlayer2.{}", name)).unwrap()
+        .serve_at("/org/mpris/MediaPlayer2", MockPlayer { state: state.clone() }).unwrap()
+        .build()
+        .await
+        .unwrap();
+
+    (connection, state)
+}
+
+#[tokio::test]
+async fn reads_now_playing_and_controls_player() {
+    let Some(daemon) = DbusDaemon::start() else { return };
+    let (_connection, state) = serve_player(&daemon.address, "mock", "Paused").await;
+
+    let (_dir, config) = config_with(|cfg| {
+        cfg.mpris.bus_address = String::from(&daemon.address);
+    });
+
+    let mut mpris = Mpris::new(config);
+
+    let now_playing = mpris.now_playing().await.unwrap().unwrap();
+    assert_eq!(now_playing.id, "/org/mock/track/1");
+    assert_eq!(now_playing.title, "Mock Song");
+    assert_eq!(now_playing.artists, vec!["Artist A", "Artist B"]);
+    assert_eq!(now_playing.position_ms, 50_000);
+    assert_eq!(now_playing.duration_ms, 200_000);
+    assert_eq!(now_playing.seek(), 0.25);
+    assert!(!now_playing.is_playing);
+
+    mpris.play().await.unwrap();
+    mpris.play().await.unwrap();
+    mpris.next().await.unwrap();
+    mpris.previous().await.unwrap();
+    mpris.pause().await.unwrap();
+    mpris.set_volume(0.8).await.unwrap();
+
+    let state = state.lock().unwrap();
+    assert_eq!(state.calls, vec!["PlayPause", "Next", "Previous", "PlayPause"]);
+    assert_eq!(state.status, "Paused");
+    assert!((state.volume - 0.8).abs() < 1e-6);
+}
+
+#[tokio::test]
+async fn stopped_player_has_nothing_playing() {
+    let Some(daemon) = DbusDaemon::start() else { return };
+    let (_connection, _state) = serve_player(&daemon.address, "mock", "Stopped").await;
+
+    let (_dir, config) = config_with(|cfg| {
+        cfg.mpris.bus_address = String::from(&daemon.address);
+    });
+
+    let mut mpris = Mpris::new(config);
+
+    assert!(mpris.now_playing().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn selects_configured_player() {
+    let Some(daemon) = DbusDaemon::start() else { return };
+    let (_first, first) = serve_player(&daemon.address, "alpha", "Playing").await;
+    let (_second, second) = serve_player(&daemon.address, "beta.instance42", "Playing").await;
+
+    let (_dir, config) = config_with(|cfg| {
+        cfg.mpris.bus_address = String::from(&daemon.address);
+        cfg.mpris.player = "beta".to_string();
+    });
+
+    let mut mpris = Mpris::new(config);
+
+    let devices = mpris.devices().await.unwrap();
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].id, "org.mpris.MediaPlayer2.beta.instance42");
+    assert_eq!(devices[0].volume_percent, 50);
+
+    mpris.next().await.unwrap();
+
+    assert!(first.lock().unwrap().calls.is_empty());
+    assert_eq!(second.lock().unwrap().calls, vec!["Next"]);
+}
+
+#[tokio::test]
+async fn no_player_has_nothing_playing() {
+    let Some(daemon) = DbusDaemon::start() else { return };
+
+    let (_dir, config) = config_with(|cfg| {
+        cfg.mpris.bus_address = String::from(&daemon.address);
+    });
+
+    let mut mpris = Mpris::new(config);
+
+    assert!(mpris.now_playing().await.unwrap().is_none());
+    assert!(mpris.next().await.is_err());
+}
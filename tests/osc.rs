@@ -0,0 +1,64 @@
+use rosc::{encoder, OscBundle, OscMessage, OscPacket, OscTime, OscType};
+use spotify_osc::utils::osc::decode_messages;
+
+fn message(addr: &str) -> OscPacket {
+    OscPacket::Message(OscMessage {
+        addr: addr.to_string(),
+        args: vec![OscType::Bool(true)]
+    })
+}
+
+fn bundle(seconds: u32, content: Vec<OscPacket>) -> OscPacket {
+    OscPacket::Bundle(OscBundle {
+        timetag: OscTime { seconds, fractional: 0 },
+        content
+    })
+}
+
+fn addresses(packet: &OscPacket) -> Vec<String> {
+    decode_messages(&encoder::encode(packet).unwrap()).unwrap()
+        .into_iter()
+        .map(|message| message.addr)
+        .collect()
+}
+
+#[test]
+fn decodes_single_message() {
+    assert_eq!(addresses(&message("/a")), vec!["/a"]);
+}
+
+#[test]
+fn unpacks_nested_bundles_in_timetag_order() {
+    let packet = bundle(10, vec![
+        message("/a"),
+        bundle(30, vec![message("/d"), message("/e")]),
+        bundle(20, vec![message("/c")]),
+        message("/b")
+    ]);
+
+    assert_eq!(addresses(&packet), vec!["/a", "/b", "/c", "/d", "/e"]);
+}
+
+#[test]
+fn empty_bundle_has_no_messages() {
+    assert!(addresses(&bundle(1, vec![])).is_empty());
+}
+
+#[test]
+fn malformed_packets_are_errors() {
+    assert!(decode_messages(b"garbage").is_err());
+    assert!(decode_messages(b"#bundle\0\x01").is_err());
+    assert!(decode_messages(&[]).is_err());
+}
+
+#[test]
+fn keeps_messages_without_arguments() {
+    let packet = OscPacket::Message(OscMessage {
+        addr: "/empty".to_string(),
+        args: vec![]
+    });
+
+    let messages = decode_messages(&encoder::encode(&packet).unwrap()).unwrap();
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].args.is_empty());
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use mdns_sd::{IfKind, ServiceDaemon, ServiceEvent};
+use spotify_osc::entities::config::{ArgumentType, ConfigFile, ConfigFileParameterOverride};
+use spotify_osc::entities::oscquery::{OscQueryHostInfo, OscQueryNode, ACCESS_WRITE};
+use spotify_osc::managers::avatar::CurrentAvatar;
+use spotify_osc::managers::oscquery::{oscquery_tree, OscQuery, OSCJSON_SERVICE, OSC_SERVICE};
+use common::config_with;
+
+/// Waits for `name` to be resolved under `service`, returning the port it's advertised on.
+fn browse(service: &str, name: &str) -> Option<u16> {
+    let daemon = ServiceDaemon::new().unwrap();
+    daemon.enable_interface(IfKind::LoopbackV4).unwrap();
+
+    let events = daemon.browse(service).unwrap();
+    let fullname = format!("{}.{}", name, service);
+
+    let port = loop {
+        match events.recv_timeout(Duration::from_secs(10)) {
+            Ok(ServiceEvent::ServiceResolved(info)) if info.get_fullname() == fullname => break Some(info.get_port()),
+            Ok(_) => continue,
+            Err(_) => break None
+        }
+    };
+
+    daemon.shutdown().unwrap();
+
+    port
+}
+
+#[test]
+fn tree_describes_receive_addresses() {
+    let mut cfg = ConfigFile::default();
+    cfg.parameters.spotify_previous = "/avatar/parameters/*_previous".to_string();
+    cfg.parameters.overrides.push(ConfigFileParameterOverride {
+        address: "/avatar/parameters/spotify_next".to_string(),
+        argument_type: ArgumentType::Int,
+        threshold: 0.5
+    });
+
+    let tree = oscquery_tree(&cfg, None);
+
+    let play = tree.find("/avatar/parameters/spotify_play").unwrap();
+    assert_eq!(play.full_path, "/avatar/parameters/spotify_play");
+    assert_eq!(play.osc_type.as_deref(), Some("T"));
+    assert_eq!(play.access, Some(ACCESS_WRITE));
+
+    assert_eq!(tree.find("/avatar/parameters/spotify_volume").unwrap().osc_type.as_deref(), Some("f"));
+    assert_eq!(tree.find("/avatar/parameters/spotify_next").unwrap().osc_type.as_deref(), Some("i"));
+    assert!(tree.find("/avatar/change").is_some());
+
+    let parameters = tree.find("/avatar/parameters").unwrap();
+    assert_eq!(parameters.full_path, "/avatar/parameters");
+    assert!(parameters.osc_type.is_none());
+    assert!(!parameters.contents.keys().any(|name| name.contains("previous")));
+
+    assert!(tree.find("/avatar/parameters/unknown").is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn serves_tree_and_host_info() {
+    let (_dir, config) = config_with(|_| {});
+
+    let osc: SocketAddr = "127.0.0.1:9123".parse().unwrap();
+    let service = OscQuery::start(config, Arc::new(CurrentAvatar::new()), "spotify-osc-serve-test", osc).unwrap();
+
+    let http = reqwest::Client::new();
+    let base = format!("http://127.0.0.1:{}", service.http_port());
+
+    let host_info: OscQueryHostInfo = http.get(format!("{}/?HOST_INFO", base)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(host_info.name, "spotify-osc-serve-test");
+    assert_eq!(host_info.osc_ip, "127.0.0.1");
+    assert_eq!(host_info.osc_port, 9123);
+    assert_eq!(host_info.osc_transport, "UDP");
+
+    let root: OscQueryNode = http.get(format!("{}/", base)).send().await.unwrap().json().await.unwrap();
+    assert!(root.find("/avatar/parameters/spotify_stop").is_some());
+
+    let node: OscQueryNode = http.get(format!("{}/avatar/parameters/spotify_volume", base)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(node.osc_type.as_deref(), Some("f"));
+
+    let attribute: serde_json::Value = http.get(format!("{}/avatar/parameters/spotify_volume?TYPE", base)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(attribute, serde_json::json!({ "TYPE": "f" }));
+
+    let missing = http.get(format!("{}/avatar/parameters/unknown", base)).send().await.unwrap();
+    assert_eq!(missing.status(), 404);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn advertises_over_mdns() {
+    let (_dir, config) = config_with(|_| {});
+
+    let osc: SocketAddr = "127.0.0.1:9124".parse().unwrap();
+    let service = OscQuery::start(config, Arc::new(CurrentAvatar::new()), "spotify-osc-mdns-test", osc).unwrap();
+    let http_port = service.http_port();
+
+    let (oscjson, osc_port) = tokio::task::spawn_blocking(|| {
+        (browse(OSCJSON_SERVICE, "spotify-osc-mdns-test"), browse(OSC_SERVICE, "spotify-osc-mdns-test"))
+    }).await.unwrap();
+
+    assert_eq!(oscjson, Some(http_port));
+    assert_eq!(osc_port, Some(9124));
+
+    let host_info: OscQueryHostInfo = reqwest::get(format!("http://127.0.0.1:{}/?HOST_INFO", oscjson.unwrap())).await.unwrap().json().await.unwrap();
+    assert_eq!(host_info.osc_port, 9124);
+}
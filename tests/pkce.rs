@@ -0,0 +1,125 @@
+mod common;
+
+use std::sync::Arc;
+use actix_web::{web, App};
+use actix_web::test::{call_service, init_service, TestRequest};
+use spotify_osc::managers::spotify::{Spotify, SpotifyAuthError};
+use spotify_osc::routes::spotify::{spotify_callback, spotify_setup};
+use spotify_osc::utils::pkce::{challenge_for, Pkce};
+use spotify_osc::managers::authorization::Authorizations;
+use common::{config_for, location_query, stored_credentials, web_data, MockSpotify};
+
+#[test]
+fn challenge_is_unpadded_base64url_sha256() {
+    // `printf verifier | openssl dgst -sha256 -binary | base64`, made url safe and unpadded.
+    assert_eq!(challenge_for("dBjftJeZ4CVP-mJ92K2rXY9e2PwOQmmq1p6P9dgfXwQ"), "LRKBqQK98WwlfgtgBVpjZL4ytWnZq99EnD8QrHWz23E");
+
+    let pkce = Pkce::new();
+    assert_eq!(pkce.verifier.len(), 64);
+    assert_eq!(pkce.challenge, challenge_for(&pkce.verifier));
+    assert_ne!(pkce.verifier, Pkce::new().verifier);
+}
+
+#[actix_web::test]
+async fn exchanges_code_with_verifier() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    config.lock().await.cfg.spotify.client_secret = "".to_string();
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config.clone());
+
+    spotify.init_credentials(&"auth-code".to_string(), Some("verifier".to_string())).await.unwrap();
+
+    let token_requests = mock.requests_to("/api/token");
+    assert_eq!(token_requests.len(), 1);
+    assert!(token_requests[0].authorization.is_none());
+    assert!(token_requests[0].body.contains("client_id=client-id"));
+    assert!(token_requests[0].body.contains("code_verifier=verifier"));
+
+    assert_eq!(stored_credentials(&config).await.refresh_token, "refresh-1");
+
+    mock.stop().await;
+}
+
+#[actix_web::test]
+async fn refreshes_without_basic_auth() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    config.lock().await.cfg.spotify.client_secret = "".to_string();
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    let token_requests = mock.requests_to("/api/token");
+    assert!(token_requests[0].authorization.is_none());
+    assert!(token_requests[0].body.contains("grant_type=refresh_token"));
+    assert!(token_requests[0].body.contains("client_id=client-id"));
+
+    mock.stop().await;
+}
+
+#[actix_web::test]
+async fn client_secret_keeps_basic_auth() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    let token_requests = mock.requests_to("/api/token");
+    assert!(token_requests[0].authorization.as_deref().unwrap().starts_with("Basic "));
+    assert!(!token_requests[0].body.contains("client_id"));
+
+    mock.stop().await;
+}
+
+#[actix_web::test]
+async fn exchange_requires_verifier() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    config.lock().await.cfg.spotify.client_secret = "".to_string();
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+
+    assert!(matches!(spotify.init_credentials(&"auth-code".to_string(), None).await, Err(SpotifyAuthError::MissingVerifier)));
+    assert!(mock.requests().is_empty());
+
+    mock.stop().await;
+}
+
+#[actix_web::test]
+async fn setup_and_callback_share_the_verifier() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    config.lock().await.cfg.spotify.client_secret = "".to_string();
+
+    let app = init_service(App::new()
+        .app_data(web::Data::new(web_data(config.clone(), Authorizations::default()).await))
+        .service(spotify_setup)
+        .service(spotify_callback)).await;
+
+    let res = call_service(&app, TestRequest::get().uri("/setup").to_request()).await;
+    let location = res.headers().get("location").unwrap().to_str().unwrap().to_string();
+
+    let query = location_query(&location);
+    assert_eq!(query["code_challenge_method"], "S256");
+
+    let uri = format!("/callback?code=auth-code&state={}", query["state"]);
+    let res = call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+    assert!(res.status().is_success());
+
+    let token_requests = mock.requests_to("/api/token");
+    assert_eq!(token_requests.len(), 1);
+
+    let form: std::collections::HashMap<String, String> = serde_urlencoded::from_str(&token_requests[0].body).unwrap();
+    let challenge = challenge_for(&form["code_verifier"]);
+
+    assert_eq!(query["code_challenge"], challenge);
+    assert_eq!(stored_credentials(&config).await.refresh_token, "refresh-1");
+
+    mock.stop().await;
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use std::sync::Arc;
+use actix_web::{web, App};
+use actix_web::test::{call_service, init_service, TestRequest};
+use spotify_osc::config::credentials::{Credentials, CredentialsStore};
+use spotify_osc::entities::config::{ConfigFile, ConfigFileSpotifyProfile};
+use spotify_osc::managers::authorization::Authorizations;
+use spotify_osc::managers::media::{MediaError, MediaSource};
+use spotify_osc::managers::profiles::{ProfileError, SpotifyProfiles};
+use spotify_osc::managers::spotify::SpotifyAuthError;
+use spotify_osc::routes::spotify::{spotify_callback, spotify_profile, spotify_profiles, spotify_setup};
+use common::{config_for, config_with, location_query, now_playing_json, stored_profile_credentials, web_data, MockSpotify};
+
+fn profiles(cfg: &mut ConfigFile) {
+    cfg.spotify.profiles = ["alice", "bob"].iter().map(|name| ConfigFileSpotifyProfile {
+        name: name.to_string(),
+        credentials_path: "".to_string()
+    }).collect();
+}
+
+#[test]
+fn profiles_get_their_own_credentials_file() {
+    let (_dir, config) = config_with(|cfg| {
+        profiles(cfg);
+
+        cfg.spotify.credentials_path = "secrets/credentials.json".to_string();
+        cfg.spotify.profiles.push(ConfigFileSpotifyProfile {
+            name: "carol".to_string(),
+            credentials_path: "/srv/carol.json".to_string()
+        });
+    });
+
+    let config = config.blocking_lock();
+
+    assert_eq!(config.cfg.spotify.credentials_path_for("alice"), "secrets/credentials.json");
+    assert_eq!(config.cfg.spotify.credentials_path_for("bob"), "secrets/credentials-bob.json");
+    assert_eq!(config.cfg.spotify.credentials_path_for("carol"), "/srv/carol.json");
+}
+
+#[tokio::test]
+async fn setup_logs_in_the_requested_profile() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    profiles(&mut config.lock().await.cfg);
+
+    let data = web::Data::new(web_data(config.clone(), Authorizations::default()).await);
+
+    let app = init_service(App::new()
+        .app_data(data.clone())
+        .service(spotify_setup)
+        .service(spotify_callback)).await;
+
+    let res = call_service(&app, TestRequest::get().uri("/setup?profile=nobody").to_request()).await;
+    assert_eq!(res.status().as_u16(), 400);
+
+    let res = call_service(&app, TestRequest::get().uri("/setup?profile=bob").to_request()).await;
+    let query = location_query(res.headers().get("location").unwrap().to_str().unwrap());
+    assert_eq!(query["show_dialog"], "true");
+
+    let uri = format!("/callback?code=auth-code&state={}", query["state"]);
+    let res = call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+    assert!(res.status().is_success());
+
+    assert_eq!(stored_profile_credentials(&config, "bob").await.refresh_token, "refresh-1");
+    assert!(stored_profile_credentials(&config, "alice").await.refresh_token.is_empty());
+
+    // Logging in bob leaves the tokens from the config to alice.
+    assert_eq!(config.lock().await.cfg.spotify.refresh_token, "refresh-0");
+
+    data.spotify.lock().await.get_mut("alice").unwrap().tokens().load().await.unwrap();
+
+    assert_eq!(stored_profile_credentials(&config, "alice").await.refresh_token, "refresh-0");
+    assert!(config.lock().await.cfg.spotify.refresh_token.is_empty());
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn switches_the_active_profile() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    {
+        let mut config = config.lock().await;
+        profiles(&mut config.cfg);
+        config.cfg.spotify.active_profile = "bob".to_string();
+    }
+
+    let mut profiles = SpotifyProfiles::new(Arc::new(reqwest::Client::new()), config).await;
+    assert_eq!(profiles.names(), vec!["alice", "bob"]);
+    assert_eq!(profiles.active_name(), "bob");
+
+    // Only alice, the first profile, gets the tokens that were in the config.
+    for spotify in profiles.iter_mut() {
+        let _ = spotify.authenticate().await;
+    }
+
+    mock.set_now_playing(Some(now_playing_json("track-1", true)));
+
+    assert!(matches!(MediaSource::now_playing(&mut profiles).await, Err(MediaError::Spotify(SpotifyAuthError::NotInitialized))));
+
+    profiles.switch_index(0).unwrap();
+    assert_eq!(MediaSource::now_playing(&mut profiles).await.unwrap().unwrap().id, "track-1");
+
+    assert_eq!(profiles.switch_index(2), Err(ProfileError::OutOfRange(2)));
+    assert_eq!(profiles.switch("carol"), Err(ProfileError::Unknown("carol".to_string())));
+    assert_eq!(profiles.active_name(), "alice");
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn web_server_switches_profiles() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    profiles(&mut config.lock().await.cfg);
+
+    CredentialsStore::from_config(&config.lock().await.cfg, Some("bob")).save(&Credentials {
+        refresh_token: "refresh-bob".to_string(),
+        ..Credentials::default()
+    }).unwrap();
+
+    let data = web_data(config, Authorizations::default()).await;
+
+    let app = init_service(App::new()
+        .app_data(web::Data::new(data.clone()))
+        .service(spotify_profiles)
+        .service(spotify_profile)).await;
+
+    // Following a link only lists the profiles.
+    let res = call_service(&app, TestRequest::get().uri("/profile?profile=bob").to_request()).await;
+    assert!(res.status().is_success());
+    assert_eq!(data.spotify.lock().await.active_name(), "alice");
+
+    let res = call_service(&app, TestRequest::post().uri("/profile").set_form([("profile", "bob")]).to_request()).await;
+    assert!(res.status().is_success());
+    assert_eq!(data.spotify.lock().await.active_name(), "bob");
+
+    data.spotify.lock().await.active().authenticate().await.unwrap();
+    assert!(mock.requests_to("/api/token")[0].body.contains("refresh_token=refresh-bob"));
+
+    let res = call_service(&app, TestRequest::post().uri("/profile").set_form([("profile", "nobody")]).to_request()).await;
+    assert_eq!(res.status().as_u16(), 404);
+    assert_eq!(data.spotify.lock().await.active_name(), "bob");
+
+    mock.stop().await;
+}
@@ -0,0 +1,94 @@
+use spotify_osc::entities::config::{ConfigFile, ConfigFileLongPress};
+use spotify_osc::entities::osc::OscAction;
+use spotify_osc::managers::router::{OscRouter, SharedRouter};
+use spotify_osc::utils::osc::matches_pattern;
+
+#[test]
+fn matches_plain_addresses_exactly() {
+    assert!(matches_pattern("/avatar/parameters/spotify_play", "/avatar/parameters/spotify_play"));
+    assert!(!matches_pattern("/avatar/parameters/spotify_play", "/avatar/parameters/spotify_play2"));
+    assert!(!matches_pattern("/avatar/parameters/spotify_play", "/avatar/parameters/spotify"));
+}
+
+#[test]
+fn star_and_question_mark_stay_within_a_part() {
+    assert!(matches_pattern("/avatar/parameters/*_play", "/avatar/parameters/spotify_play"));
+    assert!(matches_pattern("/avatar/parameters/*", "/avatar/parameters/"));
+    assert!(!matches_pattern("/avatar/*", "/avatar/parameters/spotify_play"));
+    assert!(matches_pattern("/avatar/*/spotify_play", "/avatar/parameters/spotify_play"));
+
+    assert!(matches_pattern("/avatar/parameters/play?", "/avatar/parameters/play1"));
+    assert!(!matches_pattern("/avatar/parameters/play?", "/avatar/parameters/play"));
+    assert!(!matches_pattern("/avatar?parameters", "/avatar/parameters"));
+}
+
+#[test]
+fn character_classes() {
+    assert!(matches_pattern("/play[0-9]", "/play7"));
+    assert!(!matches_pattern("/play[0-9]", "/playx"));
+    assert!(matches_pattern("/play[!0-9]", "/playx"));
+    assert!(!matches_pattern("/play[!0-9]", "/play7"));
+    assert!(matches_pattern("/play[ab-]", "/play-"));
+    assert!(!matches_pattern("/play[0-9", "/play7"));
+}
+
+#[test]
+fn alternatives() {
+    assert!(matches_pattern("/avatar/parameters/{spotify,music}_play", "/avatar/parameters/music_play"));
+    assert!(matches_pattern("/avatar/parameters/{spotify,music}_play", "/avatar/parameters/spotify_play"));
+    assert!(!matches_pattern("/avatar/parameters/{spotify,music}_play", "/avatar/parameters/radio_play"));
+    assert!(!matches_pattern("/{a,b", "/a"));
+}
+
+#[test]
+fn routes_config_addresses_and_patterns() {
+    let mut cfg = ConfigFile::default();
+    cfg.parameters.spotify_next = "/avatar/parameters/{spotify,music}_next".to_string();
+
+    let router = OscRouter::new(&cfg);
+
+    assert_eq!(router.route("/avatar/parameters/spotify_play"), vec![OscAction::Play]);
+    assert_eq!(router.route("/avatar/parameters/music_next"), vec![OscAction::Next]);
+    assert_eq!(router.route("/avatar/parameters/spotify_next"), vec![OscAction::Next]);
+    assert!(router.route("/avatar/parameters/unrelated").is_empty());
+}
+
+#[test]
+fn swapping_the_router_replaces_routes() {
+    let mut cfg = ConfigFile::default();
+    let shared = SharedRouter::new(OscRouter::new(&cfg));
+
+    let before = shared.load();
+
+    cfg.parameters.spotify_play = "/avatar/parameters/*_resume".to_string();
+    shared.store(OscRouter::new(&cfg));
+
+    assert_eq!(before.route("/avatar/parameters/spotify_play"), vec![OscAction::Play]);
+    assert!(shared.load().route("/avatar/parameters/spotify_play").is_empty());
+    assert_eq!(shared.load().route("/avatar/parameters/music_resume"), vec![OscAction::Play]);
+}
+
+#[test]
+fn long_press_actions_by_address() {
+    let mut cfg = ConfigFile::default();
+
+    // Buttons act on press unless a long press action is configured.
+    assert_eq!(OscRouter::new(&cfg).long_press("/avatar/parameters/spotify_next"), None);
+
+    cfg.buttons.long_press = vec![
+        ConfigFileLongPress {
+            address: "/avatar/parameters/spotify_next".to_string(),
+            action: OscAction::SeekForward
+        },
+        ConfigFileLongPress {
+            address: "/avatar/parameters/*_previous".to_string(),
+            action: OscAction::SeekBackward
+        }
+    ];
+
+    let router = OscRouter::new(&cfg);
+
+    assert_eq!(router.long_press("/avatar/parameters/spotify_next"), Some(OscAction::SeekForward));
+    assert_eq!(router.long_press("/avatar/parameters/music_previous"), Some(OscAction::SeekBackward));
+    assert_eq!(router.long_press("/avatar/parameters/spotify_play"), None);
+}
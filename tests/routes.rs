@@ -0,0 +1,113 @@
+mod common;
+
+use std::time::Duration;
+use actix_web::{web, App};
+use actix_web::body::to_bytes;
+use actix_web::dev::ServiceResponse;
+use actix_web::test::{call_service, init_service, TestRequest};
+use spotify_osc::managers::authorization::{Authorization, AuthorizationError, Authorizations};
+use spotify_osc::routes::spotify::{spotify_callback, spotify_setup};
+use common::{config_for, location_query, stored_credentials, web_data, MockSpotify};
+
+async fn body(res: ServiceResponse) -> String {
+    String::from_utf8(to_bytes(res.into_body()).await.unwrap().to_vec()).unwrap()
+}
+
+#[actix_web::test]
+async fn callback_accepts_state_from_setup() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let app = init_service(App::new()
+        .app_data(web::Data::new(web_data(config.clone(), Authorizations::default()).await))
+        .service(spotify_setup)
+        .service(spotify_callback)).await;
+
+    let res = call_service(&app, TestRequest::get().uri("/setup").to_request()).await;
+    assert_eq!(res.status().as_u16(), 302);
+
+    let query = location_query(res.headers().get("location").unwrap().to_str().unwrap());
+    assert_eq!(query["state"].len(), 32);
+    assert_eq!(query["scope"], "user-modify-playback-state user-read-currently-playing user-read-playback-state");
+    assert_eq!(query["redirect_uri"], "http://localhost:8080/callback");
+
+    let uri = format!("/callback?code=auth-code&state={}", query["state"]);
+    let res = call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+    assert!(res.status().is_success());
+    assert_eq!(stored_credentials(&config).await.refresh_token, "refresh-1");
+
+    // The same state can't be used twice.
+    let res = call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert_eq!(mock.requests_to("/api/token").len(), 1);
+
+    mock.stop().await;
+}
+
+#[actix_web::test]
+async fn callback_rejects_missing_or_unknown_state() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let app = init_service(App::new()
+        .app_data(web::Data::new(web_data(config, Authorizations::default()).await))
+        .service(spotify_setup)
+        .service(spotify_callback)).await;
+
+    call_service(&app, TestRequest::get().uri("/setup").to_request()).await;
+
+    let res = call_service(&app, TestRequest::get().uri("/callback?code=auth-code").to_request()).await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert!(body(res).await.contains("missing its state"));
+
+    let res = call_service(&app, TestRequest::get().uri("/callback?code=auth-code&state=forged").to_request()).await;
+    assert_eq!(res.status().as_u16(), 400);
+    assert!(body(res).await.contains("doesn't match"));
+
+    assert!(mock.requests_to("/api/token").is_empty());
+
+    mock.stop().await;
+}
+
+#[actix_web::test]
+async fn callback_handles_cancelled_login() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let app = init_service(App::new()
+        .app_data(web::Data::new(web_data(config.clone(), Authorizations::default()).await))
+        .service(spotify_setup)
+        .service(spotify_callback)).await;
+
+    let res = call_service(&app, TestRequest::get().uri("/setup").to_request()).await;
+    let query = location_query(res.headers().get("location").unwrap().to_str().unwrap());
+
+    let uri = format!("/callback?error=access_denied&state={}", query["state"]);
+    let res = call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+    assert!(res.status().is_success());
+    assert!(body(res).await.contains("cancelled"));
+
+    assert!(mock.requests_to("/api/token").is_empty());
+    assert_eq!(config.lock().await.cfg.spotify.refresh_token, "refresh-0");
+
+    mock.stop().await;
+}
+
+#[test]
+fn states_expire() {
+    let authorizations = Authorizations::new(Duration::from_millis(50));
+
+    let authorization = Authorization {
+        verifier: Some("verifier".to_string()),
+        profile: Some("default".to_string())
+    };
+
+    let state = authorizations.start(authorization.clone());
+    assert_eq!(authorizations.finish(Some(&state)), Ok(authorization));
+
+    let state = authorizations.start(Authorization::default());
+    std::thread::sleep(Duration::from_millis(60));
+    assert_eq!(authorizations.finish(Some(&state)), Err(AuthorizationError::Expired));
+
+    assert_eq!(authorizations.finish(None), Err(AuthorizationError::MissingState));
+}
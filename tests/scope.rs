@@ -0,0 +1,48 @@
+mod common;
+
+use spotify_osc::entities::scope::{Scopes, USER_MODIFY_PLAYBACK_STATE, USER_READ_CURRENTLY_PLAYING, USER_READ_PLAYBACK_STATE};
+use common::config_with;
+
+#[test]
+fn playback_controls_need_playback_scopes() {
+    let (_dir, config) = config_with(|_| {});
+    let config = config.blocking_lock();
+
+    let scopes = Scopes::required_for(&config.cfg);
+    assert!(scopes.contains(USER_READ_CURRENTLY_PLAYING));
+    assert!(scopes.contains(USER_READ_PLAYBACK_STATE));
+    assert!(scopes.contains(USER_MODIFY_PLAYBACK_STATE));
+}
+
+#[test]
+fn display_only_needs_currently_playing() {
+    let (_dir, config) = config_with(|cfg| {
+        cfg.parameters.spotify_play = "".to_string();
+        cfg.parameters.spotify_stop = "".to_string();
+        cfg.parameters.spotify_next = "".to_string();
+        cfg.parameters.spotify_previous = "".to_string();
+        cfg.parameters.spotify_volume = "".to_string();
+    });
+    let config = config.blocking_lock();
+
+    assert_eq!(Scopes::required_for(&config.cfg).to_string(), USER_READ_CURRENTLY_PLAYING);
+}
+
+#[test]
+fn reports_scopes_that_were_not_granted() {
+    let required = Scopes::new()
+        .with(USER_READ_CURRENTLY_PLAYING)
+        .with(USER_MODIFY_PLAYBACK_STATE);
+
+    let granted = Scopes::parse("user-read-currently-playing  user-read-email");
+
+    assert_eq!(required.missing(&granted).to_string(), USER_MODIFY_PLAYBACK_STATE);
+    assert!(required.missing(&required).is_empty());
+}
+
+#[test]
+fn setup_url_sits_next_to_the_callback() {
+    let (_dir, config) = config_with(|cfg| cfg.spotify.callback_url = "http://192.168.1.2:9000/callback".to_string());
+
+    assert_eq!(config.blocking_lock().cfg.get_setup_url(), "http://192.168.1.2:9000/setup");
+}
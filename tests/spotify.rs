@@ -0,0 +1,552 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+use spotify_osc::http::RequestError;
+use spotify_osc::managers::media::MediaSource;
+use spotify_osc::managers::spotify::{Spotify, SpotifyAuthError};
+use common::{config_for, now_playing_json, stored_credentials, MockSpotify};
+
+#[tokio::test]
+async fn authenticate_refreshes_token() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config.clone());
+
+    spotify.authenticate().await.unwrap();
+
+    assert!(spotify.active);
+    assert_eq!(spotify.tokens().access_token().await.unwrap(), "access-1");
+
+    let token_requests = mock.requests_to("/api/token");
+    assert_eq!(token_requests.len(), 1);
+    assert!(token_requests[0].body.contains("grant_type=refresh_token"));
+    assert!(token_requests[0].body.contains("refresh_token=refresh-0"));
+
+    assert_eq!(stored_credentials(&config).await.token, "access-1");
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn init_credentials_exchanges_code() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config.clone());
+
+    spotify.init_credentials(&"auth-code".to_string(), None).await.unwrap();
+
+    let token_requests = mock.requests_to("/api/token");
+    assert_eq!(token_requests.len(), 1);
+    assert!(token_requests[0].body.contains("grant_type=authorization_code"));
+    assert!(token_requests[0].body.contains("code=auth-code"));
+
+    let credentials = stored_credentials(&config).await;
+    assert_eq!(credentials.token, "access-1");
+    assert_eq!(credentials.refresh_token, "refresh-1");
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn authenticate_requires_setup() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    config.lock().await.cfg.spotify.refresh_token = "".to_string();
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+
+    assert!(matches!(spotify.authenticate().await, Err(SpotifyAuthError::ConfigNotInitialized)));
+    assert!(mock.requests().is_empty());
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn now_playing_reads_current_track() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    assert!(spotify.now_playing().await.unwrap().is_none());
+
+    mock.set_now_playing(Some(now_playing_json("track-1", true)));
+
+    let info = spotify.now_playing().await.unwrap().unwrap();
+    assert_eq!(info.item.id, "track-1");
+    assert_eq!(info.item.artists.len(), 2);
+    assert!(info.is_playing);
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn now_playing_reauthenticates_on_expired_token() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    mock.set_now_playing(Some(now_playing_json("track-1", true)));
+    mock.expire_token();
+
+    let info = spotify.now_playing().await.unwrap().unwrap();
+    assert_eq!(info.item.id, "track-1");
+    assert_eq!(spotify.tokens().access_token().await.unwrap(), "access-2");
+    assert_eq!(mock.requests_to("/api/token").len(), 2);
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn requires_authentication_before_requests() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+
+    assert!(matches!(spotify.now_playing().await, Err(SpotifyAuthError::NotInitialized)));
+    assert!(mock.requests().is_empty());
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn get_devices_lists_devices() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    let devices = spotify.get_devices().await.unwrap();
+    assert_eq!(devices.devices.len(), 2);
+    assert_eq!(devices.get_active().unwrap().id, "device-2");
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn set_volume_sends_percentage() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    spotify.set_volume(&"device-2".to_string(), 42).await.unwrap();
+
+    let requests = mock.requests_to("/v1/me/player/volume");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "PUT");
+    assert_eq!(requests[0].query.get("device_id").unwrap(), "device-2");
+    assert_eq!(requests[0].query.get("volume_percent").unwrap(), "42");
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn media_source_controls_active_device() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    let source: &mut dyn MediaSource = &mut spotify;
+
+    source.next().await.unwrap();
+    source.set_volume(0.25).await.unwrap();
+
+    let next = mock.requests_to("/v1/me/player/next");
+    assert_eq!(next.len(), 1);
+    assert_eq!(next[0].query.get("device_id").unwrap(), "device-2");
+
+    let volume = mock.requests_to("/v1/me/player/volume");
+    assert_eq!(volume[0].query.get("volume_percent").unwrap(), "25");
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn media_source_seeks_from_current_position() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    mock.set_now_playing(Some(now_playing_json("track-1", true)));
+
+    let source: &mut dyn MediaSource = &mut spotify;
+
+    source.seek_by(10_000).await.unwrap();
+    source.seek_by(-60_000).await.unwrap();
+
+    let seek = mock.requests_to("/v1/me/player/seek");
+    assert_eq!(seek.len(), 2);
+    assert_eq!(seek[0].query.get("device_id").unwrap(), "device-2");
+    assert_eq!(seek[0].query.get("position_ms").unwrap(), "40000");
+    assert_eq!(seek[1].query.get("position_ms").unwrap(), "0");
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn media_source_play_transfers_playback_when_idle() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    let source: &mut dyn MediaSource = &mut spotify;
+
+    source.play().await.unwrap();
+
+    let transfer: Vec<_> = mock.requests_to("/v1/me/player").into_iter().filter(|req| req.method == "PUT").collect();
+    assert_eq!(transfer.len(), 1);
+    assert!(transfer[0].body.contains("\"device_ids\":[\"device-2\"]"));
+    assert!(transfer[0].body.contains("\"play\":true"));
+
+    mock.set_now_playing(Some(now_playing_json("track-1", false)));
+
+    let now_playing = source.now_playing().await.unwrap().unwrap();
+    assert_eq!(now_playing.artists, vec!["Artist A", "Artist B"]);
+    assert_eq!(now_playing.seek(), 0.25);
+
+    source.play().await.unwrap();
+    assert_eq!(mock.requests_to("/v1/me/player/play").len(), 1);
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn transient_errors_are_retried() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    mock.fail_next(&[503]);
+
+    assert_eq!(spotify.get_devices().await.unwrap().devices.len(), 2);
+    assert_eq!(mock.requests_to("/v1/me/player/devices").len(), 2);
+    assert_eq!(mock.requests_to("/api/token").len(), 1);
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn persistent_server_errors_give_up() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    mock.fail_next(&[500, 502, 503, 504]);
+
+    assert!(spotify.get_devices().await.is_err());
+    assert_eq!(mock.requests_to("/v1/me/player/devices").len(), 3);
+    assert_eq!(mock.requests_to("/api/token").len(), 1);
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn client_errors_do_not_refresh() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    mock.fail_next(&[404]);
+
+    assert!(spotify.set_playback_next(&"device-2".to_string()).await.is_err());
+    assert_eq!(mock.requests_to("/v1/me/player/next").len(), 1);
+    assert_eq!(mock.requests_to("/api/token").len(), 1);
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn unauthorized_refreshes_only_once() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    mock.fail_next(&[401, 401, 401]);
+
+    assert!(spotify.now_playing().await.is_err());
+    assert_eq!(mock.requests_to("/v1/me/player/currently-playing").len(), 2);
+    assert_eq!(mock.requests_to("/api/token").len(), 2);
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn errors_carry_status_and_body() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    mock.fail_next_with(404, "Device not found");
+
+    match spotify.set_playback_next(&"device-2".to_string()).await {
+        Err(SpotifyAuthError::Request(RequestError::Status { status, error: Some(error) })) => {
+            assert_eq!(status.as_u16(), 404);
+            assert_eq!(error.message, "Device not found");
+            assert_eq!(error.reason.as_deref(), Some("MOCKED"));
+        }
+        res => panic!("unexpected result: {:?}", res)
+    }
+
+    mock.fail_next_with(403, "Insufficient client scope");
+
+    let err = spotify.get_devices().await.err().unwrap();
+    assert!(matches!(err, SpotifyAuthError::Request(RequestError::MissingScope(_))));
+    assert!(err.to_string().contains("Insufficient client scope"));
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn refresh_errors_carry_accounts_body() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    config.lock().await.cfg.spotify.refresh_token = "revoked".to_string();
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+
+    match spotify.authenticate().await {
+        Err(SpotifyAuthError::Request(RequestError::Status { status, error: Some(error) })) => {
+            assert_eq!(status.as_u16(), 400);
+            assert_eq!(error.message, "Refresh token revoked");
+            assert_eq!(error.reason.as_deref(), Some("invalid_grant"));
+        }
+        res => panic!("unexpected result: {:?}", res)
+    }
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn network_errors_are_reported() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config.clone());
+    spotify.authenticate().await.unwrap();
+
+    mock.stop().await;
+
+    let err = spotify.get_devices().await.err().unwrap();
+    assert!(matches!(err, SpotifyAuthError::Request(RequestError::Network(_))));
+    assert!(std::error::Error::source(&err).is_some());
+}
+
+#[tokio::test]
+async fn rate_limiting_pauses_requests() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    mock.rate_limit_next(1);
+
+    match spotify.get_devices().await {
+        Err(SpotifyAuthError::Request(RequestError::RateLimited { retry_after })) => {
+            assert_eq!(retry_after, Some(Duration::from_secs(1)));
+        }
+        res => panic!("unexpected result: {:?}", res.map(|_| ()))
+    }
+
+    assert!(MediaSource::rate_limited(&spotify));
+
+    // Nothing reaches Spotify while the window is open, and the token is left alone.
+    assert!(matches!(spotify.set_playback_next(&"device-2".to_string()).await,
+        Err(SpotifyAuthError::Request(RequestError::RateLimited { .. }))));
+    assert_eq!(mock.requests_to("/v1/me/player/devices").len(), 1);
+    assert!(mock.requests_to("/v1/me/player/next").is_empty());
+    assert_eq!(mock.requests_to("/api/token").len(), 1);
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    assert!(!MediaSource::rate_limited(&spotify));
+    spotify.set_playback_next(&"device-2".to_string()).await.unwrap();
+    assert_eq!(mock.requests_to("/v1/me/player/next").len(), 1);
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn rate_limiter_is_shared() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    let limiter = spotify.rate_limiter();
+    assert!(!limiter.is_limited());
+
+    mock.rate_limit_next(30);
+    assert!(spotify.now_playing().await.is_err());
+
+    let remaining = limiter.remaining().unwrap();
+    assert!(remaining > Duration::from_secs(25) && remaining <= Duration::from_secs(30));
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn refreshes_token_before_it_expires() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    mock.set_expires_in(2);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    spotify.authenticate().await.unwrap();
+
+    spotify.get_devices().await.unwrap();
+    assert_eq!(mock.requests_to("/api/token").len(), 1);
+
+    // Halfway through the token's lifetime it counts as about to expire.
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    spotify.get_devices().await.unwrap();
+    assert_eq!(mock.requests_to("/api/token").len(), 2);
+    assert_eq!(mock.requests_to("/v1/me/player/devices").len(), 2);
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn config_is_free_while_refreshing() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    mock.delay_token(Duration::from_millis(500));
+
+    let spotify = Spotify::new(Arc::new(reqwest::Client::new()), config.clone());
+    let tokens = spotify.tokens();
+
+    let refresh = tokio::spawn(async move { tokens.refresh(None).await });
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert_eq!(mock.requests_to("/api/token").len(), 1);
+    assert!(config.try_lock().is_ok());
+
+    assert_eq!(refresh.await.unwrap().unwrap(), "access-1");
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn background_refresher_renews_token() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    mock.set_expires_in(2);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config.clone());
+    let refresher = spotify.tokens().spawn_refresher();
+
+    spotify.authenticate().await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+
+    assert_eq!(mock.requests_to("/api/token").len(), 2);
+    assert_eq!(stored_credentials(&config).await.token, "access-2");
+
+    refresher.abort();
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn saves_rotated_refresh_token() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    mock.rotate_refresh_token("refresh-rotated");
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config.clone());
+    spotify.authenticate().await.unwrap();
+
+    assert_eq!(stored_credentials(&config).await.refresh_token, "refresh-rotated");
+
+    // A later refresh has to use the rotated token.
+    spotify.authenticate().await.unwrap();
+    assert!(mock.requests_to("/api/token")[1].body.contains("refresh_token=refresh-rotated"));
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn concurrent_callers_share_one_refresh() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let spotify = Spotify::new(Arc::new(reqwest::Client::new()), config);
+    let tokens = spotify.tokens();
+
+    let tasks: Vec<_> = (0..5).map(|_| {
+        let tokens = tokens.clone();
+
+        tokio::spawn(async move { tokens.access_token().await.unwrap() })
+    }).collect();
+
+    for task in tasks {
+        assert_eq!(task.await.unwrap(), "access-1");
+    }
+
+    // Callers that saw the same token rejected don't refresh it twice either.
+    let tasks: Vec<_> = (0..5).map(|_| {
+        let tokens = tokens.clone();
+
+        tokio::spawn(async move { tokens.refresh(Some("access-1")).await.unwrap() })
+    }).collect();
+
+    for task in tasks {
+        assert_eq!(task.await.unwrap(), "access-2");
+    }
+
+    assert_eq!(mock.requests_to("/api/token").len(), 2);
+
+    mock.stop().await;
+}
+
+#[tokio::test]
+async fn stores_granted_scope() {
+    let mock = MockSpotify::start().await;
+    let (_dir, config) = config_for(&mock);
+
+    let mut spotify = Spotify::new(Arc::new(reqwest::Client::new()), config.clone());
+    spotify.authenticate().await.unwrap();
+
+    assert_eq!(stored_credentials(&config).await.scope, "user-read-currently-playing");
+
+    mock.set_granted_scope("user-read-currently-playing user-modify-playback-state");
+    spotify.init_credentials(&"auth-code".to_string(), None).await.unwrap();
+
+    assert_eq!(stored_credentials(&config).await.scope, "user-read-currently-playing user-modify-playback-state");
+
+    mock.stop().await;
+}
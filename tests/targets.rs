@@ -0,0 +1,100 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+use rosc::OscType;
+use spotify_osc::entities::config::{ConfigFile, ConfigFileOscTarget, OscOutput};
+use spotify_osc::managers::targets::OscTargets;
+use tokio::net::UdpSocket;
+use common::{message, receive_message, udp_receiver};
+
+#[test]
+fn client_address_is_the_default_target() {
+    let cfg = ConfigFile::default();
+
+    let targets = OscTargets::new(&cfg.general.osc);
+
+    assert_eq!(targets.targets().len(), 1);
+    assert_eq!(targets.targets()[0].address(), "127.0.0.1:9000");
+    assert!(targets.targets()[0].wants(OscOutput::Chatbox));
+}
+
+#[test]
+fn longest_prefix_is_remapped() {
+    let target = ConfigFileOscTarget {
+        address: "127.0.0.1:9000".to_string(),
+        outputs: OscOutput::all(),
+        remap: BTreeMap::from([
+            ("/avatar/".to_string(), "/a/".to_string()),
+            ("/avatar/parameters/".to_string(), "/spotify/".to_string())
+        ])
+    };
+
+    let mut cfg = ConfigFile::default();
+    cfg.general.osc.targets = vec![target];
+
+    let targets = OscTargets::new(&cfg.general.osc);
+    let target = &targets.targets()[0];
+
+    assert_eq!(target.remap("/avatar/parameters/spotify_seek"), "/spotify/spotify_seek");
+    assert_eq!(target.remap("/avatar/change"), "/a/change");
+    assert_eq!(target.remap("/chatbox/input"), "/chatbox/input");
+}
+
+#[tokio::test]
+async fn sends_each_target_its_outputs() {
+    let (vrchat, vrchat_address) = udp_receiver().await;
+    let (overlay, overlay_address) = udp_receiver().await;
+
+    let mut cfg = ConfigFile::default();
+    cfg.general.osc.targets = vec![
+        ConfigFileOscTarget {
+            address: vrchat_address,
+            outputs: OscOutput::all(),
+            remap: BTreeMap::new()
+        },
+        ConfigFileOscTarget {
+            address: overlay_address,
+            outputs: vec![OscOutput::Chatbox],
+            remap: BTreeMap::from([("/chatbox/input".to_string(), "/overlay/text".to_string())])
+        }
+    ];
+
+    let targets = OscTargets::new(&cfg.general.osc);
+    let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    targets.send(&sock, OscOutput::Playing, "/avatar/parameters/spotify_playing", vec![OscType::Bool(true)], Duration::ZERO).await;
+    targets.send(&sock, OscOutput::Chatbox, "/chatbox/input", vec![OscType::String("Song".to_string())], Duration::ZERO).await;
+
+    assert_eq!(receive_message(&vrchat).await, Some(message("/avatar/parameters/spotify_playing", vec![OscType::Bool(true)])));
+    assert_eq!(receive_message(&vrchat).await, Some(message("/chatbox/input", vec![OscType::String("Song".to_string())])));
+
+    assert_eq!(receive_message(&overlay).await, Some(message("/overlay/text", vec![OscType::String("Song".to_string())])));
+    assert_eq!(receive_message(&overlay).await, None);
+}
+
+#[tokio::test]
+async fn unreachable_targets_do_not_stop_the_others() {
+    let (receiver, address) = udp_receiver().await;
+
+    let mut cfg = ConfigFile::default();
+    cfg.general.osc.targets = vec![
+        ConfigFileOscTarget {
+            address: "not an address".to_string(),
+            outputs: OscOutput::all(),
+            remap: BTreeMap::new()
+        },
+        ConfigFileOscTarget {
+            address,
+            outputs: OscOutput::all(),
+            remap: BTreeMap::new()
+        }
+    ];
+
+    let targets = OscTargets::new(&cfg.general.osc);
+    let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    targets.send(&sock, OscOutput::Seek, "/avatar/parameters/spotify_seek", vec![OscType::Float(0.5)], Duration::ZERO).await;
+
+    assert_eq!(receive_message(&receiver).await, Some(message("/avatar/parameters/spotify_seek", vec![OscType::Float(0.5)])));
+}
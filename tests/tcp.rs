@@ -0,0 +1,147 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use rosc::OscType;
+use spotify_osc::entities::config::{ConfigFile, ConfigFileOscTarget, OscOutput};
+use spotify_osc::managers::forwarder::OscForwarder;
+use spotify_osc::managers::targets::OscTargets;
+use spotify_osc::managers::transport::{parse_address, spawn_udp_receiver, OscSend, OscSender, OscTcpListener, OscTransport};
+use spotify_osc::utils::osc::encode_packet;
+use spotify_osc::utils::slip::{slip_encode, SlipDecoder};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use common::{message, receive_message, receive_queued, tcp_receiver, udp_receiver};
+
+#[test]
+fn slip_frames_round_trip_in_any_chunks() {
+    let packet = vec![1, 0xC0, 2, 0xDB, 3];
+    let frame = slip_encode(&packet);
+
+    assert_eq!(frame, vec![0xC0, 1, 0xDB, 0xDC, 2, 0xDB, 0xDD, 3, 0xC0]);
+
+    let mut decoder = SlipDecoder::new(64);
+    let stream: Vec<u8> = [frame.clone(), frame].concat();
+
+    let mut packets = vec![];
+    for byte in stream.chunks(2) {
+        packets.extend(decoder.push(byte));
+    }
+
+    assert_eq!(packets, vec![packet.clone(), packet]);
+
+    // Oversized frames are dropped without losing the next one.
+    let mut decoder = SlipDecoder::new(4);
+    assert_eq!(decoder.push(&[0xC0, 1, 2, 3, 4, 5, 0xC0, 6, 0xC0]), vec![vec![6]]);
+}
+
+#[test]
+fn parses_transport_schemes() {
+    assert_eq!(parse_address("127.0.0.1:9000"), (OscTransport::Udp, "127.0.0.1:9000"));
+    assert_eq!(parse_address("udp://127.0.0.1:9000"), (OscTransport::Udp, "127.0.0.1:9000"));
+    assert_eq!(parse_address("tcp://127.0.0.1:9000"), (OscTransport::Tcp, "127.0.0.1:9000"));
+}
+
+#[tokio::test]
+async fn listener_decodes_encoded_packets() {
+    let (mut packets, address) = tcp_receiver().await;
+
+    let buf = encode_packet("/avatar/parameters/spotify_play".to_string(), vec![OscType::Bool(true)]).unwrap();
+    let frame = slip_encode(&buf);
+
+    // Split mid frame, as a stream may deliver it.
+    let mut stream = TcpStream::connect(&address).await.unwrap();
+    stream.write_all(&frame[..5]).await.unwrap();
+    stream.flush().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    stream.write_all(&frame[5..]).await.unwrap();
+
+    assert_eq!(receive_queued(&mut packets).await, Some(message("/avatar/parameters/spotify_play", vec![OscType::Bool(true)])));
+}
+
+#[tokio::test]
+async fn targets_send_over_tcp_and_udp() {
+    let (mut packets, tcp_address) = tcp_receiver().await;
+
+    let (udp, udp_address) = udp_receiver().await;
+
+    let mut cfg = ConfigFile::default();
+    cfg.general.osc.targets = vec![
+        ConfigFileOscTarget {
+            address: format!("tcp://{}", tcp_address),
+            outputs: OscOutput::all(),
+            remap: BTreeMap::new()
+        },
+        ConfigFileOscTarget {
+            address: udp_address,
+            outputs: OscOutput::all(),
+            remap: BTreeMap::new()
+        }
+    ];
+
+    let sender = OscSender::new(Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()));
+    let targets = OscTargets::new(&cfg.general.osc);
+
+    // The second message reuses the connection.
+    targets.send(&sender, OscOutput::Playing, "/avatar/parameters/spotify_playing", vec![OscType::Bool(true)], Duration::ZERO).await;
+    targets.send(&sender, OscOutput::Seek, "/avatar/parameters/spotify_seek", vec![OscType::Float(0.5)], Duration::ZERO).await;
+
+    assert_eq!(receive_queued(&mut packets).await, Some(message("/avatar/parameters/spotify_playing", vec![OscType::Bool(true)])));
+    assert_eq!(receive_queued(&mut packets).await, Some(message("/avatar/parameters/spotify_seek", vec![OscType::Float(0.5)])));
+
+    assert_eq!(receive_message(&udp).await, Some(message("/avatar/parameters/spotify_playing", vec![OscType::Bool(true)])));
+}
+
+#[tokio::test]
+async fn udp_and_tcp_share_the_dispatch() {
+    let (tx, mut packets) = mpsc::channel(16);
+
+    let udp = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let udp_address = udp.local_addr().unwrap().to_string();
+    spawn_udp_receiver(udp, tx.clone());
+
+    let listener = OscTcpListener::bind("tcp://127.0.0.1:0").await.unwrap();
+    let tcp_address = listener.local_addr().unwrap().to_string();
+    listener.spawn(tx);
+
+    let mut cfg = ConfigFile::default();
+    cfg.general.osc.forward.addresses = vec![udp_address, format!("tcp://{}", tcp_address)];
+
+    let sender = OscSender::new(Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()));
+    let buf = encode_packet("/avatar/parameters/VelocityX".to_string(), vec![OscType::Float(1.0)]).unwrap();
+
+    OscForwarder::new(&cfg.general.osc, &[]).forward(&sender, &buf, false).await;
+
+    let expected = Some(message("/avatar/parameters/VelocityX", vec![OscType::Float(1.0)]));
+    assert_eq!(receive_queued(&mut packets).await, expected);
+    assert_eq!(receive_queued(&mut packets).await, expected);
+}
+
+#[tokio::test]
+async fn a_peer_that_stops_reading_does_not_block_sending() {
+    // Accepts the connection but never reads from it.
+    let stalled = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let stalled_address = format!("tcp://{}", stalled.local_addr().unwrap());
+    let accept = tokio::spawn(async move { stalled.accept().await.unwrap() });
+
+    let (mut packets, address) = tcp_receiver().await;
+
+    let sender = OscSender::new(Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()));
+    let buf = encode_packet("/avatar/parameters/spotify_seek".to_string(), vec![OscType::Blob(vec![0; 1024])]).unwrap();
+
+    // Far more than the socket buffers hold, what doesn't fit is dropped quietly.
+    tokio::time::timeout(Duration::from_secs(1), async {
+        for _ in 0..10_000 {
+            sender.send_packet(&buf, &stalled_address).await.unwrap();
+        }
+    }).await.expect("sending blocked on the stalled peer");
+
+    let buf = encode_packet("/avatar/parameters/spotify_playing".to_string(), vec![OscType::Bool(true)]).unwrap();
+    sender.send_packet(&buf, &format!("tcp://{}", address)).await.unwrap();
+
+    assert_eq!(receive_queued(&mut packets).await, Some(message("/avatar/parameters/spotify_playing", vec![OscType::Bool(true)])));
+
+    drop(accept);
+}
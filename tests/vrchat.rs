@@ -0,0 +1,259 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use actix_web::dev::ServerHandle;
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web};
+use mdns_sd::{IfKind, ServiceDaemon, ServiceInfo};
+use rosc::OscType;
+use serde_json::{json, Value};
+use spotify_osc::entities::config::{ConfigFile, ConfigFileAvatar, OscOutput};
+use spotify_osc::entities::osc::OscAction;
+use spotify_osc::managers::avatar::{AvatarChanges, CurrentAvatar};
+use spotify_osc::managers::oscquery::OSCJSON_SERVICE;
+use spotify_osc::managers::router::{OscRouter, SharedRouter};
+use spotify_osc::managers::targets::OscTargets;
+use spotify_osc::managers::vrchat::VrchatDiscovery;
+use tokio::net::UdpSocket;
+use common::{config_with, receive_message};
+
+struct FakeVrchatState {
+    osc_port: u16,
+    avatar: Option<Value>,
+    /// Held back before answering the next `/avatar` request.
+    avatar_delay: Option<Duration>
+}
+
+/// Answers like VRChat's OSCQuery server, with an avatar that has the given parameters.
+struct FakeVrchat {
+    state: Arc<Mutex<FakeVrchatState>>,
+    handle: ServerHandle,
+    port: u16
+}
+
+fn avatar_json(parameters: &[&str]) -> Value {
+    let contents: serde_json::Map<String, Value> = parameters.iter()
+        .map(|name| (name.to_string(), json!({
+            "FULL_PATH": format!("/avatar/parameters/{}", name),
+            "TYPE": "T",
+            "ACCESS": 3,
+            "VALUE": [false]
+        })))
+        .collect();
+
+    json!({
+        "FULL_PATH": "/avatar",
+        "ACCESS": 0,
+        "CONTENTS": {
+            "change": { "FULL_PATH": "/avatar/change", "TYPE": "s", "ACCESS": 3, "VALUE": ["avtr_1"] },
+            "parameters": { "FULL_PATH": "/avatar/parameters", "ACCESS": 0, "CONTENTS": contents }
+        }
+    })
+}
+
+async fn handle(req: HttpRequest, state: web::Data<Arc<Mutex<FakeVrchatState>>>) -> HttpResponse {
+    let (avatar, delay) = {
+        let mut state = state.lock().unwrap();
+
+        if req.query_string() == "HOST_INFO" {
+            return HttpResponse::Ok().json(json!({
+                "NAME": "VRChat-Client-TEST",
+                "OSC_IP": "127.0.0.1",
+                "OSC_PORT": state.osc_port,
+                "OSC_TRANSPORT": "UDP"
+            }))
+        }
+
+        (state.avatar.clone(), state.avatar_delay.take())
+    };
+
+    if let Some(delay) = delay {
+        tokio::time::sleep(delay).await;
+    }
+
+    match (req.path(), avatar) {
+        ("/avatar", Some(avatar)) => HttpResponse::Ok().json(avatar),
+        _ => HttpResponse::NotFound().finish()
+    }
+}
+
+impl FakeVrchat {
+    fn start(osc_port: u16, avatar: Option<Value>) -> Self {
+        let state = Arc::new(Mutex::new(FakeVrchatState { osc_port, avatar, avatar_delay: None }));
+
+        let server = HttpServer::new({
+            let state = state.clone();
+
+            move || {
+                App::new()
+                    .app_data(web::Data::new(state.clone()))
+                    .default_service(web::get().to(handle))
+            }
+        })
+            .workers(1)
+            .disable_signals()
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+
+        let port = server.addrs()[0].port();
+        let server = server.run();
+        let handle = server.handle();
+
+        tokio::spawn(server);
+
+        Self { state, handle, port }
+    }
+
+    fn base_url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+
+    fn set_avatar(&self, avatar: Option<Value>) {
+        self.state.lock().unwrap().avatar = avatar;
+    }
+
+    fn delay_avatar(&self, delay: Duration) {
+        self.state.lock().unwrap().avatar_delay = Some(delay);
+    }
+
+    async fn stop(self) {
+        self.handle.stop(false).await;
+    }
+}
+
+#[tokio::test]
+async fn connect_reads_host_info_and_avatar() {
+    let fake = FakeVrchat::start(9555, Some(avatar_json(&["spotify_playing"])));
+
+    let discovery = VrchatDiscovery::new();
+    let vrchat = discovery.connect(&reqwest::Client::new(), &fake.base_url()).await.unwrap();
+
+    assert_eq!(vrchat.osc_address, "127.0.0.1:9555");
+    assert!(vrchat.has_parameter("/avatar/parameters/spotify_playing"));
+    assert!(!vrchat.has_parameter("/avatar/parameters/spotify_seek"));
+    assert!(vrchat.has_parameter("/chatbox/input"));
+    assert!(discovery.get().is_some());
+
+    discovery.forget();
+    assert!(discovery.get().is_none());
+
+    fake.stop().await;
+}
+
+#[tokio::test]
+async fn unknown_avatar_sends_everything() {
+    let fake = FakeVrchat::start(9556, None);
+
+    let discovery = VrchatDiscovery::new();
+    let vrchat = discovery.connect(&reqwest::Client::new(), &fake.base_url()).await.unwrap();
+
+    assert!(vrchat.avatar.is_none());
+    assert!(vrchat.has_parameter("/avatar/parameters/spotify_seek"));
+
+    fake.stop().await;
+}
+
+#[tokio::test]
+async fn only_sends_parameters_the_avatar_has() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let fake = FakeVrchat::start(receiver.local_addr().unwrap().port(), Some(avatar_json(&["spotify_playing"])));
+
+    let http = reqwest::Client::new();
+    let discovery = VrchatDiscovery::new();
+    discovery.connect(&http, &fake.base_url()).await.unwrap();
+
+    let cfg = ConfigFile::default();
+    let parameters = &cfg.parameters;
+    let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    let targets = OscTargets::new(&cfg.general.osc).with_vrchat(discovery.get());
+    assert_eq!(targets.targets()[0].address(), receiver.local_addr().unwrap().to_string());
+
+    targets.send(&sock, OscOutput::Seek, &parameters.spotify_seek, vec![OscType::Float(0.5)], Duration::ZERO).await;
+    targets.send(&sock, OscOutput::Playing, &parameters.spotify_playing, vec![OscType::Bool(true)], Duration::ZERO).await;
+
+    assert_eq!(receive_message(&receiver).await.map(|message| message.addr).as_deref(), Some("/avatar/parameters/spotify_playing"));
+    assert_eq!(receive_message(&receiver).await, None);
+
+    // Switching to an avatar with both parameters sends both again.
+    fake.set_avatar(Some(avatar_json(&["spotify_playing", "spotify_seek"])));
+    assert!(discovery.refresh_avatar(&http).await.unwrap());
+    assert!(!discovery.refresh_avatar(&http).await.unwrap());
+
+    let targets = OscTargets::new(&cfg.general.osc).with_vrchat(discovery.get());
+    targets.send(&sock, OscOutput::Seek, &parameters.spotify_seek, vec![OscType::Float(0.5)], Duration::ZERO).await;
+
+    assert_eq!(receive_message(&receiver).await.map(|message| message.addr).as_deref(), Some("/avatar/parameters/spotify_seek"));
+
+    fake.stop().await;
+}
+
+#[tokio::test]
+async fn the_latest_avatar_change_wins() {
+    let fake = FakeVrchat::start(9558, Some(avatar_json(&["spotify_playing"])));
+
+    let (_dir, config) = config_with(|cfg| {
+        cfg.avatars.push(ConfigFileAvatar {
+            id: "avtr_music".to_string(),
+            spotify_play: Some("/avatar/parameters/music_play".to_string()),
+            ..ConfigFileAvatar::default()
+        });
+    });
+
+    let http = Arc::new(reqwest::Client::new());
+    let discovery = Arc::new(VrchatDiscovery::new());
+    discovery.connect(&http, &fake.base_url()).await.unwrap();
+
+    let router = Arc::new(SharedRouter::new(OscRouter::new(&config.lock().await.cfg)));
+    let avatar = Arc::new(CurrentAvatar::new());
+    let changes = AvatarChanges::spawn(config, router.clone(), avatar.clone(), discovery, http);
+
+    // Reading the first avatar from VRChat is slow, the second change comes in meanwhile.
+    fake.delay_avatar(Duration::from_millis(300));
+    changes.change("avtr_music");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    changes.change("avtr_other");
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+
+    assert_eq!(avatar.get().as_deref().map(String::as_str), Some("avtr_other"));
+
+    let router = router.load();
+    assert_eq!(router.route("/avatar/parameters/spotify_play"), vec![OscAction::Play]);
+    assert!(router.route("/avatar/parameters/music_play").is_empty());
+
+    fake.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn discovers_vrchat_over_mdns() {
+    let fake = FakeVrchat::start(9557, Some(avatar_json(&["spotify_playing"])));
+
+    let advertiser = ServiceDaemon::new().unwrap();
+    advertiser.enable_interface(IfKind::LoopbackV4).unwrap();
+    advertiser.register(ServiceInfo::new(OSCJSON_SERVICE, "VRChat-Client-TEST", "vrchat-test.local.", "127.0.0.1", fake.port, &[("txtvers", "1")][..]).unwrap()).unwrap();
+
+    let (_dir, config) = config_with(|_| {});
+
+    let discovery = Arc::new(VrchatDiscovery::new());
+    let task = discovery.spawn(Arc::new(reqwest::Client::new()), config, Arc::new(CurrentAvatar::new()));
+
+    let mut found = None;
+
+    for _ in 0..100 {
+        if let Some(vrchat) = discovery.get() {
+            found = Some(vrchat);
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let vrchat = found.expect("VRChat wasn't discovered");
+    assert_eq!(vrchat.osc_address, "127.0.0.1:9557");
+    assert!(!vrchat.has_parameter("/avatar/parameters/spotify_seek"));
+
+    task.abort();
+    advertiser.shutdown().unwrap();
+    fake.stop().await;
+}